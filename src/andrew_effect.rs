@@ -1,15 +1,50 @@
-use std::{collections::{VecDeque}, iter::Filter, ops::Mul, sync::{Arc, Weak}, f32::consts};
-use crate::{AndrewParams, AndrewVst, audio_clip::AudioClip, modulator::Lfo};
-use crate::biquad::{BiQuadraticFilter, FilterKind::{self, *}};
-
-pub trait AndrewEffect {
+use std::{collections::{VecDeque}, iter::Filter, ops::Mul, sync::{Arc, Weak}, f32::consts, thread};
+use std::sync::{atomic::Ordering, mpsc::{channel, Receiver, Sender}};
+use crate::{AndrewParams, AndrewVst, audio_clip::AudioClip, modulator::Lfo, noise::Noise};
+use crate::biquad::{BiQuadraticFilter, FilterKind::{self, *}, Svf};
+use crate::dynamics::Envelope;
+use vst::api::TimeInfo;
+use vst::util::AtomicFloat;
+
+// room effects keep for a block of sidechain, so copying one in doesn't
+// allocate. a host sending bigger blocks grows them the once
+pub const SIDECHAIN_RESERVE: usize = 8192;
+
+// Send, since chains are built off the audio thread and handed to it
+pub trait AndrewEffect: Send {
 	fn process( &mut self, chan_id: usize, in_buf: &[f32], out_buf: &mut [f32] ) {
 		(0..in_buf.len()).for_each(|i| out_buf[i] = in_buf[i] );
 	}
 
+	// runs a stereo pair through the effect. by default each side is
+	// handed to process on its own, effects that link or mix the two
+	// channels override this instead
+	fn process_stereo( &mut self, in_bufs: [&[f32]; 2], out_bufs: [&mut [f32]; 2] ) {
+		let [out_l, out_r] = out_bufs;
+		self.process(0, in_bufs[0], out_l);
+		self.process(1, in_bufs[1], out_r);
+	}
+
+	// the sidechain input pair for the coming block, handed over before
+	// process_stereo every block. empty when the host gives us no extra
+	// inputs, so nothing is left over from an earlier block
+	fn sidechain( &mut self, _sc_bufs: [&[f32]; 2] ) {}
+
+	// where the host's transport is for the coming block, handed over
+	// before process_stereo. only called when the host tells us
+	fn time_info( &mut self, _info: &TimeInfo ) {}
+
+	// a midi message from the host, handed over before the block it
+	// lands in. only called for effects in the chain when midi comes in
+	fn midi( &mut self, _data: [u8; 3] ) {}
+
 	fn update_params( &mut self ) {}
 
 	fn get_latency( &self ) -> usize {1}
+
+	// samples the effect keeps sounding for once the input stops,
+	// usize::MAX if it never does
+	fn get_tail_size( &self ) -> usize {0}
 }
 
 #[derive(Default)]
@@ -38,6 +73,8 @@ impl AndrewEffect for DistEffect {
 			self.gain = params.delay_feedback.get() * 2.0;
 		}
 	}
+
+	fn get_latency(&self) -> usize {0}
 }
 
 impl DistEffect {
@@ -52,6 +89,106 @@ impl DistEffect {
 
 
 
+// bit depth and sample rate reduction. both are fractional and smoothed
+// per sample, so they can be swept without stepping
+pub struct CrushEffect {
+	bits: [Smoothed; 2],
+	rate: [Smoothed; 2],
+	phase: [f32; 2],
+	held: [f32; 2],
+	aa_filter: [BiQuadraticFilter; 2],
+	// rate the anti alias filters were last tuned to
+	aa_rate: [f32; 2],
+
+	dither: bool,
+	anti_alias: bool,
+	jitter: f32,
+	sample_rate: f32,
+	noise: Noise,
+
+	params: Weak<AndrewParams>,
+}
+
+impl AndrewEffect for CrushEffect {
+	fn process(&mut self, chan_id: usize, in_buf: &[f32], out_buf: &mut [f32]) {
+		let fs = self.sample_rate;
+
+		for (samp, out) in in_buf.iter().zip(out_buf.iter_mut()) {
+			let bits = self.bits[chan_id].tick();
+			let rate = self.rate[chan_id].tick().min(fs);
+
+			let x = if self.anti_alias {
+				// retuning is costly, so only once the rate has moved a bit
+				if (rate - self.aa_rate[chan_id]).abs() > self.aa_rate[chan_id] * 0.01 {
+					self.aa_rate[chan_id] = rate;
+					self.aa_filter[chan_id].update_center_freq((0.45 * rate).min(0.49 * fs));
+				}
+				self.aa_filter[chan_id].filter(*samp)
+			} else {
+				*samp
+			};
+
+			self.phase[chan_id] += rate / fs * (1.0 + self.jitter * 0.5 * self.noise.white());
+			if self.phase[chan_id] >= 1.0 {
+				self.phase[chan_id] %= 1.0;
+
+				let levels = 2f32.powf(bits - 1.0);
+				let dither = if self.dither { self.noise.triangular() } else { 0.0 };
+				self.held[chan_id] = (x * levels + dither).round() / levels;
+			}
+			*out = self.held[chan_id];
+		}
+	}
+
+	fn update_params(&mut self) {
+		if let Some(params) = self.params.upgrade() {
+			let sample_rate = params.sample_rate.get();
+			if sample_rate != self.sample_rate {
+				self.sample_rate = sample_rate;
+				for (filter, rate) in self.aa_filter.iter_mut().zip(self.aa_rate.iter()) {
+					filter.recfg(LOWPASS, (0.45 * rate).min(0.49 * sample_rate), sample_rate, consts::FRAC_1_SQRT_2, 0.0);
+				}
+			}
+
+			for chan in 0..2 {
+				self.bits[chan].set_time(0.02, sample_rate);
+				self.rate[chan].set_time(0.02, sample_rate);
+				self.bits[chan].set(params.crush_bits.get());
+				self.rate[chan].set(params.crush_rate.get());
+			}
+			self.dither = params.crush_dither.load(Ordering::Relaxed);
+			self.anti_alias = params.crush_aa.load(Ordering::Relaxed);
+			self.jitter = params.crush_jitter.get();
+		}
+	}
+
+	fn get_latency(&self) -> usize {0}
+}
+
+impl CrushEffect {
+	pub fn new( params: Weak<AndrewParams> ) -> Self {
+		let sample_rate = 44100.0;
+		let filter = BiQuadraticFilter::new(LOWPASS, 0.45 * sample_rate, sample_rate, consts::FRAC_1_SQRT_2, 0.0);
+		CrushEffect {
+			bits: [Smoothed::new(24.0); 2],
+			rate: [Smoothed::new(sample_rate); 2],
+			phase: [0.0; 2],
+			held: [0.0; 2],
+			aa_filter: [filter.clone(), filter],
+			aa_rate: [sample_rate; 2],
+			dither: false,
+			anti_alias: true,
+			jitter: 0.0,
+			sample_rate,
+			noise: Noise::default(),
+			params,
+		}
+	}
+}
+
+
+
+
 pub struct GrainShiftEffect {
 	grains: [Vec<AudioClip>; 2],
 	pitch: f32,
@@ -78,6 +215,8 @@ impl AndrewEffect for VibEffect {
 		out_buf.iter_mut().for_each(|out| *out = self.bufs[chan_id].read_playhead() );
 		self.bufs[chan_id].shrink();
 	}
+
+	fn get_latency(&self) -> usize {0}
 }
 
 
@@ -91,17 +230,59 @@ impl VibEffect {
 	}
 }
 
+// how much input level the wah sweeps over, in db below full scale plus
+// the sensitivity
+const WAH_RANGE: f32 = 48.0;
+
+// fixed cutoff, or in wah mode an envelope follower on the input sweeping
+// a resonant bandpass or lowpass between min and max. the sweep runs on a
+// state variable filter, retuned every sample
 pub struct FilterEffect {
 	state: [BiQuadraticFilter; 2],
+	wah_filters: [Svf; 2],
+	wah_envs: [Envelope; 2],
+
+	wah: bool,
+	wah_lowpass: bool,
+	wah_min: f32,
+	wah_max: f32,
+	wah_sensitivity: f32,
+	wah_down: bool,
+	wah_resonance: f32,
+	sample_rate: f32,
+
 	params: Weak<AndrewParams>,
 }
 
 
 impl AndrewEffect for FilterEffect {
 	fn process(&mut self, chan_id: usize, in_buf: &[f32], out_buf: &mut [f32] ) {
-		in_buf.iter()
-		.zip(out_buf.iter_mut())
-		.for_each(|(samp, out)| *out = self.state[chan_id].filter(*samp));
+		if !self.wah {
+			in_buf.iter()
+			.zip(out_buf.iter_mut())
+			.for_each(|(samp, out)| *out = self.state[chan_id].filter(*samp));
+			return
+		}
+
+		let span = (self.wah_max / self.wah_min).max(1.0);
+		// both are brought down to unity at their peak. the lowpass only
+		// peaks once the resonance is past a flat response
+		let q = self.wah_resonance;
+		let norm = if self.wah_lowpass {
+			if q > consts::FRAC_1_SQRT_2 { (1.0 - 0.25 / (q * q)).sqrt() / q } else { 1.0 }
+		} else {
+			1.0 / q
+		};
+		for (samp, out) in in_buf.iter().zip(out_buf.iter_mut()) {
+			let level = self.wah_envs[chan_id].tick(samp.abs());
+			let mut position = ((amp_to_db(level) + self.wah_sensitivity + WAH_RANGE) / WAH_RANGE).clamp(0.0, 1.0);
+			if self.wah_down { position = 1.0 - position }
+
+			let filter = &mut self.wah_filters[chan_id];
+			filter.set(self.wah_min * span.powf(position), self.sample_rate, self.wah_resonance);
+			let (low, band, _) = filter.filter(*samp);
+			*out = if self.wah_lowpass { low } else { band } * norm;
+		}
 	}
 
 	fn update_params(&mut self) {
@@ -109,8 +290,29 @@ impl AndrewEffect for FilterEffect {
 			for biquad in self.state.iter_mut() {
 				biquad.update_center_freq(params.cutoff.get() * 10_000.0);
 			}
+
+			let wah = params.wah.load(Ordering::Relaxed);
+			if wah && !self.wah {
+				self.wah_filters = [Svf::default(); 2];
+			}
+			self.wah = wah;
+			self.wah_lowpass = params.wah_lowpass.load(Ordering::Relaxed);
+			self.wah_min = params.wah_min.get();
+			self.wah_max = params.wah_max.get();
+			self.wah_sensitivity = params.wah_sensitivity.get();
+			self.wah_down = params.wah_down.load(Ordering::Relaxed);
+			self.wah_resonance = params.wah_resonance.get();
+			self.sample_rate = params.sample_rate.get();
+
+			let attack = params.wah_attack.get() / 1000.0;
+			let release = params.wah_release.get() / 1000.0;
+			for env in self.wah_envs.iter_mut() {
+				env.set_times(attack, release, self.sample_rate);
+			}
 		}
 	}
+
+	fn get_latency(&self) -> usize {0}
 }
 
 impl FilterEffect {
@@ -118,6 +320,16 @@ impl FilterEffect {
 		let x = BiQuadraticFilter::new(CUSTOM, 1000.0, 44100.0, 1.0, 0.0);
 		FilterEffect {
 			state: [x.clone(), x.clone()],
+			wah_filters: [Svf::default(); 2],
+			wah_envs: [Envelope::default(); 2],
+			wah: false,
+			wah_lowpass: false,
+			wah_min: 300.0,
+			wah_max: 2500.0,
+			wah_sensitivity: 18.0,
+			wah_down: false,
+			wah_resonance: 4.0,
+			sample_rate: 44100.0,
 			params,
 		}
 	}
@@ -126,91 +338,316 @@ impl FilterEffect {
 
 
 
+// samples over which a freshly built kernel is crossfaded in
+const KERNEL_FADE_LEN: usize = 512;
+
+// how many taps the custom kernel takes from the params
+pub const KERNEL_CUSTOM_TAPS: usize = 8;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum KernelShape {
+	Parabolic,
+	Gaussian,
+	Hann,
+	Blackman,
+	Sinc,
+	Exponential,
+	Custom,
+}
+
+impl KernelShape {
+	pub const COUNT: u8 = 7;
+
+	pub fn from_index( i: u8 ) -> Self {
+		match i {
+			0 => KernelShape::Parabolic,
+			1 => KernelShape::Gaussian,
+			2 => KernelShape::Hann,
+			3 => KernelShape::Blackman,
+			4 => KernelShape::Sinc,
+			5 => KernelShape::Exponential,
+			_ => KernelShape::Custom,
+		}
+	}
+
+	pub fn name( &self ) -> &'static str {
+		match self {
+			KernelShape::Parabolic => "parabolic",
+			KernelShape::Gaussian => "gaussian",
+			KernelShape::Hann => "hann",
+			KernelShape::Blackman => "blackman",
+			KernelShape::Sinc => "sinc",
+			KernelShape::Exponential => "exp",
+			KernelShape::Custom => "custom",
+		}
+	}
+}
+
+struct KernelRequest {
+	shape: KernelShape,
+	spread: f32,
+	len: usize,
+	taps: Arc<Vec<f32>>,
+}
+
+// what the kernel thread is handed. kernels process is done with go back
+// to it too, so they are freed there rather than on the audio thread
+enum KernelJob {
+	Build(KernelRequest),
+	Free(Vec<f32>),
+}
+
+// builds a kernel of `len` taps whose taps sum to one, so the
+// dc gain stays put whatever the shape or spread.
+// the last tap lines up with the newest sample, and every shape is
+// centred on tap len / 2, so they all come out as late as each other
+pub fn build_kernel( shape: KernelShape, spread: f32, len: usize, taps: &[f32] ) -> Vec<f32> {
+	let spread = spread.max(1e-3);
+	let center = (len / 2) as f32;
+	let blackman = |x: f32| {
+		// x in -1..1
+		let p = consts::PI * (x + 1.0);
+		0.42 - 0.5 * p.cos() + 0.08 * (2.0 * p).cos()
+	};
+
+	let mut kernel: Vec<f32> = (0..len)
+		.map(|i| i as f32 - center)
+		.map(|x| match shape {
+			KernelShape::Parabolic => 1.0 - (x/spread).powi(2).min(1.0),
+			KernelShape::Gaussian => {
+				let sigma = spread * 0.5;
+				(-0.5 * (x/sigma).powi(2)).exp()
+			},
+			KernelShape::Hann => if x.abs() < spread {
+				0.5 + 0.5 * (consts::PI * x/spread).cos()
+			} else { 0.0 },
+			KernelShape::Blackman => if x.abs() < spread {
+				blackman(x/spread)
+			} else { 0.0 },
+			KernelShape::Sinc => {
+				// cutoff at nyquist/spread, windowed over the whole kernel
+				let fc = 0.5 / spread.max(1.0);
+				// the limit of the sinc at the centre is 2 fc
+				let sinc = if x == 0.0 { 2.0 * fc } else {
+					(consts::TAU * fc * x).sin() / (consts::PI * x)
+				};
+				sinc * blackman(x/(center + 1.0))
+			},
+			// decays back from the centre, nothing ahead of it
+			KernelShape::Exponential => if x > 0.0 { 0.0 } else {
+				(x/spread).exp()
+			},
+			KernelShape::Custom => {
+				// user taps are centred in the kernel, newest last
+				let offset = (len as isize - taps.len() as isize) / 2;
+				let i = (x + center) as isize - offset;
+				if i >= 0 && (i as usize) < taps.len() { taps[i as usize] } else { 0.0 }
+			},
+		})
+		.collect();
+
+	let dc = kernel.iter().sum::<f32>();
+	if dc.abs() < 1e-9 || !dc.is_finite() {
+		// nothing sensible to normalise, fall back to a pass through
+		kernel.iter_mut().for_each(|tap| *tap = 0.0);
+		kernel[len / 2] = 1.0;
+	} else {
+		kernel.iter_mut().for_each(|tap| *tap /= dc);
+	}
+	kernel
+}
+
 pub struct ConvEffect {
 	buf: [VecDeque<f32>; 2],
 	pattern: Vec<f32>,
+	// kernel being faded in and how far along each channel is
+	next_pattern: Option<Vec<f32>>,
+	fade: [usize; 2],
+	// channels the host has had us process, only those hold up the swap
+	active: [bool; 2],
 	spread: f32,
+	shape: KernelShape,
+	taps: Arc<Vec<f32>>,
 	buf_len: usize,
 
+	kernel_tx: Sender<KernelJob>,
+	kernel_rx: Receiver<Vec<f32>>,
+
 	params: Weak<AndrewParams>
 }
 
 impl AndrewEffect for ConvEffect {
 	fn process(&mut self, chan_id: usize, in_buf: &[f32], out_buf: &mut [f32]) {
+		if self.next_pattern.is_none() {
+			// only the newest is faded in, any older ones go straight back
+			for kernel in self.kernel_rx.try_iter() {
+				if let Some(stale) = self.next_pattern.replace(kernel) {
+					let _ = self.kernel_tx.send(KernelJob::Free(stale));
+				}
+				self.fade = [0; 2];
+			}
+		}
 
+		self.active[chan_id] = true;
 		self.buf[chan_id].extend(in_buf);
-		out_buf.iter_mut()
-		.for_each(|out| {
-			self.buf[chan_id].pop_front();
-			*out = self.pattern.iter()
-				.zip(self.buf[chan_id].iter())
-				.map(|elm| elm.0 * elm.1 * (1.5/self.spread))
+		let buf = &mut self.buf[chan_id];
+		let fade = &mut self.fade[chan_id];
+		for out in out_buf.iter_mut() {
+			buf.pop_front();
+			let current = self.pattern.iter()
+				.zip(buf.iter())
+				.map(|(tap, samp)| tap * samp)
 				.sum::<f32>();
-		});
+
+			*out = match &self.next_pattern {
+				Some(next) => {
+					let incoming = next.iter()
+						.zip(buf.iter())
+						.map(|(tap, samp)| tap * samp)
+						.sum::<f32>();
+					let t = *fade as f32 / KERNEL_FADE_LEN as f32;
+					*fade = (*fade + 1).min(KERNEL_FADE_LEN);
+					current * (1.0 - t) + incoming * t
+				},
+				None => current,
+			};
+		}
+
+		let faded = self.fade.iter()
+			.zip(self.active.iter())
+			.all(|(fade, active)| !active || *fade >= KERNEL_FADE_LEN);
+		if faded {
+			if let Some(next) = self.next_pattern.take() {
+				let old = std::mem::replace(&mut self.pattern, next);
+				let _ = self.kernel_tx.send(KernelJob::Free(old));
+			}
+		}
 	}
 
 	fn update_params(&mut self) {
 		if let Some(params) = self.params.upgrade() {
 			let spread = params.delay_time.get() * 100.0;
-			let len = self.buf_len.clone();
-			if spread != self.spread {
-
+			let shape = KernelShape::from_index(params.kernel_shape.load(Ordering::Relaxed));
+			if spread != self.spread || shape != self.shape {
 				self.spread = spread;
-				self.pattern = (0..len)
-					.map(|elm| elm - (len/2))
-					.map(|elm| elm as f32)
-					.map(|elm| 1.0 - (elm/spread).powi(2).min(1.0))
-					.collect();
+				self.shape = shape;
+				self.request_kernel();
+			}
+
+			let taps: Vec<f32> = params.kernel_taps.iter().map(AtomicFloat::get).collect();
+			if taps != *self.taps {
+				self.set_taps(taps);
 			}
 		}
 	}
 
+	// the age of the centre tap
+	fn get_latency(&self) -> usize {
+		self.buf_len - 1 - self.buf_len / 2
+	}
 }
 
 impl ConvEffect {
 	pub fn new(params: Weak<AndrewParams>) -> Self {
-		let len = 100;//pattern.len();
+		let len = 100;
 		let spread = 6.0;
+		let shape = KernelShape::Parabolic;
+
+		// kernels are built on their own thread and handed back to
+		// process, which crossfades them in
+		let (kernel_tx, job_rx) = channel::<KernelJob>();
+		let (result_tx, kernel_rx) = channel();
+		thread::spawn(move || {
+			for job in job_rx {
+				match job {
+					KernelJob::Build(req) => {
+						let kernel = build_kernel(req.shape, req.spread, req.len, &req.taps);
+						if result_tx.send(kernel).is_err() { break }
+					},
+					KernelJob::Free(kernel) => drop(kernel),
+				}
+			}
+		});
 
-		let pattern = (0..len)
-			.map(|elm| elm - (len/2))
-			.map(|elm| elm as f32)
-			.map(|elm| 1.0 - (elm/spread).powi(2).min(1.0))
-			.collect();
-		// let dc = pattern.iter().sum::<f32>();
-		// let pattern: Vec<f32> = pattern.iter().map(move |elm| *elm/dc).collect();
 		ConvEffect {
 			buf: [VecDeque::from(vec![0.0; len]), VecDeque::from(vec![0.0; len])],
 			buf_len: len,
-			pattern,
+			pattern: build_kernel(shape, spread, len, &[]),
+			next_pattern: None,
+			fade: [KERNEL_FADE_LEN; 2],
+			active: [false; 2],
 			params,
-			spread: 6.0,
+			spread,
+			shape,
+			taps: Arc::new(vec![]),
+			kernel_tx,
+			kernel_rx,
 		}
 	}
+
+	// taps used by KernelShape::Custom, oldest first. the list is
+	// normalised to unity dc and truncated to the kernel length
+	pub fn set_taps( &mut self, taps: Vec<f32> ) {
+		self.taps = Arc::new(taps);
+		if self.shape == KernelShape::Custom {
+			self.request_kernel();
+		}
+	}
+
+	fn request_kernel( &mut self ) {
+		let _ = self.kernel_tx.send(KernelJob::Build(KernelRequest {
+			shape: self.shape,
+			spread: self.spread,
+			len: self.buf_len,
+			taps: Arc::clone(&self.taps),
+		}));
+	}
 }
 
 
 
 
+// the integrator and differentiator are both scaled to unity gain here,
+// so that chaining one into the other is close to a pass through
+const CALCULUS_UNITY_FREQ: f32 = 1000.0;
+
 pub struct PrimeEffect {
 	prev_sample: [f32; 2],
+	// fs / (2 pi f_unity), turns the first difference into a derivative
+	scale: f32,
+	gain: f32,
+	params: Weak<AndrewParams>,
 }
 
 impl AndrewEffect for PrimeEffect {
 	fn process(&mut self, chan_id: usize, in_buf: &[f32], out_buf: &mut [f32]) {
+		let k = self.scale * self.gain;
 		in_buf.iter()
 		.zip(out_buf.iter_mut())
 		.for_each(|(sample, out)| {
-			*out = *sample - self.prev_sample[chan_id];
+			*out = (*sample - self.prev_sample[chan_id]) * k;
 			self.prev_sample[chan_id] = *sample
 		});
 	}
+
+	fn update_params(&mut self) {
+		if let Some(params) = self.params.upgrade() {
+			let sample_rate = params.sample_rate.get();
+			self.scale = sample_rate / (consts::TAU * CALCULUS_UNITY_FREQ);
+			self.gain = db_to_gain(params.calculus_gain.get());
+		}
+	}
+
+	fn get_latency(&self) -> usize {0}
 }
 
 impl PrimeEffect {
 	pub fn new(params: Weak<AndrewParams>) -> Self {
 		PrimeEffect {
 			prev_sample: [0.0; 2],
+			scale: 44100.0 / (consts::TAU * CALCULUS_UNITY_FREQ),
+			gain: 1.0,
+			params,
 		}
 	}
 }
@@ -218,109 +655,296 @@ impl PrimeEffect {
 
 
 
+// lowest the leak frequency param goes, in hz
+const CALCULUS_MIN_LEAK: f32 = 1.0;
+
+// leaky integrator followed by a moving average subtraction.
+// the average spans one period of the leak frequency, which
+// removes the dc the leak lets through
 pub struct IntEffect {
 	sum: [f32; 2],
-	avg_buf: [VecDeque<f32>; 2],
+	// the integrator's past output, newest at pos. long enough for the
+	// lowest leak frequency, so changing it only moves where the average
+	// reaches back to
+	history: [Vec<f32>; 2],
+	pos: [usize; 2],
+	// running total of the last buf_len of history, kept in f64 so it doesn't drift
+	avg_sum: [f64; 2],
 	buf_len: usize,
+	leak: f32,
+	scale: f32,
+	gain: f32,
+	params: Weak<AndrewParams>,
 }
 
 impl AndrewEffect for IntEffect {
 	fn process(&mut self, chan_id: usize, in_buf: &[f32], out_buf: &mut [f32]) {
+		let history = &mut self.history[chan_id];
+		let pos = &mut self.pos[chan_id];
+		let avg_sum = &mut self.avg_sum[chan_id];
+		let sum = &mut self.sum[chan_id];
+		let (len, buf_len) = (history.len(), self.buf_len);
+		let norm = 1.0 / buf_len as f64;
+		let (leak, scale, gain) = (self.leak, self.scale, self.gain);
+
 		in_buf.iter()
 		.zip(out_buf.iter_mut())
 		.for_each(|(sample, out)| {
-			let avg = self.avg_buf[chan_id].iter().sum::<f32>()/(self.buf_len as f32);
-			
-			self.sum[chan_id] += *sample;
-			self.sum[chan_id] -= avg * (20.0 / 44100.0);
-			
-			*out = self.sum[chan_id] - avg * (1.0 - (20.0 / 44100.0));
-			self.avg_buf[chan_id].push_front( self.sum[chan_id] );
-			self.avg_buf[chan_id].pop_back();
+			*sum = *sum * leak + *sample * scale;
+
+			// the one leaving the average, read before it's written over
+			*pos = (*pos + 1) % len;
+			let oldest = history[(*pos + len - buf_len) % len];
+			history[*pos] = *sum;
+			*avg_sum += *sum as f64 - oldest as f64;
+
+			*out = (*sum - (*avg_sum * norm) as f32) * gain;
 		});
 	}
 
+	fn update_params(&mut self) {
+		if let Some(params) = self.params.upgrade() {
+			let sample_rate = params.sample_rate.get();
+			let leak_freq = params.leak_freq.get();
+
+			self.leak = (-consts::TAU * leak_freq / sample_rate).exp();
+			self.scale = consts::TAU * CALCULUS_UNITY_FREQ / sample_rate;
+			self.gain = db_to_gain(params.calculus_gain.get());
+
+			// only a new sample rate needs more room
+			let len = (sample_rate / CALCULUS_MIN_LEAK).ceil() as usize;
+			if len != self.history[0].len() {
+				self.history = [vec![0.0; len], vec![0.0; len]];
+				self.pos = [0; 2];
+				self.avg_sum = [0.0; 2];
+				self.buf_len = 0;
+			}
+
+			let buf_len = ((sample_rate / leak_freq).round() as usize).clamp(1, len);
+			if buf_len != self.buf_len {
+				self.buf_len = buf_len;
+				for ((history, pos), avg_sum) in self.history.iter().zip(self.pos.iter()).zip(self.avg_sum.iter_mut()) {
+					*avg_sum = (0..buf_len).map(|back| history[(pos + len - back) % len] as f64).sum();
+				}
+			}
+		}
+	}
+
+	fn get_latency(&self) -> usize {0}
 }
 
 impl IntEffect {
 	pub fn new(params: Weak<AndrewParams>) -> Self {
+		let sample_rate = 44100.0;
+		let leak_freq = 20.0;
+		let buf_len = (sample_rate / leak_freq) as usize;
+		let len = (sample_rate / CALCULUS_MIN_LEAK).ceil() as usize;
 		IntEffect {
 			sum: [0.0; 2],
-			avg_buf: [VecDeque::from(vec![0.0; 1000]), VecDeque::from(vec![0.0; 1000])],
-			buf_len: 1000,
+			history: [vec![0.0; len], vec![0.0; len]],
+			pos: [0; 2],
+			avg_sum: [0.0; 2],
+			buf_len,
+			leak: (-consts::TAU * leak_freq / sample_rate).exp(),
+			scale: consts::TAU * CALCULUS_UNITY_FREQ / sample_rate,
+			gain: 1.0,
+			params,
 		}
 	}
 }
 
 
+#[inline]
+pub fn db_to_gain( db: f32 ) -> f32 {
+	10f32.powf(db / 20.0)
+}
+
+
+// one pole glide towards a target, for parameters that
+// would zipper if they jumped once a block
+#[derive(Clone, Copy)]
+pub struct Smoothed {
+	value: f32,
+	target: f32,
+	coeff: f32,
+}
+
+impl Smoothed {
+	pub fn new( value: f32 ) -> Self {
+		let mut smoothed = Smoothed { value, target: value, coeff: 1.0 };
+		smoothed.set_time(0.02, 44100.0);
+		smoothed
+	}
+
+	pub fn set_time( &mut self, seconds: f32, sample_rate: f32 ) {
+		self.coeff = 1.0 - (-1.0 / (seconds * sample_rate).max(1.0)).exp();
+	}
+
+	pub fn set( &mut self, target: f32 ) {
+		self.target = target;
+	}
+
+	#[inline]
+	pub fn tick( &mut self ) -> f32 {
+		self.value += (self.target - self.value) * self.coeff;
+		self.value
+	}
+
+	pub fn get( &self ) -> f32 {
+		self.value
+	}
+}
+
+
 
 
+// floor for the level tracked in db mode
+const SLEW_FLOOR_DB: f32 = -100.0;
+
+// the peak follower db mode limits, in seconds. slow enough to ride over
+// the zero crossings of anything but the lowest bass
+const SLEW_ENV_ATTACK: f32 = 0.0005;
+const SLEW_ENV_RELEASE: f32 = 0.05;
+
+// most db mode will lift a fall by, so a sudden stop doesn't drag the
+// noise floor up after it
+const SLEW_MAX_BOOST_DB: f32 = 24.0;
+
+// limits how fast the signal may move, with separate rates for
+// rising and falling. in db mode the rates limit a peak follower on the
+// input in db per second, and the effect turns into a gain riding the
+// input rather than a limiter on the waveform. rises are turned down and
+// falls held up, so the input's level moves at the slewed rate both ways
 pub struct SlewEffect {
-	target_sample: [f32; 2],
 	prev_sample: [f32; 2],
-	amount: f32,
+	envs: [Envelope; 2],
+	level_db: [f32; 2],
+	rise: f32,
+	fall: f32,
+	db_mode: bool,
+	link: bool,
 	params: Weak<AndrewParams>,
 }
 
 impl AndrewEffect for SlewEffect {
 	fn process(&mut self, chan_id: usize, in_buf: &[f32], out_buf: &mut [f32]) {
-	
-		let max_b = self.amount;
+		let (rise, fall) = (self.rise, self.fall);
+
+		if self.db_mode {
+			let env = &mut self.envs[chan_id];
+			let level = &mut self.level_db[chan_id];
+			for (samp, out) in in_buf.iter().zip(out_buf.iter_mut()) {
+				let target = amp_to_db(env.tick(samp.abs()));
+				*level += (target - *level).max( -fall ).min( rise );
+				*out = *samp * slew_gain(*level, target);
+			}
+		} else {
+			let prev = &mut self.prev_sample[chan_id];
+			for (samp, out) in in_buf.iter().zip(out_buf.iter_mut()) {
+				let d = *samp - *prev;
+				*out = *prev + d.max( -fall ).min( rise );
+				*prev = *out;
+			}
+		}
+	}
 
-		for i in 0..in_buf.len() {
-			self.target_sample[chan_id] = in_buf[i];
-			let d = self.target_sample[chan_id] - self.prev_sample[chan_id];
-			let clamped = d.max( -max_b ).min( max_b );
-			out_buf[i] = self.prev_sample[chan_id] + clamped;
-			self.prev_sample[chan_id] = out_buf[i];
+	fn process_stereo(&mut self, in_bufs: [&[f32]; 2], out_bufs: [&mut [f32]; 2]) {
+		let [out_l, out_r] = out_bufs;
+		if !self.link {
+			self.process(0, in_bufs[0], out_l);
+			self.process(1, in_bufs[1], out_r);
+			return
+		}
+
+		let (rise, fall) = (self.rise, self.fall);
+		for i in 0..in_bufs[0].len() {
+			let samps = [in_bufs[0][i], in_bufs[1][i]];
+
+			let outs = if self.db_mode {
+				// both sides follow the louder channel's level
+				let target = amp_to_db(self.envs[0].tick(samps[0].abs().max(samps[1].abs())));
+				let level = &mut self.level_db[0];
+				*level += (target - *level).max( -fall ).min( rise );
+				let gain = slew_gain(*level, target);
+				[samps[0] * gain, samps[1] * gain]
+			} else {
+				// scale both deltas by the same amount so the image doesn't shift
+				let prev = self.prev_sample;
+				let d = [samps[0] - prev[0], samps[1] - prev[1]];
+				let scale = d.iter()
+					.map(|d| {
+						let limit = if *d > 0.0 { rise } else { fall };
+						if d.abs() > limit { limit / d.abs() } else { 1.0 }
+					})
+					.fold(1.0f32, f32::min);
+				[prev[0] + d[0] * scale, prev[1] + d[1] * scale]
+			};
+
+			out_l[i] = outs[0];
+			out_r[i] = outs[1];
+			self.prev_sample = outs;
 		}
+		self.level_db[1] = self.level_db[0];
+		self.envs[1] = self.envs[0];
 	}
 
 	fn update_params(&mut self) {
 		if let Some(params) = self.params.upgrade() {
-			self.amount = params.slew.get() / ( params.sample_rate.get() );
+			let sample_rate = params.sample_rate.get();
+			// the same rates read as full scale per second, or db per second
+			self.rise = params.slew_rise.get() / sample_rate;
+			self.fall = params.slew_fall.get() / sample_rate;
+			self.db_mode = params.slew_db.load(Ordering::Relaxed);
+			self.link = params.slew_link.load(Ordering::Relaxed);
+			for env in self.envs.iter_mut() {
+				env.set_times(SLEW_ENV_ATTACK, SLEW_ENV_RELEASE, sample_rate);
+			}
 		}
 	}
+
+	fn get_latency(&self) -> usize {0}
 }
 
 impl SlewEffect {
 	pub fn new( params: Weak<AndrewParams> ) -> Self {
+		let mut env = Envelope::default();
+		env.set_times(SLEW_ENV_ATTACK, SLEW_ENV_RELEASE, 44100.0);
 		SlewEffect {
-			target_sample: [0.0; 2],
 			prev_sample: [0.0; 2],
-			amount: 1.0,
+			envs: [env; 2],
+			level_db: [SLEW_FLOOR_DB; 2],
+			rise: 1.0,
+			fall: 1.0,
+			db_mode: false,
+			link: false,
 			params,
 		}
 	}
+}
 
-	fn process(&mut self, chan_id: usize, in_buf: &[f32], out_buf: &mut [f32]) {
-	
-		let max_b = self.amount;
 
-		for i in 0..in_buf.len() {
-			self.target_sample[chan_id] = in_buf[i];
-			let d = self.target_sample[chan_id] - self.prev_sample[chan_id];
-			let clamped = d.max( -max_b ).min( max_b );
-			out_buf[i] = self.prev_sample[chan_id] + clamped;
-			self.prev_sample[chan_id] = out_buf[i];
-		}
-	}
+// takes the input's level in db to the slewed one
+#[inline]
+fn slew_gain( level: f32, target: f32 ) -> f32 {
+	db_to_gain((level - target).min(SLEW_MAX_BOOST_DB))
+}
 
-	fn update_params(&mut self) {
-		if let Some(params) = self.params.upgrade() {
-			self.amount = params.slew.get() / ( params.sample_rate.get() );
-		}
-	}
+#[inline]
+pub fn amp_to_db( amp: f32 ) -> f32 {
+	(20.0 * amp.abs().log10()).max(SLEW_FLOOR_DB)
 }
 
 
+// second order slew, limits both the slope and the curvature of the
+// signal. the slope is steered towards the fastest value that can still
+// brake in time to land on the input, so the output never overshoots
 pub struct TooSlewEffect {
-	target_sample: [f32; 2],
 	prev_sample: [f32; 2],
-
-	target_slope: [f32; 2],
 	prev_slope: [f32; 2],
-	amount: f32,
+
+	// limits per sample and per sample squared
+	max_slope: f32,
+	max_accel: f32,
 
 	params: Weak<AndrewParams>,
 }
@@ -328,11 +952,10 @@ pub struct TooSlewEffect {
 impl TooSlewEffect {
 	pub fn new( params: Weak<AndrewParams> ) -> Self {
 		TooSlewEffect {
-			target_sample: [0.0; 2],
 			prev_sample: [0.0; 2],
-			target_slope: [0.0; 2],
 			prev_slope: [0.0; 2],
-			amount: 1.0,
+			max_slope: 1.0,
+			max_accel: 1.0,
 			params,
 		}
 	}
@@ -340,29 +963,39 @@ impl TooSlewEffect {
 
 impl AndrewEffect for TooSlewEffect {
 	fn process(&mut self, chan_id: usize, in_buf: &[f32], out_buf: &mut [f32]) {
-		// // out_buf.iter_mut().zip(in_buf.iter()).for_each(|(out, samp)| *out = 0.0);
+		let (max_s, max_a) = (self.max_slope, self.max_accel);
+		let sample = &mut self.prev_sample[chan_id];
+		let slope = &mut self.prev_slope[chan_id];
 
-		let max_b = self.amount;
+		for (target, out) in in_buf.iter().zip(out_buf.iter_mut()) {
+			let err = *target - *sample;
 
-		for i in 0..in_buf.len() {
-			self.target_sample[chan_id] = in_buf[i];
-			self.target_slope[chan_id] 	= self.target_sample[chan_id] - self.prev_sample[chan_id];
-			let accel  					= self.target_slope[chan_id] - self.prev_slope[chan_id];
+			// fastest slope from which max_accel can still stop us on the target
+			let braking = -0.5 * max_a + (0.25 * max_a * max_a + 2.0 * max_a * err.abs()).sqrt();
+			let wanted = braking.min(max_s).min(err.abs()).copysign(err);
 
-			let slope 					= self.prev_slope[chan_id] + accel;
-			let sample 					= self.prev_sample[chan_id] + slope;
+			let accel = (wanted - *slope).max( -max_a ).min( max_a );
+			*slope += accel;
+			*sample += *slope;
 
-			out_buf[i] = sample;
-			self.prev_sample[chan_id] = sample;
-			self.prev_slope[chan_id] = slope;
+			// a nan from the input would otherwise stick in the state
+			if !sample.is_finite() || !slope.is_finite() {
+				*sample = 0.0;
+				*slope = 0.0;
+			}
+			*out = *sample;
 		}
 	}
 
 	fn update_params(&mut self) {
 		if let Some(params) = self.params.upgrade() {
-			self.amount = params.slew.get() / ( params.sample_rate.get() );
+			let sample_rate = params.sample_rate.get();
+			self.max_slope = params.accel_slope.get() / sample_rate;
+			self.max_accel = params.accel_limit.get() / (sample_rate * sample_rate);
 		}
 	}
+
+	fn get_latency(&self) -> usize {0}
 }
 
 
@@ -390,3 +1023,315 @@ impl Default for DelayEffect {
 		}
 	}
 }
+
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// magnitude of the kernel's response at `freq` cycles per sample
+	fn response( kernel: &[f32], freq: f32 ) -> f32 {
+		let (re, im) = kernel.iter()
+			.enumerate()
+			.fold((0.0, 0.0), |(re, im), (i, tap)| {
+				let w = consts::TAU * freq * i as f32;
+				(re + tap * w.cos(), im - tap * w.sin())
+			});
+		(re * re + im * im).sqrt()
+	}
+
+	#[test]
+	fn kernels_have_unity_dc() {
+		let taps = [0.5, 1.0, -0.25];
+		for shape in (0..KernelShape::COUNT).map(KernelShape::from_index) {
+			for spread in [1.0, 6.0, 40.0] {
+				let kernel = build_kernel(shape, spread, 100, &taps);
+				let dc = kernel.iter().sum::<f32>();
+				assert!((dc - 1.0).abs() < 1e-4, "{} at {} has dc {}", shape.name(), spread, dc);
+			}
+		}
+	}
+
+	#[test]
+	fn sinc_kernel_cuts_off_at_nyquist_over_spread() {
+		// cutoff at 0.125 cycles per sample
+		let kernel = build_kernel(KernelShape::Sinc, 4.0, 100, &[]);
+		assert!((response(&kernel, 0.05) - 1.0).abs() < 0.01);
+		assert!((response(&kernel, 0.125) - 0.5).abs() < 0.05);
+		assert!(response(&kernel, 0.2) < 0.01);
+		assert!(response(&kernel, 0.45) < 0.01);
+	}
+
+	#[test]
+	fn custom_kernel_uses_the_taps_centred() {
+		let kernel = build_kernel(KernelShape::Custom, 1.0, 9, &[1.0, 3.0]);
+		assert_eq!(kernel, vec![0.0, 0.0, 0.0, 0.25, 0.75, 0.0, 0.0, 0.0, 0.0]);
+	}
+
+	#[test]
+	fn conv_swaps_kernels_on_a_single_channel() {
+		let mut conv = ConvEffect::new(Weak::new());
+		let next = build_kernel(KernelShape::Hann, 10.0, conv.buf_len, &[]);
+		conv.next_pattern = Some(next.clone());
+		conv.fade = [0; 2];
+
+		let input = vec![0.0; KERNEL_FADE_LEN + 1];
+		let mut output = vec![0.0; input.len()];
+		conv.process(0, &input, &mut output);
+		assert!(conv.next_pattern.is_none());
+		assert_eq!(conv.pattern, next);
+	}
+
+	#[test]
+	fn too_slew_brakes_onto_a_step_without_overshoot() {
+		let mut slew = TooSlewEffect::new(Weak::new());
+		slew.max_slope = 0.01;
+		slew.max_accel = 0.0005;
+
+		let input: Vec<f32> = (0..2000).map(|i| if i < 1000 { 1.0 } else { -0.5 }).collect();
+		let mut output = vec![0.0; input.len()];
+		slew.process(0, &input, &mut output);
+
+		let mut prev = (0.0, 0.0);
+		for (i, (out, target)) in output.iter().zip(input.iter()).enumerate() {
+			let slope = out - prev.0;
+			assert!(slope.abs() <= slew.max_slope + 1e-6, "slope {} at {}", slope, i);
+			assert!((slope - prev.1).abs() <= slew.max_accel + 1e-6, "accel {} at {}", slope - prev.1, i);
+			prev = (*out, slope);
+
+			// heading for the step, but never past it
+			let start = if i < 1000 { 0.0 } else { 1.0 };
+			assert!((out - start) * (target - out) >= -1e-5, "overshot to {} at {}", out, i);
+		}
+		// and landed on each step before the next
+		assert!((output[999] - 1.0).abs() < 1e-4);
+		assert!((output[1999] + 0.5).abs() < 1e-4);
+	}
+
+	fn slew( rise: f32, fall: f32, db_mode: bool, link: bool ) -> SlewEffect {
+		let mut slew = SlewEffect::new(Weak::new());
+		slew.rise = rise / 44100.0;
+		slew.fall = fall / 44100.0;
+		slew.db_mode = db_mode;
+		slew.link = link;
+		slew
+	}
+
+	#[test]
+	fn slew_rises_and_falls_at_their_own_rates() {
+		// full scale in 100 samples up, 10 down
+		let mut slew = slew(441.0, 4410.0, false, false);
+		let input: Vec<f32> = (0..400).map(|i| if i < 200 { 1.0 } else { 0.0 }).collect();
+		let mut output = vec![0.0; input.len()];
+		slew.process(0, &input, &mut output);
+		assert!((output[49] - 0.5).abs() < 1e-4);
+		assert!((output[99] - 1.0).abs() < 1e-4);
+		assert!((output[204] - 0.5).abs() < 1e-4);
+		assert!(output[209].abs() < 1e-4);
+	}
+
+	#[test]
+	fn db_slew_smooths_the_level_both_ways() {
+		let rms = |buf: &[f32]| (buf.iter().map(|x| x * x).sum::<f32>() / buf.len() as f32).sqrt();
+		// a sine that jumps up 20 db, then back down. long enough before
+		// the jump for the slow one to climb up from silence
+		let (up, down) = (66150, 110250);
+		let input: Vec<f32> = (0..154350)
+			.map(|i| {
+				let amp = if (up..down).contains(&i) { 0.5 } else { 0.05 };
+				amp * (i as f32 * 0.05).sin()
+			})
+			.collect();
+
+		// fast both ways leaves it alone once the follower has caught up
+		let mut fast = slew(10000.0, 10000.0, true, false);
+		let mut output = vec![0.0; input.len()];
+		fast.process(0, &input, &mut output);
+		for at in [up - 5000, down - 5000, down + 30000] {
+			let (ins, outs) = (&input[at..at + 2000], &output[at..at + 2000]);
+			assert!((rms(outs) / rms(ins) - 1.0).abs() < 0.05, "at {}", at);
+		}
+
+		// 100 db/s takes 200 ms over each 20 db step, turning the rise down
+		// and holding the fall up
+		let mut slow = slew(100.0, 100.0, true, false);
+		slow.process(0, &input, &mut output);
+		let db = |at: usize| 20.0 * (rms(&output[at..at + 441]) / rms(&input[at..at + 441])).log10();
+		assert!(db(up - 5000).abs() < 0.5);
+		assert!((db(up + 4410) + 10.0).abs() < 1.5, "{} db 100 ms into the rise", db(up + 4410));
+		// the follower's own release takes some of the fall, so it's held up by less
+		assert!(db(down + 4410) > 3.0, "{} db 100 ms into the fall", db(down + 4410));
+		assert!(db(up + 13230).abs() < 0.5);
+		assert!(db(down + 13230).abs() < 0.5);
+	}
+
+	#[test]
+	fn linked_slew_moves_both_sides_together() {
+		let left: Vec<f32> = (0..400).map(|i| if i < 200 { 1.0 } else { -1.0 }).collect();
+		let right: Vec<f32> = left.iter().map(|samp| samp * 0.5).collect();
+		let (mut out_l, mut out_r) = (vec![0.0; 400], vec![0.0; 400]);
+
+		// the quieter side would get there first on its own
+		slew(441.0, 441.0, false, false).process_stereo([&left, &right], [&mut out_l, &mut out_r]);
+		assert!((out_r[49] - 0.5).abs() < 1e-4 && out_l[49] < 0.6);
+
+		// linked, it keeps its place in the image the whole way
+		for db_mode in [false, true] {
+			slew(441.0, 441.0, db_mode, true).process_stereo([&left, &right], [&mut out_l, &mut out_r]);
+			for (l, r) in out_l.iter().zip(out_r.iter()) {
+				assert!((l * 0.5 - r).abs() < 1e-5, "{} against {}", l, r);
+			}
+		}
+	}
+
+	#[test]
+	fn conv_impulse_comes_out_at_the_latency() {
+		let mut conv = ConvEffect::new(Weak::new());
+		let taps = [0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0];
+		for shape in (0..KernelShape::COUNT).map(KernelShape::from_index) {
+			conv.pattern = build_kernel(shape, 6.0, conv.buf_len, &taps);
+			conv.buf.iter_mut().for_each(|buf| buf.iter_mut().for_each(|samp| *samp = 0.0));
+
+			let mut impulse = vec![0.0; 256];
+			impulse[0] = 1.0;
+			let mut output = vec![0.0; impulse.len()];
+			conv.process(0, &impulse, &mut output);
+			let peak = (0..output.len()).max_by(|a, b| output[*a].total_cmp(&output[*b])).unwrap();
+			assert_eq!(peak, conv.get_latency(), "{}", shape.name());
+		}
+	}
+
+	fn sine( freq: f32, len: usize ) -> Vec<f32> {
+		(0..len).map(|i| 0.5 * (consts::TAU * freq * i as f32 / 44100.0).sin()).collect()
+	}
+
+	fn peak( buf: &[f32] ) -> f32 {
+		buf.iter().fold(0f32, |peak, samp| peak.max(samp.abs()))
+	}
+
+	#[test]
+	fn prime_has_unity_gain_at_1k() {
+		let mut prime = PrimeEffect::new(Weak::new());
+		let input = sine(CALCULUS_UNITY_FREQ, 4410);
+		let mut output = vec![0.0; input.len()];
+		prime.process(0, &input, &mut output);
+		assert!((peak(&output[100..]) / 0.5 - 1.0).abs() < 0.01, "peak {}", peak(&output[100..]));
+	}
+
+	#[test]
+	fn int_average_takes_out_the_dc() {
+		let params = Arc::new(AndrewParams::default());
+		let mut int = IntEffect::new(Arc::downgrade(&params));
+
+		// including a change of leak part way, which only moves the average
+		for leak_freq in [20.0, 150.0, 1.0, 20.0] {
+			params.leak_freq.set(leak_freq);
+			int.update_params();
+			let settle = (44100.0 / leak_freq) as usize * 8;
+
+			let input = vec![0.25; settle + 4410];
+			let mut output = vec![0.0; input.len()];
+			int.process(0, &input, &mut output);
+			assert!(peak(&output[settle..]) < 1e-3, "{} left at {} hz", peak(&output[settle..]), leak_freq);
+
+			// and the average itself passes dc at unity, lining up with the integrator
+			let mean = (int.avg_sum[0] / int.buf_len as f64) as f32;
+			assert!((mean / int.sum[0] - 1.0).abs() < 1e-3, "average {} against {}", mean, int.sum[0]);
+		}
+
+		// the integrator is at unity at 1k too
+		params.leak_freq.set(20.0);
+		int.update_params();
+		let input = sine(CALCULUS_UNITY_FREQ, 44100);
+		let mut output = vec![0.0; input.len()];
+		int.process(1, &input, &mut output);
+		assert!((peak(&output[22050..]) / 0.5 - 1.0).abs() < 0.02, "peak {}", peak(&output[22050..]));
+	}
+
+	// a sine through the wah, with the follower catching every peak and
+	// never letting go, so the sweep sits still where its level puts it
+	fn wah( lowpass: bool, resonance: f32, amp: f32, freq: f32 ) -> f32 {
+		let params = Arc::new(AndrewParams::default());
+		params.wah.store(true, Ordering::Relaxed);
+		params.wah_lowpass.store(lowpass, Ordering::Relaxed);
+		params.wah_resonance.set(resonance);
+		params.wah_min.set(250.0);
+		params.wah_max.set(4000.0);
+		params.wah_sensitivity.set(0.0);
+		params.wah_attack.set(0.001);
+		params.wah_release.set(1e6);
+		let mut filter = FilterEffect::new(Arc::downgrade(&params));
+		filter.update_params();
+
+		let input: Vec<f32> = (0..22050).map(|i| amp * (consts::TAU * freq * i as f32 / 44100.0).sin()).collect();
+		let mut output = vec![0.0; input.len()];
+		filter.process(0, &input, &mut output);
+		peak(&output[11025..]) / amp
+	}
+
+	#[test]
+	fn wah_tracks_the_level() {
+		// -24 db is half way up the 48 db range, -12 db three quarters
+		for (amp, center) in [(db_to_gain(-24.0), 1000.0), (db_to_gain(-12.0), 2000.0)] {
+			assert!((wah(false, 8.0, amp, center) - 1.0).abs() < 0.05, "{} at {}", wah(false, 8.0, amp, center), center);
+			assert!(wah(false, 8.0, amp, center * 1.5) < 0.3);
+			assert!(wah(false, 8.0, amp, center / 1.5) < 0.3);
+		}
+	}
+
+	#[test]
+	fn wah_lowpass_peaks_at_unity() {
+		let amp = db_to_gain(-24.0);
+		for resonance in [0.5, 2.0, 20.0] {
+			// the peak sits a little under the cutoff, or down at dc for a flat one
+			let peak = std::iter::once(50).chain((900..=1000).step_by(5))
+				.map(|freq| wah(true, resonance, amp, freq as f32))
+				.fold(0f32, f32::max);
+			assert!(peak <= 1.01 && peak > 0.9, "peaks at {} with a q of {}", peak, resonance);
+		}
+	}
+
+	fn crush( bits: f32, rate: f32, anti_alias: bool, input: &[f32] ) -> Vec<f32> {
+		let params = Arc::new(AndrewParams::default());
+		params.crush_bits.set(bits);
+		params.crush_rate.set(rate);
+		params.crush_aa.store(anti_alias, Ordering::Relaxed);
+		let mut crush = CrushEffect::new(Arc::downgrade(&params));
+		crush.update_params();
+
+		let mut output = vec![0.0; input.len()];
+		crush.process(0, input, &mut output);
+		// past where the smoothing has settled
+		output.split_off(22050)
+	}
+
+	#[test]
+	fn crush_holds_each_step_on_the_grid() {
+		// 4 bits is steps of an eighth, held for 10 samples at 4410
+		let output = crush(4.0, 4410.0, false, &sine(1234.0, 44100));
+		let mut changes = vec![];
+		for (i, pair) in output.windows(2).enumerate() {
+			// the smoothing stops a hair short of 4 bits in f32
+			let steps = pair[1] * 8.0;
+			assert!((steps - steps.round()).abs() < 1e-3, "{} is off the grid", pair[1]);
+			if pair[1] != pair[0] {
+				changes.push(i);
+			}
+		}
+		for gap in changes.windows(2).map(|pair| pair[1] - pair[0]) {
+			assert!(gap >= 9, "only held for {}", gap);
+		}
+		// and it does move on from most of them
+		assert!(changes.len() > output.len() / 10 * 3 / 4, "{} changes", changes.len());
+	}
+
+	#[test]
+	fn crush_filters_out_what_would_alias() {
+		// 10k folds down to 1180 at 4410, unless it's taken out first
+		let input = sine(10000.0, 44100);
+		assert!(peak(&crush(8.0, 4410.0, false, &input)) > 0.3);
+		assert!(peak(&crush(8.0, 4410.0, true, &input)) < 0.05);
+	}
+}
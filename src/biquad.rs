@@ -11,6 +11,10 @@ pub enum FilterKind {
 	PEAK,
 	LOWSHELF,
 	HIGHSHELF,
+	// second order, the phase turns through 360 degrees, 180 at the center
+	ALLPASS,
+	// first order, 90 degrees at the center. q and gain are ignored
+	ALLPASS1,
 	CUSTOM,
 }
 
@@ -135,6 +139,23 @@ impl BiQuadraticFilter {
 				a1 = 2.0 * ((gain_abs - 1.0) - (gain_abs + 1.0) * cs);
 				a2 = (gain_abs + 1.0) - (gain_abs - 1.0) * cs - beta * sn;
 			},
+			ALLPASS => {
+				b0 = 1.0 - alpha;
+				b1 = -2.0 * cs;
+				b2 = 1.0 + alpha;
+				a0 = 1.0 + alpha;
+				a1 = -2.0 * cs;
+				a2 = 1.0 - alpha;
+			},
+			ALLPASS1 => {
+				let t = (omega / 2.0).tan();
+				b0 = (t - 1.0) / (t + 1.0);
+				b1 = 1.0;
+				b2 = 0.0;
+				a0 = 1.0;
+				a1 = b0;
+				a2 = 0.0;
+			},
 			CUSTOM => {
 				b0 = -alpha;
 				b1 = 0.0;
@@ -210,3 +231,36 @@ impl BiQuadraticFilter {
 
 
 
+
+
+// state variable filter in the topology preserving form. it can be retuned
+// every sample, however fast and however resonant, without blowing up,
+// which a biquad can't promise once its coefficients move under it
+#[derive(Default, Clone, Copy)]
+pub struct Svf {
+	g: f32,
+	k: f32,
+	ic1: f32,
+	ic2: f32,
+}
+
+impl Svf {
+	#[inline]
+	pub fn set( &mut self, center_freq: f32, sample_rate: f32, q: f32 ) {
+		let cf = center_freq.min(0.49 * sample_rate);
+		self.g = (std::f32::consts::PI * cf / sample_rate).tan();
+		self.k = 1.0 / q.max(1e-3);
+	}
+
+	// one step, giving the lowpass, bandpass and highpass
+	#[inline]
+	pub fn filter( &mut self, x: f32 ) -> (f32, f32, f32) {
+		let a1 = 1.0 / (1.0 + self.g * (self.g + self.k));
+		let v3 = x - self.ic2;
+		let v1 = a1 * self.ic1 + self.g * a1 * v3;
+		let v2 = self.ic2 + self.g * v1;
+		self.ic1 = 2.0 * v1 - self.ic1;
+		self.ic2 = 2.0 * v2 - self.ic2;
+		(v2, v1, x - self.k * v1 - v2)
+	}
+}
@@ -0,0 +1,303 @@
+use std::ptr::null_mut;
+use std::sync::Weak;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use crate::AndrewParams;
+use crate::delay_line::DelayLine;
+use crate::andrew_effect::*;
+use crate::codec::CodecEffect;
+use crate::dynamics::{CompEffect, DynEqEffect, GateEffect, TransientEffect};
+use crate::ring::RingEffect;
+use crate::phaser::PhaserEffect;
+use crate::pitch::PitchEffect;
+use crate::freeze::{FreezeEffect, FreezeProcessor};
+use crate::tape::TapeEffect;
+use crate::chorus::{ChorusEffect, FlangerEffect};
+use crate::reverb::ReverbEffect;
+use crate::imager::ImagerEffect;
+use crate::tremolo::TremoloEffect;
+use crate::resonator::KsEffect;
+use crate::vocoder::VocoderEffect;
+use crate::formant::FormantEffect;
+#[cfg(feature = "mp3")]
+use crate::mp3ifier::Mp3ifier;
+
+
+// which effect the plugin runs. picking another one rebuilds the chain
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum EffectKind {
+	#[default]
+	Dist,
+	Conv,
+	Int,
+	Prime,
+	Slew,
+	TooSlew,
+	Codec,
+	Crush,
+	Comp,
+	Gate,
+	Transient,
+	Ring,
+	Phaser,
+	Pitch,
+	Freeze,
+	Tape,
+	Chorus,
+	Flanger,
+	Reverb,
+	Imager,
+	Tremolo,
+	Ks,
+	Vocoder,
+	Formant,
+	Filter,
+	DynEq,
+	#[cfg(feature = "mp3")]
+	Mp3,
+}
+
+impl EffectKind {
+	// in the order the effect param steps through them. some are
+	// behind features, so the list is built rather than matched on
+	const ALL: &'static [EffectKind] = &[
+		EffectKind::Dist,
+		EffectKind::Conv,
+		EffectKind::Int,
+		EffectKind::Prime,
+		EffectKind::Slew,
+		EffectKind::TooSlew,
+		EffectKind::Codec,
+		EffectKind::Crush,
+		EffectKind::Comp,
+		EffectKind::Gate,
+		EffectKind::Transient,
+		EffectKind::Ring,
+		EffectKind::Phaser,
+		EffectKind::Pitch,
+		EffectKind::Freeze,
+		EffectKind::Tape,
+		EffectKind::Chorus,
+		EffectKind::Flanger,
+		EffectKind::Reverb,
+		EffectKind::Imager,
+		EffectKind::Tremolo,
+		EffectKind::Ks,
+		EffectKind::Vocoder,
+		EffectKind::Formant,
+		EffectKind::Filter,
+		EffectKind::DynEq,
+		// last, so the ones before it keep their places with or without it
+		#[cfg(feature = "mp3")]
+		EffectKind::Mp3,
+	];
+
+	pub const COUNT: u8 = EffectKind::ALL.len() as u8;
+
+	pub fn from_index( i: u8 ) -> Self {
+		EffectKind::ALL[(i as usize).min(EffectKind::ALL.len() - 1)]
+	}
+
+	pub fn name( &self ) -> &'static str {
+		match self {
+			EffectKind::Dist => "dist",
+			EffectKind::Conv => "conv",
+			EffectKind::Int => "integrate",
+			EffectKind::Prime => "differentiate",
+			EffectKind::Slew => "slew",
+			EffectKind::TooSlew => "too slew",
+			EffectKind::Codec => "codec",
+			EffectKind::Crush => "crush",
+			EffectKind::Comp => "comp",
+			EffectKind::Gate => "gate",
+			EffectKind::Transient => "transient",
+			EffectKind::Ring => "ring",
+			EffectKind::Phaser => "phaser",
+			EffectKind::Pitch => "pitch",
+			EffectKind::Freeze => "freeze",
+			EffectKind::Tape => "tape",
+			EffectKind::Chorus => "chorus",
+			EffectKind::Flanger => "flanger",
+			EffectKind::Reverb => "reverb",
+			EffectKind::Imager => "imager",
+			EffectKind::Tremolo => "tremolo",
+			EffectKind::Ks => "resonator",
+			EffectKind::Vocoder => "vocoder",
+			EffectKind::Formant => "formant",
+			EffectKind::Filter => "filter",
+			EffectKind::DynEq => "dynamic eq",
+			#[cfg(feature = "mp3")]
+			EffectKind::Mp3 => "mp3",
+		}
+	}
+
+	// the effects to run, in order, each yet to have its params read
+	pub fn build( &self, params: Weak<AndrewParams> ) -> Vec<Box<dyn AndrewEffect>> {
+		match self {
+			EffectKind::Dist => vec![Box::new(DistEffect::new(params)), Box::new(VibEffect::new())],
+			EffectKind::Conv => vec![Box::new(ConvEffect::new(params))],
+			EffectKind::Int => vec![Box::new(IntEffect::new(params))],
+			EffectKind::Prime => vec![Box::new(PrimeEffect::new(params))],
+			EffectKind::Slew => vec![Box::new(SlewEffect::new(params))],
+			EffectKind::TooSlew => vec![Box::new(TooSlewEffect::new(params))],
+			EffectKind::Codec => vec![Box::new(CodecEffect::new(params))],
+			EffectKind::Crush => vec![Box::new(CrushEffect::new(params))],
+			EffectKind::Comp => vec![Box::new(CompEffect::new(params))],
+			EffectKind::Gate => vec![Box::new(GateEffect::new(params))],
+			EffectKind::Transient => vec![Box::new(TransientEffect::new(params))],
+			EffectKind::Ring => vec![Box::new(RingEffect::new(params))],
+			EffectKind::Phaser => vec![Box::new(PhaserEffect::new(params))],
+			EffectKind::Pitch => vec![Box::new(PitchEffect::new(params))],
+			EffectKind::Freeze => vec![Box::new(FreezeEffect::new(FreezeProcessor::new(params)))],
+			EffectKind::Tape => vec![Box::new(TapeEffect::new(params))],
+			EffectKind::Chorus => vec![Box::new(ChorusEffect::new(params))],
+			EffectKind::Flanger => vec![Box::new(FlangerEffect::new(params))],
+			EffectKind::Reverb => vec![Box::new(ReverbEffect::new(params))],
+			EffectKind::Imager => vec![Box::new(ImagerEffect::new(params))],
+			EffectKind::Tremolo => vec![Box::new(TremoloEffect::new(params))],
+			EffectKind::Ks => vec![Box::new(KsEffect::new(params))],
+			EffectKind::Vocoder => vec![Box::new(VocoderEffect::new(params))],
+			EffectKind::Formant => vec![Box::new(FormantEffect::new(params))],
+			EffectKind::Filter => vec![Box::new(FilterEffect::new(params))],
+			EffectKind::DynEq => vec![Box::new(DynEqEffect::new(params))],
+			#[cfg(feature = "mp3")]
+			EffectKind::Mp3 => vec![Box::new(Mp3ifier::new(params))],
+		}
+	}
+}
+
+
+// the effects for one kind, ready to run. built and dropped away from
+// the audio thread, which only ever swaps one for another
+#[derive(Default)]
+pub struct Chain {
+	pub effects: Vec<Box<dyn AndrewEffect>>,
+	// the effects run one after another, so their delays add up
+	pub latency: usize,
+	// the input, held back as long as the effects hold it, for the dry mix
+	dry: [DelayLine; 2],
+}
+
+impl Chain {
+	pub fn build( kind: EffectKind, params: Weak<AndrewParams> ) -> Self {
+		let mut effects = kind.build(params);
+		effects.iter_mut().for_each(|effect| effect.update_params());
+		let latency = effects.iter().map(|effect| effect.get_latency()).sum();
+		Chain {
+			effects,
+			latency,
+			dry: [DelayLine::new(latency), DelayLine::new(latency)],
+		}
+	}
+
+	// the input sample that lines up with what the effects give back now
+	pub fn dry( &mut self, chan_id: usize, samp: f32 ) -> f32 {
+		if self.latency == 0 {
+			return samp
+		}
+		let line = &mut self.dry[chan_id];
+		line.push(samp);
+		line.read(self.latency as f32)
+	}
+}
+
+
+// passes built chains to the audio thread, and the ones it is done with
+// back, without either side waiting on the other. the audio thread only
+// moves pointers, so it never allocates or frees a chain
+#[derive(Default)]
+pub struct ChainSwap {
+	// the newest chain built and not yet picked up
+	built: AtomicPtr<Chain>,
+	// the one the audio thread last swapped out, to be dropped elsewhere
+	retired: AtomicPtr<Chain>,
+}
+
+impl ChainSwap {
+	// hands over a chain. one built before it and never picked up is
+	// dropped, as is one the audio thread has finished with
+	pub fn offer( &self, chain: Chain ) {
+		drop_chain(self.built.swap(Box::into_raw(Box::new(chain)), Ordering::AcqRel));
+		self.collect();
+	}
+
+	// drops the chain the audio thread swapped out, if there is one
+	pub fn collect( &self ) {
+		drop_chain(self.retired.swap(null_mut(), Ordering::AcqRel));
+	}
+
+	// swaps in the newest chain built, if there is one. the old one is
+	// left to be collected, and until it has been there is nowhere to
+	// leave another, so the swap waits
+	pub fn swap( &self, chain: &mut Box<Chain> ) -> bool {
+		if !self.retired.load(Ordering::Acquire).is_null() {
+			return false
+		}
+		let built = self.built.swap(null_mut(), Ordering::AcqRel);
+		if built.is_null() {
+			return false
+		}
+		let old = std::mem::replace(chain, unsafe { Box::from_raw(built) });
+		self.retired.store(Box::into_raw(old), Ordering::Release);
+		true
+	}
+}
+
+impl Drop for ChainSwap {
+	fn drop( &mut self ) {
+		drop_chain(*self.built.get_mut());
+		drop_chain(*self.retired.get_mut());
+	}
+}
+
+// the pointers only ever come from Box::into_raw, and each is taken
+// out of its slot before it gets here, so it is freed just the once
+fn drop_chain( chain: *mut Chain ) {
+	if !chain.is_null() {
+		drop(unsafe { Box::from_raw(chain) });
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn chain( latency: usize ) -> Chain {
+		Chain { latency, ..Default::default() }
+	}
+
+	#[test]
+	fn swap_takes_the_newest_and_waits_on_the_old_one() {
+		let swap = ChainSwap::default();
+		let mut running = Box::new(chain(0));
+		assert!(!swap.swap(&mut running));
+
+		// only the newest of two built is picked up
+		swap.offer(chain(1));
+		swap.offer(chain(2));
+		assert!(swap.swap(&mut running));
+		assert_eq!(running.latency, 2);
+
+		// the one swapped out has to be collected before another goes in
+		swap.built.store(Box::into_raw(Box::new(chain(3))), Ordering::Release);
+		assert!(!swap.swap(&mut running));
+		assert_eq!(running.latency, 2);
+		swap.collect();
+		assert!(swap.swap(&mut running));
+		assert_eq!(running.latency, 3);
+	}
+
+	#[test]
+	fn dry_lines_up_with_the_effects() {
+		let mut chain = Chain::build(EffectKind::Codec, Weak::new());
+		assert!(chain.latency > 0);
+		let out: Vec<f32> = (0..chain.latency * 2).map(|i| chain.dry(0, (i == 3) as u8 as f32)).collect();
+		assert_eq!(out.iter().position(|samp| *samp != 0.0), Some(chain.latency + 3));
+		assert_eq!(out[chain.latency + 3], 1.0);
+
+		let mut chain = Chain::build(EffectKind::Dist, Weak::new());
+		assert_eq!(chain.latency, 0);
+		assert_eq!(chain.dry(1, 0.5), 0.5);
+	}
+}
@@ -0,0 +1,300 @@
+use std::f32::consts::FRAC_PI_4;
+use std::sync::{Weak, atomic::Ordering};
+
+use crate::AndrewParams;
+use crate::andrew_effect::AndrewEffect;
+use crate::delay_line::DelayLine;
+use crate::modulator::{Lfo, LfoShape};
+
+pub const CHORUS_MAX_VOICES: usize = 8;
+
+// delay the voices sweep up from, and the most they sweep by, in seconds
+const CHORUS_BASE_DELAY: f32 = 0.012;
+pub const CHORUS_MAX_DEPTH: f32 = 0.008;
+
+// longest the flanger's delay and sweep can add up to, in seconds
+const FLANGER_MAX_DELAY: f32 = 0.02;
+
+// the furthest through zero holds the dry side back, with the delay and
+// depth params at their tops. the output waits this long in either mode,
+// so moving them never changes the latency the host has been told
+const FLANGER_LATENCY: f32 = 0.015;
+
+// keeps the flanger's feedback loop from running away
+const FLANGER_MAX_FEEDBACK: f32 = 0.95;
+
+
+// up to eight modulated taps on the same delay. each voice runs its own
+// lfo, spread in phase around the cycle and in rate by `detune`, and is
+// panned to its own place across the stereo field
+pub struct ChorusEffect {
+	lines: [DelayLine; 2],
+	lfos: [Lfo; CHORUS_MAX_VOICES],
+	// left and right gain of each voice
+	pans: [(f32, f32); CHORUS_MAX_VOICES],
+
+	voices: usize,
+	rate: f32,
+	depth: f32,
+	detune: f32,
+	width: f32,
+	mix: f32,
+	sample_rate: f32,
+
+	params: Weak<AndrewParams>,
+}
+
+impl AndrewEffect for ChorusEffect {
+	fn process_stereo(&mut self, in_bufs: [&[f32]; 2], out_bufs: [&mut [f32]; 2]) {
+		let [out_l, out_r] = out_bufs;
+		let base = CHORUS_BASE_DELAY * self.sample_rate;
+		let depth = self.depth * self.sample_rate;
+		let norm = 1.0 / (self.voices as f32).sqrt();
+
+		for i in 0..in_bufs[0].len() {
+			let (in_l, in_r) = (in_bufs[0][i], in_bufs[1][i]);
+			self.lines[0].push(in_l);
+			self.lines[1].push(in_r);
+
+			let mut wet = (0.0, 0.0);
+			for (lfo, (gain_l, gain_r)) in self.lfos.iter_mut().zip(self.pans.iter()).take(self.voices) {
+				let delay = base + depth * (0.5 + 0.5 * lfo.tick());
+				wet.0 += self.lines[0].read(delay) * gain_l;
+				wet.1 += self.lines[1].read(delay) * gain_r;
+			}
+
+			out_l[i] = in_l * (1.0 - self.mix) + wet.0 * norm * self.mix;
+			out_r[i] = in_r * (1.0 - self.mix) + wet.1 * norm * self.mix;
+		}
+	}
+
+	fn update_params(&mut self) {
+		if let Some(params) = self.params.upgrade() {
+			let voices = (params.chorus_voices.load(Ordering::Relaxed) as usize).clamp(1, CHORUS_MAX_VOICES);
+			self.rate = params.chorus_rate.get();
+			self.depth = params.chorus_depth.get().min(CHORUS_MAX_DEPTH);
+			self.detune = params.chorus_detune.get();
+			self.width = params.chorus_width.get();
+			self.mix = params.chorus_mix.get();
+
+			let sample_rate = params.sample_rate.get();
+			if sample_rate != self.sample_rate {
+				self.sample_rate = sample_rate;
+				let max_delay = ((CHORUS_BASE_DELAY + CHORUS_MAX_DEPTH) * sample_rate) as usize + 4;
+				self.lines = [DelayLine::new(max_delay), DelayLine::new(max_delay)];
+			}
+
+			for (v, (lfo, pan)) in self.lfos.iter_mut().zip(self.pans.iter_mut()).enumerate().take(voices) {
+				// -1 to 1 across the voices
+				let spot = if voices > 1 { 2.0 * v as f32 / (voices - 1) as f32 - 1.0 } else { 0.0 };
+
+				lfo.set_sample_rate(sample_rate);
+				lfo.set_freq(self.rate * (1.0 + 0.5 * self.detune * spot));
+				if voices != self.voices {
+					lfo.set_phase(v as f32 / voices as f32);
+				}
+
+				// equal power, with the center at unity on both sides
+				let angle = (1.0 + self.width * spot) * FRAC_PI_4;
+				*pan = (angle.cos() * 2f32.sqrt(), angle.sin() * 2f32.sqrt());
+			}
+			self.voices = voices;
+		}
+	}
+
+	fn get_latency(&self) -> usize {0}
+}
+
+impl ChorusEffect {
+	pub fn new( params: Weak<AndrewParams> ) -> Self {
+		let max_delay = ((CHORUS_BASE_DELAY + CHORUS_MAX_DEPTH) * 44100.0) as usize + 4;
+		ChorusEffect {
+			lines: [DelayLine::new(max_delay), DelayLine::new(max_delay)],
+			lfos: Default::default(),
+			pans: [(1.0, 1.0); CHORUS_MAX_VOICES],
+			voices: 0,
+			rate: 0.8,
+			depth: 0.003,
+			detune: 0.3,
+			width: 0.7,
+			mix: 0.5,
+			sample_rate: 44100.0,
+			params,
+		}
+	}
+}
+
+
+// a single short sweeping tap fed back on itself. through zero the dry
+// side is delayed to the middle of the sweep, so the tap can pass it and
+// the notches fold right down through the bottom of the spectrum
+pub struct FlangerEffect {
+	lines: [DelayLine; 2],
+	// the dry input on its own, for through zero
+	dry_lines: [DelayLine; 2],
+	// the output, padded out to the latency
+	out_lines: [DelayLine; 2],
+	lfo: [Lfo; 2],
+	feedback_samp: [f32; 2],
+
+	delay: f32,
+	depth: f32,
+	feedback: f32,
+	through_zero: bool,
+	mix: f32,
+	sample_rate: f32,
+
+	params: Weak<AndrewParams>,
+}
+
+impl AndrewEffect for FlangerEffect {
+	fn process(&mut self, chan_id: usize, in_buf: &[f32], out_buf: &mut [f32]) {
+		let min_delay = (self.delay * self.sample_rate).max(1.0);
+		let depth = self.depth * self.sample_rate;
+		let center = min_delay + depth;
+		let latency = self.get_latency() as f32;
+		let pad = if self.through_zero { latency - center } else { latency };
+
+		for (samp, out) in in_buf.iter().zip(out_buf.iter_mut()) {
+			let sweep = self.lfo[chan_id].tick();
+			self.lines[chan_id].push(samp + self.feedback * self.feedback_samp[chan_id]);
+
+			let (dry, wet) = if self.through_zero {
+				self.dry_lines[chan_id].push(*samp);
+				(self.dry_lines[chan_id].read(center), self.lines[chan_id].read(center + depth * sweep))
+			} else {
+				(*samp, self.lines[chan_id].read(min_delay + depth * (0.5 + 0.5 * sweep)))
+			};
+			self.feedback_samp[chan_id] = wet;
+
+			self.out_lines[chan_id].push(dry * (1.0 - self.mix) + wet * self.mix);
+			*out = self.out_lines[chan_id].read(pad);
+		}
+	}
+
+	fn update_params(&mut self) {
+		if let Some(params) = self.params.upgrade() {
+			self.delay = params.flanger_delay.get();
+			self.depth = params.flanger_depth.get().min(FLANGER_LATENCY - self.delay).max(0.0);
+			self.feedback = params.flanger_feedback.get().clamp(-FLANGER_MAX_FEEDBACK, FLANGER_MAX_FEEDBACK);
+			self.mix = params.flanger_mix.get();
+
+			let through_zero = params.flanger_tz.load(Ordering::Relaxed);
+			if through_zero != self.through_zero {
+				self.through_zero = through_zero;
+				self.dry_lines.iter_mut().for_each(DelayLine::clear);
+			}
+
+			let sample_rate = params.sample_rate.get();
+			if sample_rate != self.sample_rate {
+				self.sample_rate = sample_rate;
+				let max_delay = (FLANGER_MAX_DELAY * sample_rate) as usize + 4;
+				self.lines = [DelayLine::new(max_delay), DelayLine::new(max_delay)];
+				self.dry_lines = self.lines.clone();
+				let latency = self.get_latency();
+				self.out_lines = [DelayLine::new(latency), DelayLine::new(latency)];
+			}
+
+			for lfo in self.lfo.iter_mut() {
+				lfo.set_sample_rate(sample_rate);
+				lfo.set_freq(params.flanger_rate.get());
+			}
+		}
+	}
+
+	// a sample over, so there is always some padding left through zero
+	fn get_latency(&self) -> usize {
+		(FLANGER_LATENCY * self.sample_rate).ceil() as usize + 1
+	}
+}
+
+impl FlangerEffect {
+	pub fn new( params: Weak<AndrewParams> ) -> Self {
+		let line = DelayLine::new((FLANGER_MAX_DELAY * 44100.0) as usize + 4);
+		let out_line = DelayLine::new((FLANGER_LATENCY * 44100.0).ceil() as usize + 1);
+		let mut lfo = Lfo::default();
+		lfo.set_shape(LfoShape::Triangle);
+		FlangerEffect {
+			lines: [line.clone(), line.clone()],
+			dry_lines: [line.clone(), line],
+			out_lines: [out_line.clone(), out_line],
+			lfo: [lfo.clone(), lfo],
+			feedback_samp: [0.0; 2],
+			delay: 0.001,
+			depth: 0.002,
+			feedback: 0.5,
+			through_zero: false,
+			mix: 0.5,
+			sample_rate: 44100.0,
+			params,
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::Arc;
+
+	fn peak_at( buf: &[f32] ) -> usize {
+		(0..buf.len()).max_by(|a, b| buf[*a].abs().total_cmp(&buf[*b].abs())).unwrap()
+	}
+
+	#[test]
+	fn flanger_latency_stays_put_and_lines_up() {
+		let params = Arc::new(AndrewParams::default());
+		params.flanger_mix.set(0.0);
+		let mut flanger = FlangerEffect::new(Arc::downgrade(&params));
+		flanger.update_params();
+		let latency = flanger.get_latency();
+
+		for through_zero in [false, true] {
+			for (delay, depth) in [(0.0001, 0.0), (0.001, 0.002), (0.01, 0.005)] {
+				params.flanger_tz.store(through_zero, Ordering::Relaxed);
+				params.flanger_delay.set(delay);
+				params.flanger_depth.set(depth);
+				flanger.update_params();
+				assert_eq!(flanger.get_latency(), latency);
+
+				// the dry side alone, which through zero is held back too
+				let mut impulse = vec![0.0; 2048];
+				impulse[0] = 1.0;
+				let mut out = vec![0.0; 2048];
+				flanger.process(0, &impulse, &mut out);
+				assert_eq!(peak_at(&out), latency, "through zero {} at {} + {}", through_zero, delay, depth);
+			}
+		}
+	}
+
+	#[test]
+	fn chorus_voices_sweep_between_the_base_delay_and_the_depth() {
+		let params = Arc::new(AndrewParams::default());
+		params.chorus_rate.set(1.0);
+		params.chorus_depth.set(CHORUS_MAX_DEPTH);
+		params.chorus_mix.set(1.0);
+		let (base, depth) = (CHORUS_BASE_DELAY * 44100.0, CHORUS_MAX_DEPTH * 44100.0);
+
+		for voices in [1, 3, CHORUS_MAX_VOICES as u8] {
+			params.chorus_voices.store(voices, Ordering::Relaxed);
+			let mut chorus = ChorusEffect::new(Arc::downgrade(&params));
+			chorus.update_params();
+
+			// a click every 50 ms for two turns of the lfo
+			let input: Vec<f32> = (0..88200).map(|i| (i % 2205 == 0) as u8 as f32).collect();
+			let (mut left, mut right) = (vec![0.0; input.len()], vec![0.0; input.len()]);
+			chorus.process_stereo([&input, &input], [&mut left, &mut right]);
+
+			let mut arrivals = vec![];
+			for click in left.chunks(2205) {
+				// the interpolation spreads each tap over the samples either side of it
+				let heard: Vec<usize> = (0..click.len()).filter(|i| click[*i].abs() > 1e-4).collect();
+				assert!(heard.iter().all(|i| *i as f32 >= base - 2.0 && *i as f32 <= base + depth + 3.0), "{} voices heard at {:?}", voices, heard);
+				arrivals.push(peak_at(click));
+			}
+			// and the sweep gets to both ends
+			let (first, last) = (arrivals.iter().min().unwrap(), arrivals.iter().max().unwrap());
+			assert!((*first as f32) < base + 0.1 * depth && (*last as f32) > base + 0.9 * depth, "{} voices only from {} to {}", voices, first, last);
+		}
+	}
+}
@@ -0,0 +1,310 @@
+use std::sync::{Weak, atomic::Ordering};
+
+use crate::AndrewParams;
+use crate::andrew_effect::AndrewEffect;
+use crate::fft::Mdct;
+use crate::noise::Noise;
+
+// mdct coefficients per frame, frames overlap by half
+const CODEC_HOP: usize = 512;
+
+// how far under the spread band energy the masking threshold sits
+const CODEC_SMR_DB: f32 = 14.0;
+
+// level in db spl that a full scale sine is taken to play back at
+const CODEC_FULL_SCALE_SPL: f32 = 96.0;
+
+// quantiser side info, charged per band that has anything left in it
+const CODEC_BAND_BITS: f32 = 4.0;
+
+
+// imitates a low bitrate perceptual codec without encoding anything. each
+// frame is mdct'd, every band gets a quantiser step from a masking
+// threshold, and a global offset on those steps is searched until the
+// frame fits in the bit budget. bands that quantise to nothing drop out
+pub struct CodecEffect {
+	chans: [CodecChannel; 2],
+	mdct: Mdct,
+	window: Vec<f32>,
+
+	// bin ranges, roughly one bark wide
+	bands: Vec<(usize, usize)>,
+	// threshold in quiet per bin, for each band
+	ath: Vec<f32>,
+	// masking spread by band distance, indexed from -bands.len()
+	spread: Vec<f32>,
+	noise: Noise,
+
+	frame: Vec<f32>,
+	coeffs: Vec<f32>,
+	// coefficient magnitudes to the 3/4, the same for every pass of the search
+	mags: Vec<f32>,
+	energy: Vec<f32>,
+	thr: Vec<f32>,
+
+	kbps: f32,
+	bandwidth: f32,
+	pre_echo: bool,
+	warble: f32,
+	sample_rate: f32,
+	// skips the coding, leaving only the transform
+	bypass: bool,
+
+	params: Weak<AndrewParams>,
+}
+
+struct CodecChannel {
+	// the last two hops of input, oldest first
+	input: Vec<f32>,
+	// samples written since the last frame
+	fill: usize,
+	// second half of the previous synthesis frame
+	overlap: Vec<f32>,
+	// finished samples being played back
+	ready: Vec<f32>,
+	// band thresholds of the previous frame, for pre-echo control
+	prev_thr: Vec<f32>,
+}
+
+impl CodecChannel {
+	fn new() -> Self {
+		CodecChannel {
+			input: vec![0.0; 2 * CODEC_HOP],
+			fill: 0,
+			overlap: vec![0.0; CODEC_HOP],
+			ready: vec![0.0; CODEC_HOP],
+			prev_thr: vec![],
+		}
+	}
+}
+
+impl AndrewEffect for CodecEffect {
+	fn process(&mut self, chan_id: usize, in_buf: &[f32], out_buf: &mut [f32]) {
+		for (samp, out) in in_buf.iter().zip(out_buf.iter_mut()) {
+			let chan = &mut self.chans[chan_id];
+			chan.input[CODEC_HOP + chan.fill] = *samp;
+			*out = chan.ready[chan.fill];
+			chan.fill += 1;
+
+			if chan.fill == CODEC_HOP {
+				self.run_frame(chan_id);
+				let chan = &mut self.chans[chan_id];
+				chan.input.copy_within(CODEC_HOP.., 0);
+				chan.fill = 0;
+			}
+		}
+	}
+
+	fn update_params(&mut self) {
+		if let Some(params) = self.params.upgrade() {
+			self.kbps = params.codec_kbps.get();
+			self.bandwidth = params.codec_bandwidth.get();
+			self.pre_echo = params.codec_pre_echo.load(Ordering::Relaxed);
+			self.warble = params.codec_warble.get();
+
+			let sample_rate = params.sample_rate.get();
+			if sample_rate != self.sample_rate {
+				self.set_sample_rate(sample_rate);
+			}
+		}
+	}
+
+	fn get_latency(&self) -> usize {
+		2 * CODEC_HOP
+	}
+}
+
+impl CodecEffect {
+	pub fn new( params: Weak<AndrewParams> ) -> Self {
+		let mut codec = CodecEffect {
+			chans: [CodecChannel::new(), CodecChannel::new()],
+			mdct: Mdct::new(CODEC_HOP),
+			window: Mdct::window(CODEC_HOP),
+			bands: vec![],
+			ath: vec![],
+			spread: vec![],
+			noise: Noise::default(),
+			frame: vec![0.0; 2 * CODEC_HOP],
+			coeffs: vec![0.0; CODEC_HOP],
+			mags: vec![0.0; CODEC_HOP],
+			energy: vec![],
+			thr: vec![],
+			kbps: 64.0,
+			bandwidth: 16000.0,
+			pre_echo: false,
+			warble: 0.0,
+			sample_rate: 0.0,
+			bypass: false,
+			params,
+		};
+		codec.set_sample_rate(44100.0);
+		codec
+	}
+
+	fn set_sample_rate( &mut self, sample_rate: f32 ) {
+		self.sample_rate = sample_rate;
+		let bin_hz = sample_rate / (2 * CODEC_HOP) as f32;
+		let bark = |f: f32| 13.0 * (0.00076 * f).atan() + 3.5 * (f / 7500.0).powi(2).atan();
+
+		self.bands.clear();
+		let mut start = 0;
+		for bin in 1..=CODEC_HOP {
+			if bin == CODEC_HOP || bark(bin as f32 * bin_hz).floor() != bark(start as f32 * bin_hz).floor() {
+				self.bands.push((start, bin));
+				start = bin;
+			}
+		}
+
+		// terhardt's threshold in quiet, relative to a full scale bin
+		let full_scale = CODEC_HOP as f32 / 4.0;
+		self.ath = self.bands.iter()
+			.map(|(lo, hi)| {
+				let khz = ((lo + hi) as f32 * 0.5 * bin_hz / 1000.0).max(0.02);
+				let spl = 3.64 * khz.powf(-0.8)
+					- 6.5 * (-0.6 * (khz - 3.3).powi(2)).exp()
+					+ 1e-3 * khz.powi(4);
+				full_scale * 10f32.powf((spl.min(96.0) - CODEC_FULL_SCALE_SPL) / 10.0)
+			})
+			.collect();
+
+		// masking reaches 10 db per band upwards and 25 db per band downwards
+		let n = self.bands.len() as isize;
+		self.spread = (-n..=n)
+			.map(|d| if d >= 0 { -10.0 * d as f32 } else { 25.0 * d as f32 })
+			.map(|db| 10f32.powf(db / 10.0))
+			.collect();
+
+		self.energy = vec![0.0; self.bands.len()];
+		self.thr = vec![0.0; self.bands.len()];
+		for chan in self.chans.iter_mut() {
+			chan.prev_thr = self.ath.clone();
+		}
+	}
+
+	fn run_frame( &mut self, chan_id: usize ) {
+		let chan = &mut self.chans[chan_id];
+		for ((frame, samp), w) in self.frame.iter_mut().zip(chan.input.iter()).zip(self.window.iter()) {
+			*frame = samp * w;
+		}
+		self.mdct.forward(&self.frame, &mut self.coeffs);
+		if !self.bypass {
+			self.code(chan_id);
+		}
+
+		let chan = &mut self.chans[chan_id];
+		self.mdct.inverse(&self.coeffs, &mut self.frame);
+		for (i, (frame, w)) in self.frame.iter().zip(self.window.iter()).enumerate() {
+			let samp = frame * w;
+			if i < CODEC_HOP {
+				chan.ready[i] = chan.overlap[i] + samp;
+			} else {
+				chan.overlap[i - CODEC_HOP] = samp;
+			}
+		}
+	}
+
+	// quantises the frame's coefficients in place to fit the bitrate
+	fn code( &mut self, chan_id: usize ) {
+		let chan = &mut self.chans[chan_id];
+		let cutoff = ((self.bandwidth / self.sample_rate * (2 * CODEC_HOP) as f32) as usize).min(CODEC_HOP);
+		self.coeffs[cutoff..].iter_mut().for_each(|c| *c = 0.0);
+
+		for (energy, (lo, hi)) in self.energy.iter_mut().zip(self.bands.iter()) {
+			*energy = self.coeffs[*lo..*hi].iter().map(|c| c * c).sum();
+		}
+
+		// allowed noise per bin in every band
+		let n = self.bands.len();
+		let smr = 10f32.powf(-CODEC_SMR_DB / 10.0);
+		let spread = &self.spread;
+		for b in 0..n {
+			let masked = self.energy.iter()
+				.enumerate()
+				.map(|(j, e)| e * spread[b + n - j])
+				.sum::<f32>();
+			let width = (self.bands[b].1 - self.bands[b].0) as f32;
+			let mut thr = (masked * smr / width).max(self.ath[b]);
+
+			// an attack can't hide behind the quiet frame before it,
+			// unless pre-echo is what we are after
+			if !self.pre_echo {
+				thr = thr.min(2.0 * chan.prev_thr[b]);
+			}
+			chan.prev_thr[b] = thr;
+
+			if self.warble > 0.0 {
+				thr *= 10f32.powf(self.warble * 1.2 * self.noise.white());
+			}
+			self.thr[b] = thr;
+		}
+
+		// search the global offset that just fits the budget
+		for (mag, c) in self.mags.iter_mut().zip(self.coeffs.iter()) {
+			*mag = c.abs().powf(0.75);
+		}
+		let budget = self.kbps * 1000.0 * 0.5 * CODEC_HOP as f32 / self.sample_rate;
+		let (mut lo, mut hi) = (-30.0, 60.0);
+		for _ in 0..10 {
+			let mid = 0.5 * (lo + hi);
+			if self.quantise(mid, false) > budget { lo = mid } else { hi = mid }
+		}
+		self.quantise(hi, true);
+	}
+
+	// mp3 style power law quantiser, returns a rough bit count. with
+	// `write` set the coefficients are replaced by their reconstruction
+	fn quantise( &mut self, offset_db: f32, write: bool ) -> f32 {
+		let gain = 10f32.powf(offset_db / 10.0);
+		let mut bits = 0.0;
+
+		for ((lo, hi), thr) in self.bands.iter().zip(self.thr.iter()) {
+			let step = (12.0 * thr * gain).sqrt();
+			let scale = step.powf(-0.75);
+			let mut band_bits = 0.0;
+			let mut used = false;
+
+			for (c, mag) in self.coeffs[*lo..*hi].iter_mut().zip(self.mags[*lo..*hi].iter()) {
+				let q = (mag * scale + 0.4054).floor();
+				if q > 0.0 {
+					used = true;
+					band_bits += 2.0 * (q + 1.0).log2() + 1.0;
+				} else {
+					band_bits += 0.5;
+				}
+				if write {
+					*c = q.powf(4.0 / 3.0) * step * c.signum();
+				}
+			}
+
+			if used { bits += band_bits + CODEC_BAND_BITS }
+		}
+		bits
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn transform_alone_gives_the_input_back() {
+		let mut codec = CodecEffect::new(Weak::new());
+		codec.bypass = true;
+
+		let mut noise = Noise::default();
+		let input: Vec<f32> = (0..20 * CODEC_HOP).map(|_| noise.white() * 0.5).collect();
+		let mut output = vec![0.0; input.len()];
+		// odd block sizes, so frames land mid block
+		for (ins, outs) in input.chunks(300).zip(output.chunks_mut(300)) {
+			codec.process(0, ins, outs);
+		}
+
+		let latency = codec.get_latency();
+		assert_eq!(latency, 2 * CODEC_HOP);
+		assert!(output[..latency].iter().all(|samp| samp.abs() < 1e-4));
+		for (out, samp) in output[latency..].iter().zip(input.iter()) {
+			assert!((out - samp).abs() < 1e-4, "{} came back as {}", samp, out);
+		}
+	}
+}
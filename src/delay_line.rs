@@ -0,0 +1,58 @@
+// ring buffer that can be read between samples. reads go through a four
+// point hermite, so a modulated read neither zippers nor dulls the top
+// end the way a linear one does
+#[derive(Clone, Default)]
+pub struct DelayLine {
+	buf: Vec<f32>,
+	mask: usize,
+	write: usize,
+	max_delay: usize,
+}
+
+impl DelayLine {
+	pub fn new( max_delay: usize ) -> Self {
+		let len = (max_delay + 4).next_power_of_two();
+		DelayLine {
+			buf: vec![0.0; len],
+			mask: len - 1,
+			write: 0,
+			max_delay,
+		}
+	}
+
+	pub fn max_delay( &self ) -> usize {
+		self.max_delay
+	}
+
+	#[inline]
+	pub fn push( &mut self, samp: f32 ) {
+		self.buf[self.write] = samp;
+		self.write = (self.write + 1) & self.mask;
+	}
+
+	// `delay` samples back from the newest one. the interpolator needs a
+	// sample either side, so it is held between 1 and max_delay
+	#[inline]
+	pub fn read( &self, delay: f32 ) -> f32 {
+		let delay = delay.clamp(1.0, self.max_delay as f32);
+		let i = delay as usize;
+		let t = delay - i as f32;
+		let at = |d: usize| self.buf[(self.write + self.mask - d) & self.mask];
+		// reading further back runs backwards in time, so the points go in reverse
+		hermite(t, at(i - 1), at(i), at(i + 1), at(i + 2))
+	}
+
+	pub fn clear( &mut self ) {
+		self.buf.iter_mut().for_each(|samp| *samp = 0.0);
+	}
+}
+
+
+// cubic hermite through four points, t between the middle two
+#[inline]
+pub fn hermite( t: f32, y0: f32, y1: f32, y2: f32, y3: f32 ) -> f32 {
+	let c1 = 0.5 * (y2 - y0);
+	let c2 = y0 - 2.5 * y1 + 2.0 * y2 - 0.5 * y3;
+	let c3 = 0.5 * (y3 - y0) + 1.5 * (y1 - y2);
+	((c3 * t + c2) * t + c1) * t + y1
+}
@@ -0,0 +1,804 @@
+use std::f32::consts;
+use std::sync::{Weak, atomic::Ordering};
+
+use crate::AndrewParams;
+use crate::andrew_effect::{AndrewEffect, SIDECHAIN_RESERVE, amp_to_db, db_to_gain};
+use crate::biquad::{BiQuadraticFilter, FilterKind::{self, *}};
+
+// window of the rms detector
+const RMS_TIME: f32 = 0.01;
+
+
+// one pole coefficient that gets 1 - 1/e of the way there in `seconds`
+#[inline]
+pub fn time_coeff( seconds: f32, sample_rate: f32 ) -> f32 {
+	if seconds <= 0.0 { 0.0 } else { (-1.0 / (seconds * sample_rate)).exp() }
+}
+
+
+// attack while the input is above the state, release while it is below
+#[derive(Clone, Copy, Default)]
+pub struct Envelope {
+	attack: f32,
+	release: f32,
+	value: f32,
+}
+
+impl Envelope {
+	pub fn set_times( &mut self, attack: f32, release: f32, sample_rate: f32 ) {
+		self.attack = time_coeff(attack, sample_rate);
+		self.release = time_coeff(release, sample_rate);
+	}
+
+	#[inline]
+	pub fn tick( &mut self, x: f32 ) -> f32 {
+		let coeff = if x > self.value { self.attack } else { self.release };
+		self.value = coeff * self.value + (1.0 - coeff) * x;
+		self.value
+	}
+}
+
+
+// level of the signal in db, from the peak or a short rms
+#[derive(Clone, Copy, Default)]
+pub struct Detector {
+	rms: bool,
+	coeff: f32,
+	mean_sq: f32,
+}
+
+impl Detector {
+	pub fn set( &mut self, rms: bool, sample_rate: f32 ) {
+		self.rms = rms;
+		self.coeff = time_coeff(RMS_TIME, sample_rate);
+	}
+
+	#[inline]
+	pub fn tick( &mut self, x: f32 ) -> f32 {
+		if self.rms {
+			self.mean_sq = self.coeff * self.mean_sq + (1.0 - self.coeff) * x * x;
+			amp_to_db(self.mean_sq.sqrt())
+		} else {
+			amp_to_db(x)
+		}
+	}
+}
+
+
+// gain change in db for a level in db, with a knee `knee` db wide
+// centred on the threshold. `ratio` below one expands instead
+#[inline]
+pub fn compress_db( level: f32, threshold: f32, ratio: f32, knee: f32 ) -> f32 {
+	let over = level - threshold;
+	let slope = 1.0 / ratio - 1.0;
+	if 2.0 * over < -knee {
+		0.0
+	} else if 2.0 * over.abs() <= knee && knee > 0.0 {
+		slope * (over + knee * 0.5).powi(2) / (2.0 * knee)
+	} else {
+		slope * over
+	}
+}
+
+
+
+
+// feed forward compressor. the detector listens to the input or the
+// sidechain pair, through a high pass so the low end doesn't pump it
+pub struct CompEffect {
+	detectors: [Detector; 2],
+	sc_filter: [BiQuadraticFilter; 2],
+	// gain reduction in db, smoothed by attack and release
+	reduction: [Envelope; 2],
+	sc_bufs: [Vec<f32>; 2],
+
+	threshold: f32,
+	ratio: f32,
+	knee: f32,
+	makeup: f32,
+	link: bool,
+	external: bool,
+
+	params: Weak<AndrewParams>,
+}
+
+impl AndrewEffect for CompEffect {
+	fn process_stereo(&mut self, in_bufs: [&[f32]; 2], out_bufs: [&mut [f32]; 2]) {
+		let [out_l, out_r] = out_bufs;
+		let use_sc = self.external && self.sc_bufs[0].len() >= in_bufs[0].len();
+
+		for i in 0..in_bufs[0].len() {
+			let mut levels = [0.0; 2];
+			for (chan, level) in levels.iter_mut().enumerate() {
+				let key = if use_sc { self.sc_bufs[chan][i] } else { in_bufs[chan][i] };
+				*level = self.detectors[chan].tick(self.sc_filter[chan].filter(key));
+			}
+			if self.link {
+				let loudest = levels[0].max(levels[1]);
+				levels = [loudest; 2];
+			}
+
+			let mut gains = [0.0; 2];
+			for (chan, gain) in gains.iter_mut().enumerate() {
+				// reduction is kept positive so attack is the envelope rising
+				let target = -compress_db(levels[chan], self.threshold, self.ratio, self.knee);
+				*gain = db_to_gain(self.makeup - self.reduction[chan].tick(target));
+			}
+
+			out_l[i] = in_bufs[0][i] * gains[0];
+			out_r[i] = in_bufs[1][i] * gains[1];
+		}
+	}
+
+	fn sidechain(&mut self, sc_bufs: [&[f32]; 2]) {
+		for (buf, sc) in self.sc_bufs.iter_mut().zip(sc_bufs.iter()) {
+			buf.clear();
+			buf.extend_from_slice(sc);
+		}
+	}
+
+	fn update_params(&mut self) {
+		if let Some(params) = self.params.upgrade() {
+			let sample_rate = params.sample_rate.get();
+			self.threshold = params.comp_threshold.get();
+			self.ratio = params.comp_ratio.get();
+			self.knee = params.comp_knee.get();
+			self.makeup = params.comp_makeup.get();
+			self.link = params.comp_link.load(Ordering::Relaxed);
+			self.external = params.comp_external.load(Ordering::Relaxed);
+
+			let rms = params.comp_rms.load(Ordering::Relaxed);
+			let attack = params.comp_attack.get() / 1000.0;
+			let release = params.comp_release.get() / 1000.0;
+			let hpf = params.comp_sc_hpf.get();
+			for chan in 0..2 {
+				self.detectors[chan].set(rms, sample_rate);
+				self.reduction[chan].set_times(attack, release, sample_rate);
+				self.sc_filter[chan].recfg(HIGHPASS, hpf, sample_rate, consts::FRAC_1_SQRT_2, 0.0);
+			}
+		}
+	}
+
+	fn get_latency(&self) -> usize {0}
+}
+
+impl CompEffect {
+	pub fn new( params: Weak<AndrewParams> ) -> Self {
+		let filter = BiQuadraticFilter::new(HIGHPASS, 20.0, 44100.0, consts::FRAC_1_SQRT_2, 0.0);
+		CompEffect {
+			detectors: [Detector::default(); 2],
+			sc_filter: [filter.clone(), filter],
+			reduction: [Envelope::default(); 2],
+			sc_bufs: [Vec::with_capacity(SIDECHAIN_RESERVE), Vec::with_capacity(SIDECHAIN_RESERVE)],
+			threshold: 0.0,
+			ratio: 1.0,
+			knee: 0.0,
+			makeup: 0.0,
+			link: true,
+			external: false,
+			params,
+		}
+	}
+}
+
+
+
+
+// downward expander that becomes a gate as the ratio goes up. it opens
+// above the threshold and only closes once the key has fallen
+// `hysteresis` db under it and the hold time has run out
+pub struct GateEffect {
+	key_hpf: [BiQuadraticFilter; 2],
+	key_lpf: [BiQuadraticFilter; 2],
+	// peak of the key, so the level doesn't drop out at zero crossings
+	key_env: Envelope,
+	// gain in db, rising at the attack and falling at the release
+	gain: Envelope,
+	open: bool,
+	hold_left: usize,
+
+	threshold: f32,
+	range: f32,
+	ratio: f32,
+	hysteresis: f32,
+	hold: usize,
+
+	params: Weak<AndrewParams>,
+}
+
+impl AndrewEffect for GateEffect {
+	fn process_stereo(&mut self, in_bufs: [&[f32]; 2], out_bufs: [&mut [f32]; 2]) {
+		let [out_l, out_r] = out_bufs;
+
+		for i in 0..in_bufs[0].len() {
+			let mut key = 0f32;
+			for (chan, buf) in in_bufs.iter().enumerate() {
+				let x = self.key_lpf[chan].filter(self.key_hpf[chan].filter(buf[i]));
+				key = key.max(x.abs());
+			}
+			let level = amp_to_db(self.key_env.tick(key));
+
+			if level > self.threshold {
+				self.open = true;
+				self.hold_left = self.hold;
+			} else if level > self.threshold - self.hysteresis {
+				// in the hysteresis band the gate stays as it is
+				if self.open { self.hold_left = self.hold }
+			} else if self.hold_left > 0 {
+				self.hold_left -= 1;
+			} else {
+				self.open = false;
+			}
+
+			let target = if self.open {
+				0.0
+			} else {
+				((level - self.threshold) * (self.ratio - 1.0)).max(-self.range)
+			};
+			let gain = db_to_gain(self.gain.tick(target));
+
+			out_l[i] = in_bufs[0][i] * gain;
+			out_r[i] = in_bufs[1][i] * gain;
+		}
+	}
+
+	fn update_params(&mut self) {
+		if let Some(params) = self.params.upgrade() {
+			let sample_rate = params.sample_rate.get();
+			self.threshold = params.gate_threshold.get();
+			self.range = params.gate_range.get();
+			self.ratio = params.gate_ratio.get();
+			self.hysteresis = params.gate_hysteresis.get();
+			self.hold = (params.gate_hold.get() / 1000.0 * sample_rate) as usize;
+
+			self.gain.set_times(params.gate_attack.get() / 1000.0, params.gate_release.get() / 1000.0, sample_rate);
+			self.key_env.set_times(0.0, 0.01, sample_rate);
+
+			let (lo, hi) = (params.gate_key_lo.get(), params.gate_key_hi.get().min(0.45 * sample_rate));
+			for chan in 0..2 {
+				self.key_hpf[chan].recfg(HIGHPASS, lo, sample_rate, consts::FRAC_1_SQRT_2, 0.0);
+				self.key_lpf[chan].recfg(LOWPASS, hi, sample_rate, consts::FRAC_1_SQRT_2, 0.0);
+			}
+		}
+	}
+
+	fn get_latency(&self) -> usize {0}
+}
+
+impl GateEffect {
+	pub fn new( params: Weak<AndrewParams> ) -> Self {
+		let hpf = BiQuadraticFilter::new(HIGHPASS, 20.0, 44100.0, consts::FRAC_1_SQRT_2, 0.0);
+		let lpf = BiQuadraticFilter::new(LOWPASS, 18000.0, 44100.0, consts::FRAC_1_SQRT_2, 0.0);
+		GateEffect {
+			key_hpf: [hpf.clone(), hpf],
+			key_lpf: [lpf.clone(), lpf],
+			key_env: Envelope::default(),
+			gain: Envelope::default(),
+			open: true,
+			hold_left: 0,
+			threshold: -80.0,
+			range: 0.0,
+			ratio: 1.0,
+			hysteresis: 0.0,
+			hold: 0,
+			params,
+		}
+	}
+}
+
+
+
+
+// passes anything under half the ceiling untouched, then bends
+// smoothly into the ceiling
+#[inline]
+pub fn soft_clip( x: f32, ceiling: f32 ) -> f32 {
+	let knee = 0.5 * ceiling;
+	if x.abs() <= knee {
+		x
+	} else {
+		(knee + knee * ((x.abs() - knee) / knee).tanh()).copysign(x)
+	}
+}
+
+
+// followers behind the transient shaper, in seconds
+const TRANS_FAST: f32 = 0.0005;
+const TRANS_SLOW: f32 = 0.025;
+const TRANS_RELEASE: f32 = 0.05;
+const TRANS_LONG_RELEASE: f32 = 0.5;
+
+// envelope difference in db that counts as a full attack or sustain
+// at a sensitivity of one
+const TRANS_RANGE_DB: f32 = 6.0;
+
+
+// boosts or cuts attacks and sustain independently. the attack is where a
+// fast follower runs ahead of a slow one, the sustain is where a slow
+// release hangs on above a fast one. both channels share one gain. no
+// state depends on the block size, so renders are repeatable
+pub struct TransientEffect {
+	fast: Envelope,
+	slow: Envelope,
+	short_tail: Envelope,
+	long_tail: Envelope,
+
+	attack: f32,
+	sustain: f32,
+	sensitivity: f32,
+	ceiling: f32,
+
+	params: Weak<AndrewParams>,
+}
+
+impl AndrewEffect for TransientEffect {
+	fn process_stereo(&mut self, in_bufs: [&[f32]; 2], out_bufs: [&mut [f32]; 2]) {
+		let [out_l, out_r] = out_bufs;
+		let scale = self.sensitivity / TRANS_RANGE_DB;
+
+		for i in 0..in_bufs[0].len() {
+			let key = in_bufs[0][i].abs().max(in_bufs[1][i].abs());
+
+			let attack = (amp_to_db(self.fast.tick(key)) - amp_to_db(self.slow.tick(key))) * scale;
+			let sustain = (amp_to_db(self.long_tail.tick(key)) - amp_to_db(self.short_tail.tick(key))) * scale;
+			let gain = db_to_gain(
+				self.attack * attack.clamp(0.0, 1.0)
+				+ self.sustain * sustain.clamp(0.0, 1.0)
+			);
+
+			out_l[i] = soft_clip(in_bufs[0][i] * gain, self.ceiling);
+			out_r[i] = soft_clip(in_bufs[1][i] * gain, self.ceiling);
+		}
+	}
+
+	fn update_params(&mut self) {
+		if let Some(params) = self.params.upgrade() {
+			let sample_rate = params.sample_rate.get();
+			self.fast.set_times(TRANS_FAST, TRANS_RELEASE, sample_rate);
+			self.slow.set_times(TRANS_SLOW, TRANS_RELEASE, sample_rate);
+			self.short_tail.set_times(TRANS_FAST, TRANS_RELEASE, sample_rate);
+			self.long_tail.set_times(TRANS_FAST, TRANS_LONG_RELEASE, sample_rate);
+
+			self.attack = params.trans_attack.get();
+			self.sustain = params.trans_sustain.get();
+			self.sensitivity = params.trans_sensitivity.get();
+			self.ceiling = db_to_gain(params.trans_clip.get());
+		}
+	}
+
+	fn get_latency(&self) -> usize {0}
+}
+
+impl TransientEffect {
+	pub fn new( params: Weak<AndrewParams> ) -> Self {
+		TransientEffect {
+			fast: Envelope::default(),
+			slow: Envelope::default(),
+			short_tail: Envelope::default(),
+			long_tail: Envelope::default(),
+			attack: 0.0,
+			sustain: 0.0,
+			sensitivity: 1.0,
+			ceiling: 1.0,
+			params,
+		}
+	}
+}
+
+
+
+pub const DYNEQ_BANDS: usize = 3;
+
+// samples between retunes of the band filters
+const DYNEQ_CONTROL_LEN: usize = 16;
+
+// the de-esser's fixed ratio and timing, in seconds
+const DEESS_RATIO: f32 = 4.0;
+const DEESS_ATTACK: f32 = 0.001;
+const DEESS_RELEASE: f32 = 0.06;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DynEqKind {
+	Peak,
+	LowShelf,
+	HighShelf,
+}
+
+impl DynEqKind {
+	pub const COUNT: u8 = 3;
+
+	pub fn from_index( i: u8 ) -> Self {
+		match i {
+			0 => DynEqKind::Peak,
+			1 => DynEqKind::LowShelf,
+			_ => DynEqKind::HighShelf,
+		}
+	}
+
+	pub fn name( &self ) -> &'static str {
+		match self {
+			DynEqKind::Peak => "peak",
+			DynEqKind::LowShelf => "low shelf",
+			DynEqKind::HighShelf => "high shelf",
+		}
+	}
+
+	// the filter that moves, and the one that picks out what the detector hears
+	fn kinds( &self ) -> (FilterKind, FilterKind) {
+		match self {
+			DynEqKind::Peak => (PEAK, BANDPASS),
+			DynEqKind::LowShelf => (LOWSHELF, LOWPASS),
+			DynEqKind::HighShelf => (HIGHSHELF, HIGHPASS),
+		}
+	}
+}
+
+
+// one band. its detector only hears the band's own part of the input,
+// and its gain comes down as that goes over the threshold
+#[derive(Clone)]
+struct DynEqBand {
+	filters: [BiQuadraticFilter; 2],
+	key_filters: [BiQuadraticFilter; 2],
+	detectors: [Detector; 2],
+	// gain reduction in db, linked across the pair
+	reduction: Envelope,
+
+	kind: DynEqKind,
+	freq: f32,
+	q: f32,
+	threshold: f32,
+	ratio: f32,
+}
+
+impl DynEqBand {
+	// the band's part of each side, and the louder side's level in db
+	#[inline]
+	fn detect( &mut self, samps: [f32; 2] ) -> ([f32; 2], f32) {
+		let keys = [self.key_filters[0].filter(samps[0]), self.key_filters[1].filter(samps[1])];
+		let level = self.detectors[0].tick(keys[0]).max(self.detectors[1].tick(keys[1]));
+		(keys, level)
+	}
+}
+
+
+// dynamic eq, each band a peak or a shelf that turns itself down by as
+// much as a compressor on its own band would. in de-ess mode it's one
+// band listening above `deess_freq`, and either a high shelf that ducks
+// just the top or the whole signal coming down. listen plays what the
+// chosen band's detector hears instead
+pub struct DynEqEffect {
+	bands: [DynEqBand; DYNEQ_BANDS],
+	deess: DynEqBand,
+	// samples until the next retune
+	countdown: usize,
+
+	deess_mode: bool,
+	deess_range: f32,
+	wideband: bool,
+	// 0 is off, otherwise the band soloed, any band in de-ess mode
+	listen: usize,
+	sample_rate: f32,
+
+	params: Weak<AndrewParams>,
+}
+
+impl AndrewEffect for DynEqEffect {
+	fn process_stereo(&mut self, in_bufs: [&[f32]; 2], out_bufs: [&mut [f32]; 2]) {
+		let [out_l, out_r] = out_bufs;
+
+		for i in 0..in_bufs[0].len() {
+			let retune = self.countdown == 0;
+			if retune { self.countdown = DYNEQ_CONTROL_LEN }
+			self.countdown -= 1;
+
+			let mut samps = [in_bufs[0][i], in_bufs[1][i]];
+			let mut heard = [0.0; 2];
+
+			if self.deess_mode {
+				let band = &mut self.deess;
+				let (keys, level) = band.detect(samps);
+				// reduction is kept positive so attack is the envelope rising
+				let target = (-compress_db(level, band.threshold, band.ratio, 0.0)).min(self.deess_range);
+				let reduction = band.reduction.tick(target);
+
+				if self.wideband {
+					let gain = db_to_gain(-reduction);
+					samps.iter_mut().for_each(|samp| *samp *= gain);
+				} else {
+					if retune {
+						for filter in band.filters.iter_mut() {
+							filter.recfg(HIGHSHELF, band.freq, self.sample_rate, consts::FRAC_1_SQRT_2, -reduction);
+						}
+					}
+					for (samp, filter) in samps.iter_mut().zip(band.filters.iter_mut()) {
+						*samp = filter.filter(*samp);
+					}
+				}
+				heard = keys;
+			} else {
+				for (b, band) in self.bands.iter_mut().enumerate() {
+					let (keys, level) = band.detect(samps);
+					let reduction = band.reduction.tick(-compress_db(level, band.threshold, band.ratio, 0.0));
+
+					if retune {
+						let (kind, _) = band.kind.kinds();
+						for filter in band.filters.iter_mut() {
+							filter.recfg(kind, band.freq, self.sample_rate, band.q, -reduction);
+						}
+					}
+					for (samp, filter) in samps.iter_mut().zip(band.filters.iter_mut()) {
+						*samp = filter.filter(*samp);
+					}
+					if self.listen == b + 1 { heard = keys }
+				}
+			}
+
+			if self.listen != 0 { samps = heard }
+			out_l[i] = samps[0];
+			out_r[i] = samps[1];
+		}
+	}
+
+	fn update_params(&mut self) {
+		if let Some(params) = self.params.upgrade() {
+			let sample_rate = params.sample_rate.get();
+			self.sample_rate = sample_rate;
+			self.deess_range = params.deess_range.get();
+			self.wideband = params.deess_wideband.load(Ordering::Relaxed);
+			self.listen = (params.dyneq_listen.load(Ordering::Relaxed) as usize).min(DYNEQ_BANDS);
+
+			// the filters coming in get their gain on the next sample
+			let deess_mode = params.dyneq_deess.load(Ordering::Relaxed);
+			if deess_mode != self.deess_mode {
+				self.deess_mode = deess_mode;
+				self.countdown = 0;
+			}
+
+			let nyquist = 0.45 * sample_rate;
+			for (b, band) in self.bands.iter_mut().enumerate() {
+				band.kind = DynEqKind::from_index(params.dyneq_kind[b].load(Ordering::Relaxed));
+				band.freq = params.dyneq_freq[b].get().min(nyquist);
+				band.q = params.dyneq_q[b].get();
+				band.threshold = params.dyneq_threshold[b].get();
+				band.ratio = params.dyneq_ratio[b].get();
+				band.reduction.set_times(params.dyneq_attack[b].get() / 1000.0, params.dyneq_release[b].get() / 1000.0, sample_rate);
+
+				band.detectors.iter_mut().for_each(|detector| detector.set(true, sample_rate));
+				let (_, key_kind) = band.kind.kinds();
+				for filter in band.key_filters.iter_mut() {
+					filter.recfg(key_kind, band.freq, sample_rate, band.q, 0.0);
+				}
+			}
+
+			let band = &mut self.deess;
+			band.freq = params.deess_freq.get().min(nyquist);
+			band.threshold = params.deess_threshold.get();
+			band.ratio = DEESS_RATIO;
+			band.reduction.set_times(DEESS_ATTACK, DEESS_RELEASE, sample_rate);
+			// peak, so short esses still get caught
+			band.detectors.iter_mut().for_each(|detector| detector.set(false, sample_rate));
+			for filter in band.key_filters.iter_mut() {
+				filter.recfg(HIGHPASS, band.freq, sample_rate, consts::FRAC_1_SQRT_2, 0.0);
+			}
+		}
+	}
+
+	fn get_latency(&self) -> usize {0}
+}
+
+impl DynEqEffect {
+	pub fn new( params: Weak<AndrewParams> ) -> Self {
+		let filter = BiQuadraticFilter::new(PEAK, 1000.0, 44100.0, 1.0, 0.0);
+		let band = DynEqBand {
+			filters: [filter.clone(), filter.clone()],
+			key_filters: [filter.clone(), filter],
+			detectors: [Detector::default(); 2],
+			reduction: Envelope::default(),
+			kind: DynEqKind::Peak,
+			freq: 1000.0,
+			q: 1.0,
+			threshold: 0.0,
+			ratio: 1.0,
+		};
+		DynEqEffect {
+			bands: [band.clone(), band.clone(), band.clone()],
+			deess: band,
+			countdown: 0,
+			deess_mode: false,
+			deess_range: 12.0,
+			wideband: false,
+			listen: 0,
+			sample_rate: 44100.0,
+			params,
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::Arc;
+
+	// a kick every quarter second, a decaying low sine with a click on top
+	fn drums() -> Vec<f32> {
+		(0..44100)
+			.map(|i| {
+				let t = (i % 11025) as f32 / 44100.0;
+				let body = (consts::TAU * 60.0 * t).sin() * (-t / 0.08).exp();
+				let click = if i % 11025 < 40 { 0.5 } else { 0.0 };
+				0.2 * (body + click)
+			})
+			.collect()
+	}
+
+	fn render( attack: f32, sustain: f32, block: usize ) -> Vec<f32> {
+		let params = Arc::new(AndrewParams::default());
+		params.trans_attack.set(attack);
+		params.trans_sustain.set(sustain);
+		params.trans_clip.set(0.0);
+		let mut shaper = TransientEffect::new(Arc::downgrade(&params));
+		shaper.update_params();
+
+		let input = drums();
+		let (mut left, mut right) = (vec![0.0; input.len()], vec![0.0; input.len()]);
+		for ((ins, l), r) in input.chunks(block).zip(left.chunks_mut(block)).zip(right.chunks_mut(block)) {
+			shaper.process_stereo([ins, ins], [l, r]);
+		}
+		assert_eq!(left, right);
+		left
+	}
+
+	fn rms( buf: &[f32] ) -> f32 {
+		(buf.iter().map(|x| x * x).sum::<f32>() / buf.len() as f32).sqrt()
+	}
+
+	// the first few ms of every hit, and the tail from 100 to 200 ms on
+	fn hits( buf: &[f32] ) -> f32 {
+		rms(&buf.chunks(11025).flat_map(|hit| hit[..200].iter().copied()).collect::<Vec<_>>())
+	}
+	fn tails( buf: &[f32] ) -> f32 {
+		rms(&buf.chunks(11025).flat_map(|hit| hit[4410..8820].iter().copied()).collect::<Vec<_>>())
+	}
+
+	#[test]
+	fn transient_renders_the_same_at_any_block_size() {
+		assert_eq!(render(12.0, -12.0, 64), render(12.0, -12.0, 1000));
+		assert_eq!(render(-6.0, 6.0, 1), render(-6.0, 6.0, 4096));
+	}
+
+	#[test]
+	fn transient_gains_move_the_right_way() {
+		let dry = render(0.0, 0.0, 512);
+		assert_eq!(dry, drums());
+
+		let punchy = render(12.0, 0.0, 512);
+		assert!(hits(&punchy) > 1.5 * hits(&dry));
+		// the body's own cycles read as small attacks, so the tail comes up a
+		// little too, but nowhere near as much as the hit
+		assert!(hits(&punchy) / hits(&dry) > 2.0 * tails(&punchy) / tails(&dry));
+
+		let soft = render(-12.0, 0.0, 512);
+		assert!(hits(&soft) < 0.7 * hits(&dry));
+
+		let roomy = render(0.0, 12.0, 512);
+		assert!(tails(&roomy) > 1.5 * tails(&dry));
+		assert!(tails(&roomy) / tails(&dry) > 2.0 * hits(&roomy) / hits(&dry));
+		assert!(render(0.0, -12.0, 512).iter().zip(dry.iter()).all(|(wet, dry)| wet.abs() <= dry.abs() + 1e-6));
+	}
+
+	fn sine( amp: f32, freq: f32, len: usize ) -> Vec<f32> {
+		(0..len).map(|i| amp * (consts::TAU * freq * i as f32 / 44100.0).sin()).collect()
+	}
+
+	// change in level from the input to the output over `range`, in db
+	fn gain_db( input: &[f32], output: &[f32], range: std::ops::Range<usize> ) -> f32 {
+		amp_to_db(rms(&output[range.clone()]) / rms(&input[range]))
+	}
+
+	fn comp( threshold: f32, knee: f32, makeup: f32, input: &[f32] ) -> Vec<f32> {
+		let params = Arc::new(AndrewParams::default());
+		params.comp_threshold.set(threshold);
+		params.comp_ratio.set(4.0);
+		params.comp_knee.set(knee);
+		params.comp_makeup.set(makeup);
+		params.comp_rms.store(true, Ordering::Relaxed);
+		let mut comp = CompEffect::new(Arc::downgrade(&params));
+		comp.update_params();
+
+		let (mut left, mut right) = (vec![0.0; input.len()], vec![0.0; input.len()]);
+		comp.process_stereo([input, input], [&mut left, &mut right]);
+		left
+	}
+
+	#[test]
+	fn comp_reduces_by_the_ratio_over_the_threshold() {
+		// a sine at 0.5 reads -9 db rms, 21 db over a -30 threshold. at 4:1
+		// that comes out 5.25 db over it, 15.75 db down
+		let input = sine(0.5, 1000.0, 22050);
+		let settled = 11025..22050;
+		let hard = comp(-30.0, 0.0, 0.0, &input);
+		assert!((gain_db(&input, &hard, settled.clone()) + 15.75).abs() < 0.3, "{} db", gain_db(&input, &hard, settled.clone()));
+		let lifted = comp(-30.0, 0.0, 6.0, &input);
+		assert!((gain_db(&input, &lifted, settled.clone()) + 9.75).abs() < 0.3);
+
+		// right on the threshold, a 12 db knee has already taken 1.125 db off
+		let soft = comp(-9.03, 12.0, 0.0, &input);
+		assert!((gain_db(&input, &soft, settled.clone()) + 1.125).abs() < 0.2, "{} db", gain_db(&input, &soft, settled.clone()));
+
+		// and well under it nothing moves
+		let quiet = sine(0.01, 1000.0, 22050);
+		assert!(gain_db(&quiet, &comp(-30.0, 0.0, 0.0, &quiet), settled).abs() < 0.01);
+	}
+
+	fn gate( hysteresis: f32, input: &[f32] ) -> Vec<f32> {
+		let params = Arc::new(AndrewParams::default());
+		params.gate_threshold.set(-20.0);
+		params.gate_hysteresis.set(hysteresis);
+		params.gate_hold.set(50.0);
+		params.gate_range.set(60.0);
+		params.gate_attack.set(0.0);
+		params.gate_release.set(0.0);
+		let mut gate = GateEffect::new(Arc::downgrade(&params));
+		gate.update_params();
+
+		let (mut left, mut right) = (vec![0.0; input.len()], vec![0.0; input.len()]);
+		gate.process_stereo([input, input], [&mut left, &mut right]);
+		left
+	}
+
+	#[test]
+	fn gate_holds_open_through_the_hysteresis_and_the_hold() {
+		// loud, then into the band between -30 and -20, then under it, then back
+		let steps = [(0.0, 4410), (-25.0, 13230), (-40.0, 13230), (-25.0, 8820)];
+		let input: Vec<f32> = steps.iter().flat_map(|(db, len)| sine(db_to_gain(*db), 1000.0, *len)).collect();
+		let (band, under, back) = (4410, 17640, 30870);
+
+		let output = gate(10.0, &input);
+		assert!(gain_db(&input, &output, band + 2205..under).abs() < 0.01, "shut in the band");
+		assert!(gain_db(&input, &output, under..under + 1764).abs() < 0.01, "shut inside the hold");
+		assert!(gain_db(&input, &output, under + 4410..back) < -59.0, "open under the band");
+		assert!(gain_db(&input, &output, back..input.len()) < -59.0, "opened in the band");
+
+		// without the band it shuts once the hold is over
+		let output = gate(0.0, &input);
+		assert!(gain_db(&input, &output, band..band + 1764).abs() < 0.01);
+		assert!(gain_db(&input, &output, band + 4410..under) < -59.0);
+	}
+
+	// one peak band at 1k, -20 db threshold and 4:1, the others left flat
+	fn dyneq( input: &[f32] ) -> Vec<f32> {
+		let params = Arc::new(AndrewParams::default());
+		params.dyneq_kind[0].store(DynEqKind::Peak as u8, Ordering::Relaxed);
+		params.dyneq_freq[0].set(1000.0);
+		params.dyneq_q[0].set(1.0);
+		params.dyneq_threshold[0].set(-20.0);
+		params.dyneq_ratio[0].set(4.0);
+		for band in 1..DYNEQ_BANDS {
+			params.dyneq_ratio[band].set(1.0);
+		}
+		let mut eq = DynEqEffect::new(Arc::downgrade(&params));
+		eq.update_params();
+
+		let (mut left, mut right) = (vec![0.0; input.len()], vec![0.0; input.len()]);
+		eq.process_stereo([input, input], [&mut left, &mut right]);
+		left
+	}
+
+	#[test]
+	fn dyneq_only_turns_down_what_goes_over_in_its_band() {
+		let settled = 11025..22050;
+		// -9 db rms in the band is 11 db over, so it comes down by three quarters of that
+		let loud = sine(0.5, 1000.0, 22050);
+		let gain = gain_db(&loud, &dyneq(&loud), settled.clone());
+		assert!((gain + 8.23).abs() < 0.3, "{} db at 1k", gain);
+
+		// as loud but a few octaves out of the band, or in it and quiet
+		for (amp, freq) in [(0.5, 100.0), (0.5, 8000.0), (0.01, 1000.0)] {
+			let input = sine(amp, freq, 22050);
+			let gain = gain_db(&input, &dyneq(&input), settled.clone());
+			assert!(gain.abs() < 0.05, "{} db at {} hz and {}", gain, freq, amp);
+		}
+	}
+}
@@ -0,0 +1,205 @@
+use std::f32::consts::PI;
+use std::ops::{Add, Mul, Sub};
+
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub struct Complex {
+	pub re: f32,
+	pub im: f32,
+}
+
+impl Complex {
+	pub fn new( re: f32, im: f32 ) -> Self {
+		Complex { re, im }
+	}
+
+	pub fn from_polar( mag: f32, phase: f32 ) -> Self {
+		Complex { re: mag * phase.cos(), im: mag * phase.sin() }
+	}
+
+	pub fn norm( &self ) -> f32 {
+		self.re.hypot(self.im)
+	}
+
+	pub fn arg( &self ) -> f32 {
+		self.im.atan2(self.re)
+	}
+
+	pub fn conj( &self ) -> Self {
+		Complex { re: self.re, im: -self.im }
+	}
+
+	pub fn scale( &self, k: f32 ) -> Self {
+		Complex { re: self.re * k, im: self.im * k }
+	}
+}
+
+impl Add for Complex {
+	type Output = Complex;
+	fn add( self, rhs: Complex ) -> Complex {
+		Complex { re: self.re + rhs.re, im: self.im + rhs.im }
+	}
+}
+
+impl Sub for Complex {
+	type Output = Complex;
+	fn sub( self, rhs: Complex ) -> Complex {
+		Complex { re: self.re - rhs.re, im: self.im - rhs.im }
+	}
+}
+
+impl Mul for Complex {
+	type Output = Complex;
+	fn mul( self, rhs: Complex ) -> Complex {
+		Complex {
+			re: self.re * rhs.re - self.im * rhs.im,
+			im: self.re * rhs.im + self.im * rhs.re,
+		}
+	}
+}
+
+
+// in place radix-2 fft, the size has to be a power of two.
+// twiddles and the bit reversal table are worked out up front
+// so transforming allocates nothing
+#[derive(Clone)]
+pub struct Fft {
+	len: usize,
+	twiddles: Vec<Complex>,
+	bit_rev: Vec<usize>,
+}
+
+impl Fft {
+	pub fn new( len: usize ) -> Self {
+		assert!(len.is_power_of_two(), "fft size must be a power of two");
+		let bits = len.trailing_zeros();
+		Fft {
+			len,
+			twiddles: (0..len / 2)
+				.map(|i| Complex::from_polar(1.0, -2.0 * PI * i as f32 / len as f32))
+				.collect(),
+			bit_rev: (0..len)
+				.map(|i| if bits == 0 { 0 } else { i.reverse_bits() >> (usize::BITS - bits) })
+				.collect(),
+		}
+	}
+
+	pub fn forward( &self, buf: &mut [Complex] ) {
+		self.transform(buf);
+	}
+
+	// unscaled, forward followed by inverse multiplies by len
+	pub fn inverse( &self, buf: &mut [Complex] ) {
+		buf.iter_mut().for_each(|elm| *elm = elm.conj());
+		self.transform(buf);
+		buf.iter_mut().for_each(|elm| *elm = elm.conj());
+	}
+
+	fn transform( &self, buf: &mut [Complex] ) {
+		let n = self.len;
+		for i in 0..n {
+			let j = self.bit_rev[i];
+			if j > i { buf.swap(i, j) }
+		}
+
+		let mut size = 2;
+		while size <= n {
+			let half = size / 2;
+			let stride = n / size;
+			for start in (0..n).step_by(size) {
+				for k in 0..half {
+					let t = buf[start + k + half] * self.twiddles[k * stride];
+					let u = buf[start + k];
+					buf[start + k] = u + t;
+					buf[start + k + half] = u - t;
+				}
+			}
+			size *= 2;
+		}
+	}
+}
+
+
+// mdct with `len` coefficients over frames of 2 * len samples, done as a
+// dct-iv through a len / 2 point fft. coefficients are scaled so that
+// windowing with `Mdct::window` on both sides and overlap adding by len
+// gives the input back
+#[derive(Clone)]
+pub struct Mdct {
+	len: usize,
+	fft: Fft,
+	twiddles: Vec<Complex>,
+	post_twiddles: Vec<Complex>,
+	fold: Vec<f32>,
+	scratch: Vec<Complex>,
+}
+
+impl Mdct {
+	pub fn new( len: usize ) -> Self {
+		assert!(len >= 4 && len.is_power_of_two(), "mdct size must be a power of two");
+		Mdct {
+			len,
+			fft: Fft::new(len / 2),
+			twiddles: (0..len / 2)
+				.map(|j| Complex::from_polar(1.0, -PI * (4 * j + 1) as f32 / (4 * len) as f32))
+				.collect(),
+			post_twiddles: (0..len / 2)
+				.map(|k| Complex::from_polar((2.0 / len as f32).sqrt(), -PI * k as f32 / len as f32))
+				.collect(),
+			fold: vec![0.0; len],
+			scratch: vec![Complex::default(); len / 2],
+		}
+	}
+
+	// sine window over the 2 * len frame
+	pub fn window( len: usize ) -> Vec<f32> {
+		(0..2 * len)
+			.map(|i| (PI * (i as f32 + 0.5) / (2 * len) as f32).sin())
+			.collect()
+	}
+
+	// frame is 2 * len samples, already windowed. coeffs gets len values
+	pub fn forward( &mut self, frame: &[f32], coeffs: &mut [f32] ) {
+		let half = self.len / 2;
+		for i in 0..half {
+			self.fold[i] = -frame[3 * half + i] - frame[3 * half - 1 - i];
+		}
+		for i in half..self.len {
+			self.fold[i] = frame[i - half] - frame[3 * half - 1 - i];
+		}
+
+		let fold = std::mem::take(&mut self.fold);
+		self.dct4(&fold, coeffs);
+		self.fold = fold;
+	}
+
+	// coeffs holds len values, frame gets 2 * len samples to be windowed and overlap added
+	pub fn inverse( &mut self, coeffs: &[f32], frame: &mut [f32] ) {
+		let half = self.len / 2;
+		let mut fold = std::mem::take(&mut self.fold);
+		self.dct4(coeffs, &mut fold);
+
+		for (n, out) in frame.iter_mut().enumerate().take(2 * self.len) {
+			*out = if n < half {
+				fold[half + n]
+			} else if n < 3 * half {
+				-fold[3 * half - 1 - n]
+			} else {
+				-fold[n - 3 * half]
+			};
+		}
+		self.fold = fold;
+	}
+
+	fn dct4( &mut self, input: &[f32], output: &mut [f32] ) {
+		let n = self.len;
+		for j in 0..n / 2 {
+			self.scratch[j] = Complex::new(input[2 * j], input[n - 1 - 2 * j]) * self.twiddles[j];
+		}
+		self.fft.forward(&mut self.scratch);
+		for k in 0..n / 2 {
+			let y = self.scratch[k] * self.post_twiddles[k];
+			output[2 * k] = y.re;
+			output[n - 1 - 2 * k] = -y.im;
+		}
+	}
+}
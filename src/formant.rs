@@ -0,0 +1,210 @@
+use std::sync::{Weak, atomic::Ordering};
+
+use crate::AndrewParams;
+use crate::andrew_effect::{AndrewEffect, amp_to_db, db_to_gain};
+use crate::biquad::{BiQuadraticFilter, FilterKind::*};
+use crate::dynamics::Envelope;
+use crate::modulator::Lfo;
+
+const FORMANTS: usize = 5;
+const VOWELS: usize = 5;
+
+// samples between retunes of the formants
+const FORMANT_CONTROL_LEN: usize = 16;
+
+// how fast the envelope drive follows the input, in seconds
+const FORMANT_ATTACK: f32 = 0.005;
+const FORMANT_RELEASE: f32 = 0.1;
+
+// the formants only pass a thin slice of a full signal, this brings it
+// back up to about where it went in
+const FORMANT_MAKEUP: f32 = 4.0;
+
+// range of input level the envelope drive sweeps over, in db down from full
+const FORMANT_ENV_RANGE: f32 = 60.0;
+
+// frequency, level in db and bandwidth of each formant, for a e i o u
+type VowelTable = [[(f32, f32, f32); FORMANTS]; VOWELS];
+
+const MALE: VowelTable = [
+	[(600.0, 0.0, 60.0), (1040.0, -7.0, 70.0), (2250.0, -9.0, 110.0), (2450.0, -9.0, 120.0), (2750.0, -20.0, 130.0)],
+	[(400.0, 0.0, 40.0), (1620.0, -12.0, 80.0), (2400.0, -9.0, 100.0), (2800.0, -12.0, 120.0), (3100.0, -18.0, 120.0)],
+	[(250.0, 0.0, 60.0), (1750.0, -30.0, 90.0), (2600.0, -16.0, 100.0), (3050.0, -22.0, 120.0), (3340.0, -28.0, 120.0)],
+	[(400.0, 0.0, 40.0), (750.0, -11.0, 80.0), (2400.0, -21.0, 100.0), (2600.0, -20.0, 120.0), (2900.0, -40.0, 120.0)],
+	[(350.0, 0.0, 40.0), (600.0, -20.0, 80.0), (2400.0, -32.0, 100.0), (2675.0, -28.0, 120.0), (2950.0, -36.0, 120.0)],
+];
+
+const FEMALE: VowelTable = [
+	[(800.0, 0.0, 80.0), (1150.0, -6.0, 90.0), (2900.0, -32.0, 120.0), (3900.0, -20.0, 130.0), (4950.0, -50.0, 140.0)],
+	[(350.0, 0.0, 60.0), (2000.0, -20.0, 100.0), (2800.0, -15.0, 120.0), (3600.0, -40.0, 150.0), (4950.0, -56.0, 200.0)],
+	[(270.0, 0.0, 60.0), (2140.0, -12.0, 90.0), (2950.0, -26.0, 100.0), (3900.0, -26.0, 120.0), (4950.0, -44.0, 120.0)],
+	[(450.0, 0.0, 70.0), (800.0, -11.0, 80.0), (2830.0, -22.0, 100.0), (3800.0, -22.0, 130.0), (4950.0, -50.0, 135.0)],
+	[(325.0, 0.0, 50.0), (700.0, -16.0, 60.0), (2700.0, -35.0, 170.0), (3800.0, -40.0, 180.0), (4950.0, -60.0, 200.0)],
+];
+
+// the vowel nearest a morph position, for display
+pub fn vowel_name( morph: f32 ) -> &'static str {
+	["a", "e", "i", "o", "u"][(morph.clamp(0.0, 1.0) * (VOWELS - 1) as f32).round() as usize]
+}
+
+
+// talk box. five bandpasses in parallel, tuned and levelled to a vowel's
+// formants. the morph runs through a e i o u, sliding the frequencies
+// in pitch and the bandwidths and levels straight between neighbours,
+// and can be pushed around by an lfo and by the level of the input
+pub struct FormantEffect {
+	filters: [[BiQuadraticFilter; FORMANTS]; 2],
+	gains: [f32; FORMANTS],
+	lfo: Lfo,
+	env: Envelope,
+	// samples until the next retune
+	countdown: usize,
+
+	morph: f32,
+	female: bool,
+	lfo_depth: f32,
+	env_amount: f32,
+	mix: f32,
+	sample_rate: f32,
+
+	params: Weak<AndrewParams>,
+}
+
+impl AndrewEffect for FormantEffect {
+	fn process_stereo(&mut self, in_bufs: [&[f32]; 2], out_bufs: [&mut [f32]; 2]) {
+		let [out_l, out_r] = out_bufs;
+
+		for i in 0..in_bufs[0].len() {
+			let level = self.env.tick(0.5 * (in_bufs[0][i] + in_bufs[1][i]).abs());
+			let sweep = self.lfo.tick();
+
+			if self.countdown == 0 {
+				self.countdown = FORMANT_CONTROL_LEN;
+				let loudness = ((amp_to_db(level) + FORMANT_ENV_RANGE) / FORMANT_ENV_RANGE).clamp(0.0, 1.0);
+				let morph = self.morph + 0.5 * self.lfo_depth * sweep + self.env_amount * loudness;
+				self.retune(morph.clamp(0.0, 1.0));
+			}
+			self.countdown -= 1;
+
+			let mut wet = [0.0; 2];
+			for (chan, wet) in wet.iter_mut().enumerate() {
+				let samp = in_bufs[chan][i];
+				*wet = self.filters[chan].iter_mut()
+					.zip(self.gains.iter())
+					.map(|(filter, gain)| filter.filter(samp) * gain)
+					.sum();
+			}
+
+			out_l[i] = in_bufs[0][i] * (1.0 - self.mix) + wet[0] * self.mix;
+			out_r[i] = in_bufs[1][i] * (1.0 - self.mix) + wet[1] * self.mix;
+		}
+	}
+
+	fn update_params(&mut self) {
+		if let Some(params) = self.params.upgrade() {
+			self.morph = params.formant_vowel.get();
+			self.female = params.formant_female.load(Ordering::Relaxed);
+			self.lfo_depth = params.formant_lfo_depth.get();
+			self.env_amount = params.formant_env.get();
+			self.mix = params.formant_mix.get();
+			self.sample_rate = params.sample_rate.get();
+
+			self.lfo.set_freq(params.formant_lfo_rate.get());
+			self.lfo.set_sample_rate(self.sample_rate);
+			self.env.set_times(FORMANT_ATTACK, FORMANT_RELEASE, self.sample_rate);
+			// retune on the next sample
+			self.countdown = 0;
+		}
+	}
+
+	fn get_latency(&self) -> usize {0}
+}
+
+impl FormantEffect {
+	pub fn new( params: Weak<AndrewParams> ) -> Self {
+		// retune sets them all up
+		let bank: [BiQuadraticFilter; FORMANTS] = Default::default();
+		let mut env = Envelope::default();
+		env.set_times(FORMANT_ATTACK, FORMANT_RELEASE, 44100.0);
+		let mut formant = FormantEffect {
+			filters: [bank.clone(), bank],
+			gains: [0.0; FORMANTS],
+			lfo: Lfo::default(),
+			env,
+			countdown: 0,
+			morph: 0.0,
+			female: false,
+			lfo_depth: 0.0,
+			env_amount: 0.0,
+			mix: 1.0,
+			sample_rate: 44100.0,
+			params,
+		};
+		formant.retune(0.0);
+		formant
+	}
+
+	// sets the formants somewhere between two neighbouring vowels
+	fn retune( &mut self, morph: f32 ) {
+		let table = if self.female { &FEMALE } else { &MALE };
+		let pos = morph * (VOWELS - 1) as f32;
+		let from = (pos as usize).min(VOWELS - 2);
+		let t = pos - from as f32;
+		let nyquist = 0.45 * self.sample_rate;
+
+		for k in 0..FORMANTS {
+			let (freq_a, db_a, bw_a) = table[from][k];
+			let (freq_b, db_b, bw_b) = table[from + 1][k];
+			let freq = (freq_a * (freq_b / freq_a).powf(t)).min(nyquist);
+			let bandwidth = bw_a + (bw_b - bw_a) * t;
+			self.gains[k] = db_to_gain(db_a + (db_b - db_a) * t) * FORMANT_MAKEUP;
+
+			for chan in self.filters.iter_mut() {
+				chan[k].recfg(BANDPASS, freq, self.sample_rate, freq / bandwidth, 0.0);
+			}
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn impulse_response( mut filter: BiQuadraticFilter ) -> Vec<f32> {
+		(0..64).map(|i| filter.filter(if i == 0 { 1.0 } else { 0.0 })).collect()
+	}
+
+	// the bank holds the formant at `freq` with `bandwidth`, at `db`
+	fn assert_formant( formant: &FormantEffect, k: usize, (freq, db, bandwidth): (f32, f32, f32) ) {
+		let expected = BiQuadraticFilter::new(BANDPASS, freq, 44100.0, freq / bandwidth, 0.0);
+		for (got, want) in impulse_response(formant.filters[0][k].clone()).iter().zip(impulse_response(expected).iter()) {
+			assert!((got - want).abs() < 1e-5, "formant {} off {} hz", k, freq);
+		}
+		assert!((formant.gains[k] - db_to_gain(db) * FORMANT_MAKEUP).abs() < 1e-5, "formant {} not at {} db", k, db);
+	}
+
+	#[test]
+	fn retune_lands_on_every_vowel() {
+		let mut formant = FormantEffect::new(Weak::new());
+		for (female, table) in [(false, &MALE), (true, &FEMALE)] {
+			formant.female = female;
+			for (vowel, formants) in table.iter().enumerate() {
+				formant.retune(vowel as f32 / (VOWELS - 1) as f32);
+				for (k, target) in formants.iter().enumerate() {
+					assert_formant(&formant, k, *target);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn retune_slides_in_pitch_between_vowels() {
+		let mut formant = FormantEffect::new(Weak::new());
+		// halfway from a to e
+		formant.retune(0.5 / (VOWELS - 1) as f32);
+		for (k, ((freq_a, db_a, bw_a), (freq_e, db_e, bw_e))) in MALE[0].iter().zip(MALE[1].iter()).enumerate() {
+			assert_formant(&formant, k, ((freq_a * freq_e).sqrt(), 0.5 * (db_a + db_e), 0.5 * (bw_a + bw_e)));
+		}
+	}
+}
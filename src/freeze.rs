@@ -0,0 +1,164 @@
+use std::f32::consts::{FRAC_PI_2, TAU};
+use std::sync::{Weak, atomic::Ordering};
+
+use crate::AndrewParams;
+use crate::dynamics::time_coeff;
+use crate::fft::Complex;
+use crate::noise::Noise;
+use crate::stft::{SpectralProcessor, StftConfig, StftEffect};
+
+// time constant of the magnitude averaging at full blur, in seconds
+const BLUR_MAX_TIME: f32 = 5.0;
+
+// how long the frozen spectrum takes to come in or go, in seconds
+const FREEZE_FADE_TIME: f32 = 0.05;
+
+pub type FreezeEffect = StftEffect<FreezeProcessor>;
+
+
+// holds a spectrum and smears it over time. blur averages the magnitudes
+// over frames, and freeze grabs them and plays them back forever with fresh
+// random phases every frame, crossfaded with the live signal by `mix`
+pub struct FreezeProcessor {
+	chans: [FreezeChannel; 2],
+	noise: Noise,
+	hop: usize,
+	random_gain: f32,
+
+	freeze: bool,
+	blur: f32,
+	mix: f32,
+	sample_rate: f32,
+
+	params: Weak<AndrewParams>,
+}
+
+#[derive(Default)]
+struct FreezeChannel {
+	blurred: Vec<f32>,
+	frozen: Vec<f32>,
+	// whether this freeze has been grabbed yet
+	captured: bool,
+	// how far in the frozen spectrum is, faded per frame
+	level: f32,
+}
+
+impl SpectralProcessor for FreezeProcessor {
+	fn process_frame(&mut self, chan_id: usize, bins: &mut [Complex]) {
+		let frame_rate = self.sample_rate / self.hop as f32;
+		let blur = time_coeff(BLUR_MAX_TIME * self.blur * self.blur, frame_rate);
+		let chan = &mut self.chans[chan_id];
+
+		if self.freeze && !chan.captured {
+			chan.frozen.copy_from_slice(&chan.blurred);
+			chan.captured = true;
+		}
+		chan.captured &= self.freeze;
+
+		let step = 1.0 / (FREEZE_FADE_TIME * frame_rate).max(1.0);
+		chan.level = if self.freeze { (chan.level + step).min(1.0) } else { (chan.level - step).max(0.0) };
+		// equal power, the two sides don't correlate
+		let angle = chan.level * self.mix * FRAC_PI_2;
+		let (frozen_gain, live_gain) = angle.sin_cos();
+
+		for (k, bin) in bins.iter_mut().enumerate() {
+			let mag = bin.norm();
+			let blurred = &mut chan.blurred[k];
+			*blurred = blur * *blurred + (1.0 - blur) * mag;
+
+			// tails that outlast the live signal get random phases, so
+			// they don't buzz at the frame rate
+			let mut out = if mag >= 0.5 * *blurred {
+				Complex::from_polar(*blurred * live_gain, bin.arg())
+			} else {
+				Complex::from_polar(*blurred * live_gain * self.random_gain, self.noise.uniform() * TAU)
+			};
+
+			if frozen_gain > 0.0 {
+				let mag = chan.frozen[k] * frozen_gain * self.random_gain;
+				out = out + Complex::from_polar(mag, self.noise.uniform() * TAU);
+			}
+			*bin = out;
+		}
+	}
+
+	fn configure(&mut self, config: StftConfig) {
+		self.hop = config.hop();
+		self.random_gain = config.random_phase_gain();
+		let bins = config.fft_len / 2 + 1;
+		for chan in self.chans.iter_mut() {
+			chan.blurred = vec![0.0; bins];
+			chan.frozen = vec![0.0; bins];
+			chan.captured = false;
+			chan.level = 0.0;
+		}
+	}
+
+	fn update_params(&mut self) {
+		if let Some(params) = self.params.upgrade() {
+			self.freeze = params.freeze.load(Ordering::Relaxed);
+			self.blur = params.freeze_blur.get();
+			self.mix = params.freeze_mix.get();
+			self.sample_rate = params.sample_rate.get();
+		}
+	}
+}
+
+impl FreezeProcessor {
+	pub fn new( params: Weak<AndrewParams> ) -> Self {
+		FreezeProcessor {
+			chans: Default::default(),
+			noise: Noise::default(),
+			hop: StftConfig::default().hop(),
+			random_gain: 1.0,
+			freeze: false,
+			blur: 0.0,
+			mix: 1.0,
+			sample_rate: 44100.0,
+			params,
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::Arc;
+	use crate::andrew_effect::AndrewEffect;
+
+	fn rms( buf: &[f32] ) -> f32 {
+		(buf.iter().map(|x| x * x).sum::<f32>() / buf.len() as f32).sqrt()
+	}
+
+	#[test]
+	fn freeze_holds_the_level_after_the_input_stops() {
+		let params = Arc::new(AndrewParams::default());
+		let mut freeze = FreezeEffect::new(FreezeProcessor::new(Arc::downgrade(&params)));
+		freeze.update_params();
+
+		let mut noise = Noise::new(7);
+		let input: Vec<f32> = (0..44100).map(|_| 0.25 * noise.white()).collect();
+		let mut output = vec![0.0; input.len()];
+		freeze.process(0, &input, &mut output);
+		let live = rms(&output[22050..]);
+		assert!((live / rms(&input) - 1.0).abs() < 0.05, "{} live against {}", live, rms(&input));
+
+		// grabbed on the next frame, and still there long after the input has gone
+		params.freeze.store(true, Ordering::Relaxed);
+		freeze.update_params();
+		let silence = vec![0.0; 88200];
+		let mut output = vec![0.0; silence.len()];
+		freeze.process(0, &silence, &mut output);
+		for at in [11025, 44100, 77175] {
+			let held = rms(&output[at..at + 11025]);
+			assert!((20.0 * (held / live).log10()).abs() < 1.0, "{} held against {} at {}", held, live, at);
+		}
+
+		// and let go of, it fades back out to the silence
+		params.freeze.store(false, Ordering::Relaxed);
+		freeze.update_params();
+		freeze.process(0, &silence, &mut output);
+		assert!(rms(&output[11025..]) < 1e-4);
+	}
+}
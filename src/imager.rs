@@ -0,0 +1,187 @@
+use std::sync::{Weak, atomic::Ordering};
+
+use crate::AndrewParams;
+use crate::andrew_effect::AndrewEffect;
+use crate::biquad::{BiQuadraticFilter, FilterKind::{self, *}};
+use crate::delay_line::DelayLine;
+
+// longest the haas delay goes, in seconds
+pub const HAAS_MAX_DELAY: f32 = 0.03;
+
+// butterworth, two in a row make a linkwitz-riley
+const BUTTERWORTH_Q: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+
+// fourth order linkwitz-riley split. the two halves add back up flat,
+// with the phase turned the same way as an allpass at the crossover
+#[derive(Clone)]
+struct Crossover {
+	low: [BiQuadraticFilter; 2],
+	high: [BiQuadraticFilter; 2],
+}
+
+impl Crossover {
+	fn new( freq: f32, sample_rate: f32 ) -> Self {
+		let stage = |kind: FilterKind| BiQuadraticFilter::new(kind, freq, sample_rate, BUTTERWORTH_Q, 0.0);
+		Crossover {
+			low: [stage(LOWPASS), stage(LOWPASS)],
+			high: [stage(HIGHPASS), stage(HIGHPASS)],
+		}
+	}
+
+	fn set_freq( &mut self, freq: f32 ) {
+		self.low.iter_mut().chain(self.high.iter_mut()).for_each(|filter| filter.update_center_freq(freq));
+	}
+
+	// low and high
+	#[inline]
+	fn split( &mut self, x: f32 ) -> (f32, f32) {
+		let low = self.low.iter_mut().fold(x, |y, filter| filter.filter(y));
+		let high = self.high.iter_mut().fold(x, |y, filter| filter.filter(y));
+		(low, high)
+	}
+}
+
+
+// stereo field after the chain. the sides can be swapped and flipped,
+// one side held back by a haas delay, the side signal scaled by width
+// and cut out below a crossover so the bass stays mono, then balanced
+pub struct ImagerEffect {
+	haas_lines: [DelayLine; 2],
+	mid_split: Crossover,
+	side_split: Crossover,
+
+	width: f32,
+	bass_mono: bool,
+	mono_freq: f32,
+	// seconds, positive holds back the right and negative the left
+	haas: f32,
+	balance: f32,
+	swap: bool,
+	invert: [bool; 2],
+	sample_rate: f32,
+
+	params: Weak<AndrewParams>,
+}
+
+impl AndrewEffect for ImagerEffect {
+	fn process_stereo(&mut self, in_bufs: [&[f32]; 2], out_bufs: [&mut [f32]; 2]) {
+		let [out_l, out_r] = out_bufs;
+		let (in_l, in_r) = if self.swap { (in_bufs[1], in_bufs[0]) } else { (in_bufs[0], in_bufs[1]) };
+		let polarity = self.invert.map(|invert| if invert { -1.0 } else { 1.0 });
+
+		let haas = self.haas.abs() * self.sample_rate;
+		let held = if self.haas > 0.0 { 1 } else { 0 };
+		let gains = [(1.0 - self.balance).min(1.0), (1.0 + self.balance).min(1.0)];
+
+		for i in 0..in_l.len() {
+			let mut samps = [in_l[i] * polarity[0], in_r[i] * polarity[1]];
+
+			// both lines are always fed, so whichever side gets held back
+			// picks up from what just went by. under a sample there's
+			// nothing to hold back
+			self.haas_lines[0].push(samps[0]);
+			self.haas_lines[1].push(samps[1]);
+			if haas >= 1.0 {
+				samps[held] = self.haas_lines[held].read(haas);
+			}
+
+			let mut mid = 0.5 * (samps[0] + samps[1]);
+			let mut side = 0.5 * (samps[0] - samps[1]) * self.width;
+			if self.bass_mono {
+				// the mid goes through the split too, so its phase
+				// still lines up with the side's highs
+				let (low, high) = self.mid_split.split(mid);
+				mid = low + high;
+				side = self.side_split.split(side).1;
+			}
+
+			out_l[i] = (mid + side) * gains[0];
+			out_r[i] = (mid - side) * gains[1];
+		}
+	}
+
+	fn update_params(&mut self) {
+		if let Some(params) = self.params.upgrade() {
+			self.width = params.imager_width.get();
+			self.balance = params.imager_balance.get().clamp(-1.0, 1.0);
+			self.swap = params.imager_swap.load(Ordering::Relaxed);
+			self.invert = [params.imager_invert_l.load(Ordering::Relaxed), params.imager_invert_r.load(Ordering::Relaxed)];
+
+			self.haas = params.imager_haas.get().clamp(-HAAS_MAX_DELAY, HAAS_MAX_DELAY);
+
+			let bass_mono = params.imager_mono.load(Ordering::Relaxed);
+			let mono_freq = params.imager_mono_freq.get();
+			let sample_rate = params.sample_rate.get();
+			if sample_rate != self.sample_rate {
+				let max_delay = (HAAS_MAX_DELAY * sample_rate) as usize + 4;
+				self.haas_lines = [DelayLine::new(max_delay), DelayLine::new(max_delay)];
+			}
+			// the split starts fresh when it comes in, rather than from
+			// whatever it held when it was last on
+			if sample_rate != self.sample_rate || (bass_mono && !self.bass_mono) {
+				self.sample_rate = sample_rate;
+				self.mid_split = Crossover::new(mono_freq, sample_rate);
+				self.side_split = self.mid_split.clone();
+			} else if mono_freq != self.mono_freq {
+				self.mid_split.set_freq(mono_freq);
+				self.side_split.set_freq(mono_freq);
+			}
+			self.bass_mono = bass_mono;
+			self.mono_freq = mono_freq;
+		}
+	}
+
+	fn get_latency(&self) -> usize {0}
+}
+
+impl ImagerEffect {
+	pub fn new( params: Weak<AndrewParams> ) -> Self {
+		let line = DelayLine::new((HAAS_MAX_DELAY * 44100.0) as usize + 4);
+		let split = Crossover::new(120.0, 44100.0);
+		ImagerEffect {
+			haas_lines: [line.clone(), line],
+			mid_split: split.clone(),
+			side_split: split,
+			width: 1.0,
+			bass_mono: false,
+			mono_freq: 120.0,
+			haas: 0.0,
+			balance: 0.0,
+			swap: false,
+			invert: [false; 2],
+			sample_rate: 44100.0,
+			params,
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::Arc;
+
+	#[test]
+	fn haas_picks_up_from_what_just_went_by() {
+		let params = Arc::new(AndrewParams::default());
+		let mut imager = ImagerEffect::new(Arc::downgrade(&params));
+		let noise: Vec<f32> = (0..3000).map(|i| ((i * 7919) % 1000) as f32 / 500.0 - 1.0).collect();
+		let (mut out_l, mut out_r) = (vec![0.0; 1000], vec![0.0; 1000]);
+
+		// held back, let go, then held back again a block later
+		for (block, haas) in [0.01, 0.0, 0.01].iter().enumerate() {
+			params.imager_haas.set(*haas);
+			imager.update_params();
+			let ins = &noise[block * 1000..(block + 1) * 1000];
+			imager.process_stereo([ins, ins], [&mut out_l, &mut out_r]);
+		}
+
+		// the right side comes out 441 samples late, straight out of the last block
+		let start = 2000 - 441;
+		for (i, out) in out_r.iter().enumerate() {
+			assert!((out - noise[start + i]).abs() < 1e-5, "{} at {} should be {}", out, i, noise[start + i]);
+		}
+		assert!(out_l.iter().zip(&noise[2000..]).all(|(out, ins)| (out - ins).abs() < 1e-5));
+	}
+}
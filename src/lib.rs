@@ -5,14 +5,18 @@ extern crate vst;
 
 
 use andrew_effect::*;
-use vst::{api::EventType, plugin::{Category, HostCallback, Info, Plugin, PluginParameters}};
+use vst::{api::{EventType, Supported}, plugin::{CanDo, Category, HostCallback, Info, Plugin, PluginParameters}};
+use vst::channels::ChannelInfo;
 use vst::buffer::AudioBuffer;
 use vst::util::AtomicFloat;
-use vst::api;
+use vst::api::{self, TimeInfoFlags};
+use vst::host::Host;
 use vst::event::Event;
 
 
 mod andrew_effect;
+mod chain;
+use chain::{Chain, ChainSwap, EffectKind};
 mod biquad;
 use biquad::BiQuadraticFilter;
 
@@ -23,44 +27,73 @@ use log::Logger;
 mod types;
 mod audio_clip;
 mod modulator;
+use modulator::{LfoShape, NoteDivision};
+#[cfg(feature = "mp3")]
+mod mp3ifier;
+mod fft;
+mod noise;
+mod codec;
+mod dynamics;
+use dynamics::{DynEqKind, DYNEQ_BANDS};
+mod ring;
+mod phaser;
+mod pitch;
+mod stft;
+mod freeze;
+mod delay_line;
+mod tape;
+use tape::TapeSpeed;
+mod chorus;
+mod reverb;
+mod imager;
+mod tremolo;
+mod resonator;
+use resonator::KS_MAX_VOICES;
+mod vocoder;
+mod formant;
 
-use std::{cell::{Ref, RefCell}, path::Path, rc::Weak, sync::atomic::{self, AtomicBool, AtomicU8, Ordering}};
-use std::sync::Arc;
+use std::{cell::{Ref, RefCell}, path::Path, sync::atomic::{self, AtomicBool, AtomicU8, Ordering}};
+use std::sync::{Arc, Weak};
 
 #[derive(Default)]
 struct AndrewVst {
 	sample_rate: f32,
 	logger: Logger,
 	params: Arc<AndrewParams>,
-	effects: Vec<Box<dyn AndrewEffect>>,
+	chain: Box<Chain>,
+	host: HostCallback,
 }
 
 impl Plugin for AndrewVst {
 
-	fn new(_host: HostCallback) -> Self
+	fn new(host: HostCallback) -> Self
 	where Self: Sized + Default, {
-		let params = Arc::new(<AndrewParams as Default>::default());
-		let weak = Arc::downgrade(&params);
+		let params = Arc::new_cyclic(|this| AndrewParams {
+			this: this.clone(),
+			host,
+			..Default::default()
+		});
+		let effect = EffectKind::from_index(params.effect.load(Ordering::Relaxed));
+		let chain = Box::new(Chain::build(effect, Arc::downgrade(&params)));
 		AndrewVst {
 			sample_rate: 44100.0,
 			logger: Logger::new( &Path::new("/Library/Audio/Plug-Ins/VST/Custom/conv_log.txt")),
 			params,
-			effects: vec![Box::new(DistEffect::new(weak))],
+			chain,
+			host,
 		}
 	}
 
-	fn init( &mut self ) {
-		// self.effects.push(Box::new(SlewEffect::new( Arc::downgrade( &self.params ))));
-		self.effects.push(Box::new(VibEffect::new()));
-	}
-
 	fn get_info(&self) -> Info {
 		Info {
 			name: "Conv".into(),
 			vendor: "Andrew Wilson".into(),
-			inputs: 2,
+			// the second pair is the sidechain
+			inputs: 4,
 			outputs: 2,
-			parameters: 5,
+			midi_inputs: 1,
+			parameters: 178,
+			initial_delay: self.chain.latency as i32,
 			category: Category::Effect,
 			..Default::default()
 		}
@@ -69,84 +102,603 @@ impl Plugin for AndrewVst {
 	fn set_sample_rate(&mut self, rate: f32) {
 		self.sample_rate = rate;
 		self.params.sample_rate.set(rate);
+		self.params.updated.store(true, Ordering::Relaxed);
 		self.logger.log(&format!("changed sample rate too {}", rate));
 	}
 
+	// the host stops processing to change the sample rate, so the chain
+	// is rebuilt for it here, where there is time to
+	fn resume(&mut self) {
+		self.params.build_chain();
+		self.params.chains.swap(&mut self.chain);
+		self.params.chains.collect();
+	}
+
 
 	fn process( &mut self, buffer: &mut AudioBuffer<f32> ) {
-		// must have at most two channels 
-		if buffer.input_count() > 2 { return }
+		// a chain built for another effect, if there is one waiting
+		self.params.chains.swap(&mut self.chain);
 
 		//update params
 		if self.params.updated.load( Ordering::Relaxed ) {
 			self.logger.log("updating effect params");
-			self.effects.iter_mut().for_each(|effect| effect.update_params() );
-			self.params.updated.store( false, Ordering::Relaxed )
+			self.chain.effects.iter_mut().for_each(|effect| effect.update_params() );
+			self.params.updated.store( false, Ordering::Relaxed );
 		}
 
 		let buf_len = buffer.samples(); 
-		// loop over AndrewEffects
-		
-		for (chan_id, (in_chan, out_chan)) in buffer.zip().enumerate() {
-			// out_chan.iter_mut().zip(in_chan.iter()).for_each(|(out, samp)| *out = *samp);
-			let mut in_vec = Vec::from(in_chan);
-			let mut out_vec = vec![0f32; buf_len];
-
-			let mut buf = (in_vec, out_vec);
-			for effect in self.effects.iter_mut() {
-				// flips the bufs beforehand so that an extra flip
-				// is not needed after the loop has finished
-				buf = (buf.1, buf.0);
-
-				// consequentially, the bufs are given to process in the opposite order
-				effect.process(chan_id, &buf.1, &mut buf.0);
+		let (inputs, mut outputs) = buffer.split();
+
+		// must have one or two channels, plus a sidechain pair. anything
+		// else comes out silent rather than as whatever the host left there
+		if inputs.is_empty() || inputs.len() > 4 {
+			for chan_id in 0..outputs.len() {
+				outputs.get_mut(chan_id).iter_mut().for_each(|out| *out = 0.0);
 			}
+			return
+		}
+
+		// a mono input runs down both sides of the pair
+		let right = inputs.len().min(2) - 1;
+		let mut bufs = [
+			(Vec::from(inputs.get(0)), vec![0f32; buf_len]),
+			(Vec::from(inputs.get(right)), vec![0f32; buf_len]),
+		];
+
+		let time_info = self.host.get_time_info((TimeInfoFlags::TEMPO_VALID | TimeInfoFlags::PPQ_POS_VALID).bits());
 
-			let dry_wet = self.params.dry_wet.get();
+		let sc_bufs = if inputs.len() >= 4 { [inputs.get(2), inputs.get(3)] } else { [&[][..]; 2] };
 
+		// loop over AndrewEffects, a stereo pair at a time
+		for effect in self.chain.effects.iter_mut() {
+			effect.sidechain(sc_bufs);
+			if let Some(info) = &time_info {
+				effect.time_info(info);
+			}
+
+			// flips the bufs beforehand so that an extra flip
+			// is not needed after the loop has finished
+			for buf in bufs.iter_mut() {
+				std::mem::swap(&mut buf.0, &mut buf.1);
+			}
+
+			// consequentially, the bufs are given to process in the opposite order
+			let [(out_l, in_l), (out_r, in_r)] = &mut bufs;
+			effect.process_stereo([in_l, in_r], [out_l, out_r]);
+		}
+
+		let dry_wet = self.params.dry_wet.get();
+
+		for (chan_id, buf) in bufs.iter().enumerate().take(outputs.len()) {
+			let in_chan = inputs.get(chan_id.min(right));
+			let out_chan = outputs.get_mut(chan_id);
 			for i in 0..buf.0.len() {
-				out_chan[i] = (buf.0[i] * dry_wet)  +  (in_chan[i] * (1.0 - dry_wet));
+				let dry = self.chain.dry(chan_id, in_chan[i]);
+				out_chan[i] = (buf.0[i] * dry_wet)  +  (dry * (1.0 - dry_wet));
 			}
 		}
-			
 	} 
 
+	// midi goes to every effect, ahead of the block it's for
+	fn process_events( &mut self, events: &api::Events ) {
+		for event in events.events() {
+			if let Event::Midi(midi) = event {
+				self.chain.effects.iter_mut().for_each(|effect| effect.midi(midi.data));
+			}
+		}
+	}
+
+	fn can_do( &self, can_do: CanDo ) -> Supported {
+		match can_do {
+			CanDo::ReceiveEvents | CanDo::ReceiveMidiEvent | CanDo::ReceiveTimeInfo => Supported::Yes,
+			_ => Supported::Maybe,
+		}
+	}
+
+	fn get_input_info( &self, input: i32 ) -> ChannelInfo {
+		let side = if input % 2 == 0 { "L" } else { "R" };
+		match input {
+			0 | 1 => ChannelInfo::new(format!("Input {}", side), Some(format!("In {}", side)), true, None),
+			_ => ChannelInfo::new(format!("Sidechain {}", side), Some(format!("SC {}", side)), true, None),
+		}
+	}
+
 	fn get_parameter_object( &mut self ) -> Arc<dyn PluginParameters> {
 		Arc::clone( &self.params ) as Arc<dyn PluginParameters>
 	}
+
+	// the effects ring out one after another, so their tails add up.
+	// zero leaves it to the host, which is what we had before any had one.
+	// one that never stops makes the tail the longest a host will read
+	fn get_tail_size( &self ) -> isize {
+		self.chain.effects.iter()
+			.fold(0usize, |tail, effect| tail.saturating_add(effect.get_tail_size()))
+			.min(i32::MAX as usize) as isize
+	}
+}
+
+// the host only reads the delay off the AEffect, which the vst crate
+// fills in from get_info once. after that it has to be written there
+// and the host told to look again. hosts want this off the audio thread
+fn report_latency( host: &HostCallback, latency: usize ) {
+	let effect = host.raw_effect();
+	if let (Some(callback), false) = (host.raw_callback(), effect.is_null()) {
+		unsafe { (*effect).initialDelay = latency as i32 };
+		callback(effect, vst::host::OpCode::IOChanged.into(), 0, 0, std::ptr::null_mut(), 0.0);
+	}
 }
 
 
 
 
 
+// bitrates lame will accept, in kbps
+pub const MP3_BITRATES: [f32; 17] = [8.0, 16.0, 24.0, 32.0, 40.0, 48.0, 56.0, 64.0, 80.0, 96.0, 112.0, 128.0, 160.0, 192.0, 224.0, 256.0, 320.0];
+
 pub struct AndrewParams {
+	// to hand the effects built here, and to tell the host about them
+	this: Weak<AndrewParams>,
+	host: HostCallback,
+	// chains on their way to and from the audio thread
+	chains: ChainSwap,
 	updated: AtomicBool,
 	sample_rate: AtomicFloat,
+	// which effect the chain is built for
+	effect: AtomicU8,
 	dry_wet: AtomicFloat,
-	slew: AtomicFloat,
+	slew_rise: AtomicFloat,
 	delay_time: AtomicFloat,
 	delay_feedback: AtomicFloat,
 
 	// filter
 	cutoff: AtomicFloat,
+
+	// conv
+	kernel_shape: AtomicU8,
+	// custom kernel, oldest tap first
+	kernel_taps: [AtomicFloat; KERNEL_CUSTOM_TAPS],
+
+	// integrator / differentiator
+	leak_freq: AtomicFloat,
+	calculus_gain: AtomicFloat,
+
+	// slew
+	slew_fall: AtomicFloat,
+	slew_db: AtomicBool,
+	slew_link: AtomicBool,
+
+	// too slew, in full scale per second and per second squared
+	accel_slope: AtomicFloat,
+	accel_limit: AtomicFloat,
+
+	// mp3
+	mp3_bitrate: AtomicFloat,
+	mp3_quality: AtomicU8,
+
+	// codec emulation
+	codec_kbps: AtomicFloat,
+	codec_bandwidth: AtomicFloat,
+	codec_pre_echo: AtomicBool,
+	codec_warble: AtomicFloat,
+
+	// crush
+	crush_bits: AtomicFloat,
+	crush_rate: AtomicFloat,
+	crush_dither: AtomicBool,
+	crush_aa: AtomicBool,
+	crush_jitter: AtomicFloat,
+
+	// compressor
+	comp_threshold: AtomicFloat,
+	comp_ratio: AtomicFloat,
+	comp_knee: AtomicFloat,
+	comp_attack: AtomicFloat,
+	comp_release: AtomicFloat,
+	comp_makeup: AtomicFloat,
+	comp_rms: AtomicBool,
+	comp_link: AtomicBool,
+	comp_sc_hpf: AtomicFloat,
+	comp_external: AtomicBool,
+
+	// gate
+	gate_threshold: AtomicFloat,
+	gate_range: AtomicFloat,
+	gate_ratio: AtomicFloat,
+	gate_attack: AtomicFloat,
+	gate_hold: AtomicFloat,
+	gate_release: AtomicFloat,
+	gate_hysteresis: AtomicFloat,
+	gate_key_lo: AtomicFloat,
+	gate_key_hi: AtomicFloat,
+
+	// transient shaper
+	trans_attack: AtomicFloat,
+	trans_sustain: AtomicFloat,
+	trans_sensitivity: AtomicFloat,
+	trans_clip: AtomicFloat,
+
+	// ring mod / frequency shifter
+	ring_shift: AtomicBool,
+	ring_freq: AtomicFloat,
+	ring_shape: AtomicU8,
+	shift_hz: AtomicFloat,
+	shift_feedback: AtomicFloat,
+
+	// phaser
+	phaser_stages: AtomicU8,
+	phaser_rate: AtomicFloat,
+	phaser_depth: AtomicFloat,
+	phaser_center: AtomicFloat,
+	phaser_feedback: AtomicFloat,
+	// fraction of a cycle the right lfo leads by
+	phaser_spread: AtomicFloat,
+	// second order stages, a notch each rather than one a pair
+	phaser_second_order: AtomicBool,
+
+	// phase vocoder
+	pv_semitones: AtomicFloat,
+	pv_formant: AtomicBool,
+	pv_transient: AtomicBool,
+
+	// spectral freeze
+	freeze: AtomicBool,
+	freeze_blur: AtomicFloat,
+	freeze_mix: AtomicFloat,
+
+	// tape
+	tape_speed: AtomicU8,
+	tape_drive: AtomicFloat,
+	tape_wow: AtomicFloat,
+	tape_flutter: AtomicFloat,
+	tape_hiss: AtomicFloat,
+
+	// chorus, delays in seconds
+	chorus_voices: AtomicU8,
+	chorus_rate: AtomicFloat,
+	chorus_depth: AtomicFloat,
+	chorus_detune: AtomicFloat,
+	chorus_width: AtomicFloat,
+	chorus_mix: AtomicFloat,
+
+	// flanger, delays in seconds
+	flanger_delay: AtomicFloat,
+	flanger_depth: AtomicFloat,
+	flanger_rate: AtomicFloat,
+	flanger_feedback: AtomicFloat,
+	flanger_tz: AtomicBool,
+	flanger_mix: AtomicFloat,
+
+	// reverb, times in seconds
+	reverb_size: AtomicFloat,
+	reverb_decay: AtomicFloat,
+	reverb_predelay: AtomicFloat,
+	reverb_damping: AtomicFloat,
+	reverb_diffusion: AtomicFloat,
+	reverb_mod: AtomicFloat,
+	reverb_width: AtomicFloat,
+	reverb_freeze: AtomicBool,
+	reverb_mix: AtomicFloat,
+
+	// stereo imager, haas delay in seconds
+	imager_width: AtomicFloat,
+	imager_mono: AtomicBool,
+	imager_mono_freq: AtomicFloat,
+	imager_haas: AtomicFloat,
+	imager_balance: AtomicFloat,
+	imager_swap: AtomicBool,
+	imager_invert_l: AtomicBool,
+	imager_invert_r: AtomicBool,
+
+	// tremolo, offset in cycles and smoothing in seconds
+	tremolo_rate: AtomicFloat,
+	tremolo_depth: AtomicFloat,
+	tremolo_shape: AtomicU8,
+	tremolo_smooth: AtomicFloat,
+	tremolo_offset: AtomicFloat,
+	tremolo_sync: AtomicBool,
+	tremolo_division: AtomicU8,
+
+	// karplus-strong, decay in seconds and notes as midi numbers
+	ks_count: AtomicU8,
+	ks_decay: AtomicFloat,
+	ks_damping: AtomicFloat,
+	ks_brightness: AtomicFloat,
+	ks_midi: AtomicBool,
+	ks_mix: AtomicFloat,
+	ks_notes: [AtomicU8; KS_MAX_VOICES],
+
+	// vocoder, shift in semitones
+	vocoder_bands: AtomicU8,
+	vocoder_shift: AtomicFloat,
+	vocoder_internal: AtomicBool,
+	vocoder_pitch: AtomicFloat,
+	vocoder_noise: AtomicFloat,
+	vocoder_sibilance: AtomicFloat,
+	vocoder_mix: AtomicFloat,
+
+	// formant filter, vowel as a morph through a e i o u
+	formant_vowel: AtomicFloat,
+	formant_female: AtomicBool,
+	formant_lfo_rate: AtomicFloat,
+	formant_lfo_depth: AtomicFloat,
+	formant_env: AtomicFloat,
+	formant_mix: AtomicFloat,
+
+	// auto-wah mode of the filter, attack and release in ms
+	wah: AtomicBool,
+	wah_lowpass: AtomicBool,
+	wah_min: AtomicFloat,
+	wah_max: AtomicFloat,
+	wah_sensitivity: AtomicFloat,
+	wah_attack: AtomicFloat,
+	wah_release: AtomicFloat,
+	wah_down: AtomicBool,
+	wah_resonance: AtomicFloat,
+
+	// dynamic eq, per band, attack and release in ms
+	dyneq_kind: [AtomicU8; DYNEQ_BANDS],
+	dyneq_freq: [AtomicFloat; DYNEQ_BANDS],
+	dyneq_q: [AtomicFloat; DYNEQ_BANDS],
+	dyneq_threshold: [AtomicFloat; DYNEQ_BANDS],
+	dyneq_ratio: [AtomicFloat; DYNEQ_BANDS],
+	dyneq_attack: [AtomicFloat; DYNEQ_BANDS],
+	dyneq_release: [AtomicFloat; DYNEQ_BANDS],
+	// de-esser mode, range in db
+	dyneq_deess: AtomicBool,
+	deess_freq: AtomicFloat,
+	deess_threshold: AtomicFloat,
+	deess_range: AtomicFloat,
+	deess_wideband: AtomicBool,
+	// 0 off, otherwise the band whose detector is soloed
+	dyneq_listen: AtomicU8,
+}
+
+// where a value sits along `low * range^v`, undoing the exponential
+// mappings in set_parameter
+#[inline]
+fn exp_norm( value: f32, low: f32, range: f32 ) -> f32 {
+	((value / low).ln() / range.ln()).clamp(0.0, 1.0)
+}
+
+// where a value sits between `low` and `high`
+#[inline]
+fn lin_norm( value: f32, low: f32, high: f32 ) -> f32 {
+	((value - low) / (high - low)).clamp(0.0, 1.0)
 }
 
 impl PluginParameters for AndrewParams {
 	fn get_parameter( &self, i: i32 ) -> f32 {
 		match i {
 			0 => self.dry_wet.get(),
-			1 => self.slew.get(),
-			2 => self.delay_time.get(),
+			1 => exp_norm(self.slew_rise.get(), 20.0, 1000.0),
+			2 => lin_norm(self.delay_time.get(), 1.0, 0.01),
 			3 => self.delay_feedback.get(),
-			4 => self.cutoff.get(),
+			4 => lin_norm(self.cutoff.get(), 0.01, 1.0),
+			5 => self.kernel_shape.load(Ordering::Relaxed) as f32 / (KernelShape::COUNT - 1) as f32,
+			6 => exp_norm(self.leak_freq.get(), 1.0, 200.0),
+			7 => lin_norm(self.calculus_gain.get(), -24.0, 24.0),
+			8 => exp_norm(self.slew_fall.get(), 20.0, 1000.0),
+			9 => self.slew_db.load(Ordering::Relaxed) as u8 as f32,
+			10 => self.slew_link.load(Ordering::Relaxed) as u8 as f32,
+			11 => exp_norm(self.accel_slope.get(), 20.0, 1000.0),
+			12 => exp_norm(self.accel_limit.get(), 1e4, 1e6),
+			// without the codec built in there's nothing for them to set
+			13 | 14 if !cfg!(feature = "mp3") => 0.0,
+			13 => MP3_BITRATES.iter().position(|kbps| *kbps == self.mp3_bitrate.get()).unwrap_or(0) as f32 / (MP3_BITRATES.len() - 1) as f32,
+			14 => self.mp3_quality.load(Ordering::Relaxed) as f32 / 9.0,
+			15 => exp_norm(self.codec_kbps.get(), 8.0, 40.0),
+			16 => exp_norm(self.codec_bandwidth.get(), 1000.0, 20.0),
+			17 => self.codec_pre_echo.load(Ordering::Relaxed) as u8 as f32,
+			18 => self.codec_warble.get(),
+			19 => lin_norm(self.crush_bits.get(), 1.0, 24.0),
+			20 => exp_norm(self.crush_rate.get(), 100.0, 1920.0),
+			21 => self.crush_dither.load(Ordering::Relaxed) as u8 as f32,
+			22 => self.crush_aa.load(Ordering::Relaxed) as u8 as f32,
+			23 => self.crush_jitter.get(),
+			24 => lin_norm(self.comp_threshold.get(), -60.0, 0.0),
+			25 => exp_norm(self.comp_ratio.get(), 1.0, 20.0),
+			26 => lin_norm(self.comp_knee.get(), 0.0, 24.0),
+			27 => exp_norm(self.comp_attack.get(), 0.1, 1000.0),
+			28 => exp_norm(self.comp_release.get(), 10.0, 200.0),
+			29 => lin_norm(self.comp_makeup.get(), 0.0, 24.0),
+			30 => self.comp_rms.load(Ordering::Relaxed) as u8 as f32,
+			31 => self.comp_link.load(Ordering::Relaxed) as u8 as f32,
+			32 => exp_norm(self.comp_sc_hpf.get(), 20.0, 50.0),
+			33 => self.comp_external.load(Ordering::Relaxed) as u8 as f32,
+			34 => lin_norm(self.gate_threshold.get(), -80.0, 0.0),
+			35 => lin_norm(self.gate_range.get(), 0.0, 80.0),
+			36 => exp_norm(self.gate_ratio.get(), 1.0, 50.0),
+			37 => exp_norm(self.gate_attack.get(), 0.01, 5000.0),
+			38 => lin_norm(self.gate_hold.get(), 0.0, 500.0),
+			39 => exp_norm(self.gate_release.get(), 5.0, 400.0),
+			40 => lin_norm(self.gate_hysteresis.get(), 0.0, 12.0),
+			41 => exp_norm(self.gate_key_lo.get(), 20.0, 100.0),
+			42 => exp_norm(self.gate_key_hi.get(), 1000.0, 20.0),
+			43 => lin_norm(self.trans_attack.get(), -24.0, 24.0),
+			44 => lin_norm(self.trans_sustain.get(), -24.0, 24.0),
+			45 => exp_norm(self.trans_sensitivity.get(), 0.25, 16.0),
+			46 => lin_norm(self.trans_clip.get(), -24.0, 0.0),
+			47 => self.ring_shift.load(Ordering::Relaxed) as u8 as f32,
+			48 => exp_norm(self.ring_freq.get(), 0.1, 50000.0),
+			49 => self.ring_shape.load(Ordering::Relaxed) as f32 / (LfoShape::COUNT - 1) as f32,
+			50 => ((self.shift_hz.get() / 2000.0).cbrt() + 1.0) * 0.5,
+			51 => self.shift_feedback.get(),
+			52 => (self.phaser_stages.load(Ordering::Relaxed) - 2) as f32 / 10.0,
+			53 => exp_norm(self.phaser_rate.get(), 0.02, 500.0),
+			54 => self.phaser_depth.get(),
+			55 => exp_norm(self.phaser_center.get(), 100.0, 100.0),
+			56 => lin_norm(self.phaser_feedback.get(), -0.9, 0.9),
+			57 => lin_norm(self.phaser_spread.get(), 0.0, 0.5),
+			58 => lin_norm(self.pv_semitones.get(), -24.0, 24.0),
+			59 => self.pv_formant.load(Ordering::Relaxed) as u8 as f32,
+			60 => self.pv_transient.load(Ordering::Relaxed) as u8 as f32,
+			61 => self.freeze.load(Ordering::Relaxed) as u8 as f32,
+			62 => self.freeze_blur.get(),
+			63 => self.freeze_mix.get(),
+			64 => self.tape_speed.load(Ordering::Relaxed) as f32 / (TapeSpeed::COUNT - 1) as f32,
+			65 => lin_norm(self.tape_drive.get(), -12.0, 24.0),
+			66 => self.tape_wow.get(),
+			67 => self.tape_flutter.get(),
+			68 => lin_norm(self.tape_hiss.get(), -90.0, -30.0),
+			69 => (self.chorus_voices.load(Ordering::Relaxed) - 1) as f32 / 7.0,
+			70 => exp_norm(self.chorus_rate.get(), 0.05, 100.0),
+			71 => lin_norm(self.chorus_depth.get(), 0.0, 0.008),
+			72 => self.chorus_detune.get(),
+			73 => self.chorus_width.get(),
+			74 => self.chorus_mix.get(),
+			75 => exp_norm(self.flanger_delay.get(), 0.0001, 100.0),
+			76 => lin_norm(self.flanger_depth.get(), 0.0, 0.005),
+			77 => exp_norm(self.flanger_rate.get(), 0.02, 500.0),
+			78 => lin_norm(self.flanger_feedback.get(), -0.95, 0.95),
+			79 => self.flanger_tz.load(Ordering::Relaxed) as u8 as f32,
+			80 => self.flanger_mix.get(),
+			81 => exp_norm(self.reverb_size.get(), 0.25, 8.0),
+			82 => exp_norm(self.reverb_decay.get(), 0.1, 200.0),
+			83 => lin_norm(self.reverb_predelay.get(), 0.0, 0.25),
+			84 => exp_norm(self.reverb_damping.get(), 1000.0, 20.0),
+			85 => self.reverb_diffusion.get(),
+			86 => self.reverb_mod.get(),
+			87 => self.reverb_width.get(),
+			88 => self.reverb_freeze.load(Ordering::Relaxed) as u8 as f32,
+			89 => self.reverb_mix.get(),
+			90 => lin_norm(self.imager_width.get(), 0.0, 2.0),
+			91 => self.imager_mono.load(Ordering::Relaxed) as u8 as f32,
+			92 => exp_norm(self.imager_mono_freq.get(), 20.0, 25.0),
+			93 => lin_norm(self.imager_haas.get(), -0.03, 0.03),
+			94 => lin_norm(self.imager_balance.get(), -1.0, 1.0),
+			95 => self.imager_swap.load(Ordering::Relaxed) as u8 as f32,
+			96 => self.imager_invert_l.load(Ordering::Relaxed) as u8 as f32,
+			97 => self.imager_invert_r.load(Ordering::Relaxed) as u8 as f32,
+			98 => exp_norm(self.tremolo_rate.get(), 0.05, 400.0),
+			99 => self.tremolo_depth.get(),
+			100 => self.tremolo_shape.load(Ordering::Relaxed) as f32 / (LfoShape::COUNT - 1) as f32,
+			101 => exp_norm(self.tremolo_smooth.get(), 0.0005, 40.0),
+			102 => self.tremolo_offset.get(),
+			103 => self.tremolo_sync.load(Ordering::Relaxed) as u8 as f32,
+			104 => self.tremolo_division.load(Ordering::Relaxed) as f32 / (NoteDivision::COUNT - 1) as f32,
+			105 => (self.ks_count.load(Ordering::Relaxed) - 1) as f32 / 7.0,
+			106 => exp_norm(self.ks_decay.get(), 0.05, 400.0),
+			107 => lin_norm(self.ks_damping.get(), 0.0, 0.95),
+			108 => exp_norm(self.ks_brightness.get(), 200.0, 100.0),
+			109 => self.ks_midi.load(Ordering::Relaxed) as u8 as f32,
+			110 => self.ks_mix.get(),
+			111..=118 => self.ks_notes[i as usize - 111].load(Ordering::Relaxed) as f32 / 127.0,
+			119 => (self.vocoder_bands.load(Ordering::Relaxed) - 8) as f32 / 24.0,
+			120 => lin_norm(self.vocoder_shift.get(), -12.0, 12.0),
+			121 => self.vocoder_internal.load(Ordering::Relaxed) as u8 as f32,
+			122 => exp_norm(self.vocoder_pitch.get(), 40.0, 25.0),
+			123 => self.vocoder_noise.get(),
+			124 => self.vocoder_sibilance.get(),
+			125 => self.vocoder_mix.get(),
+			126 => self.formant_vowel.get(),
+			127 => self.formant_female.load(Ordering::Relaxed) as u8 as f32,
+			128 => exp_norm(self.formant_lfo_rate.get(), 0.05, 400.0),
+			129 => self.formant_lfo_depth.get(),
+			130 => lin_norm(self.formant_env.get(), -1.0, 1.0),
+			131 => self.formant_mix.get(),
+			132 => self.wah.load(Ordering::Relaxed) as u8 as f32,
+			133 => self.wah_lowpass.load(Ordering::Relaxed) as u8 as f32,
+			134 => exp_norm(self.wah_min.get(), 50.0, 40.0),
+			135 => exp_norm(self.wah_max.get(), 200.0, 100.0),
+			136 => lin_norm(self.wah_sensitivity.get(), 0.0, 48.0),
+			137 => exp_norm(self.wah_attack.get(), 0.5, 100.0),
+			138 => exp_norm(self.wah_release.get(), 10.0, 100.0),
+			139 => self.wah_down.load(Ordering::Relaxed) as u8 as f32,
+			140 => exp_norm(self.wah_resonance.get(), 0.5, 40.0),
+			141..=143 => self.dyneq_kind[i as usize - 141].load(Ordering::Relaxed) as f32 / (DynEqKind::COUNT - 1) as f32,
+			144..=146 => exp_norm(self.dyneq_freq[i as usize - 144].get(), 20.0, 1000.0),
+			147..=149 => exp_norm(self.dyneq_q[i as usize - 147].get(), 0.3, 50.0),
+			150..=152 => lin_norm(self.dyneq_threshold[i as usize - 150].get(), -60.0, 0.0),
+			153..=155 => exp_norm(self.dyneq_ratio[i as usize - 153].get(), 1.0, 20.0),
+			156..=158 => exp_norm(self.dyneq_attack[i as usize - 156].get(), 0.1, 1000.0),
+			159..=161 => exp_norm(self.dyneq_release[i as usize - 159].get(), 10.0, 200.0),
+			162 => self.dyneq_deess.load(Ordering::Relaxed) as u8 as f32,
+			163 => exp_norm(self.deess_freq.get(), 2000.0, 6.0),
+			164 => lin_norm(self.deess_threshold.get(), -60.0, 0.0),
+			165 => lin_norm(self.deess_range.get(), 0.0, 24.0),
+			166 => self.deess_wideband.load(Ordering::Relaxed) as u8 as f32,
+			167 => self.dyneq_listen.load(Ordering::Relaxed) as f32 / DYNEQ_BANDS as f32,
+			168..=175 => (self.kernel_taps[i as usize - 168].get() + 1.0) * 0.5,
+			176 => self.effect.load(Ordering::Relaxed) as f32 / (EffectKind::COUNT - 1) as f32,
+			177 => self.phaser_second_order.load(Ordering::Relaxed) as u8 as f32,
 			_ => 0.0,
 		}
 	}
 
 	fn get_parameter_label(&self, i: i32) -> String {
 		match i {
+			// the slew rates read as db per second in db mode
+			1 | 8 if self.slew_db.load(Ordering::Relaxed) => "dB/s",
 			1 => "Hz",
+			6 => "Hz",
+			7 => "dB",
+			8 => "Hz",
+			11 => "/s",
+			12 => "/s^2",
+			13 if !cfg!(feature = "mp3") => "",
+			13 => "kbps",
+			15 => "kbps",
+			16 => "Hz",
+			19 => "bits",
+			20 => "Hz",
+			24 => "dB",
+			25 => ":1",
+			26 => "dB",
+			27 => "ms",
+			28 => "ms",
+			29 => "dB",
+			32 => "Hz",
+			34 => "dB",
+			35 => "dB",
+			36 => ":1",
+			37 => "ms",
+			38 => "ms",
+			39 => "ms",
+			40 => "dB",
+			41 => "Hz",
+			42 => "Hz",
+			43 => "dB",
+			44 => "dB",
+			46 => "dB",
+			48 => "Hz",
+			50 => "Hz",
+			53 => "Hz",
+			55 => "Hz",
+			57 => "deg",
+			58 => "st",
+			65 => "dB",
+			68 => "dB",
+			70 => "Hz",
+			71 => "ms",
+			75 => "ms",
+			76 => "ms",
+			77 => "Hz",
+			82 => "s",
+			83 => "ms",
+			84 => "Hz",
+			90 => "%",
+			92 => "Hz",
+			93 => "ms",
+			98 => "Hz",
+			101 => "ms",
+			102 => "deg",
+			106 => "s",
+			108 => "Hz",
+			120 => "st",
+			122 => "Hz",
+			128 => "Hz",
+			134 => "Hz",
+			135 => "Hz",
+			136 => "dB",
+			137 => "ms",
+			138 => "ms",
+			144..=146 => "Hz",
+			150..=152 => "dB",
+			156..=158 => "ms",
+			159..=161 => "ms",
+			163 => "Hz",
+			164 => "dB",
+			165 => "dB",
 			_ => "",
 		}.into()
 	}
@@ -154,10 +706,159 @@ impl PluginParameters for AndrewParams {
 	fn get_parameter_text( &self, i: i32 ) -> String {
 		match i {
 			0 => format!("{:.1}", self.dry_wet.get()).into(),
-			1 => format!("{:.1}", self.slew.get()).into(),
+			1 => format!("{:.1}", self.slew_rise.get()).into(),
 			2 => format!("{:.1}", self.delay_time.get() * 100.0).into(),
 			3 => format!("{:.1}", self.delay_feedback.get() * 100.0).into(),
 			4 => format!("{:.1}", self.cutoff.get()).into(),
+			5 => KernelShape::from_index(self.kernel_shape.load(Ordering::Relaxed)).name().to_string(),
+			6 => format!("{:.1}", self.leak_freq.get()),
+			7 => format!("{:.1}", self.calculus_gain.get()),
+			8 => format!("{:.1}", self.slew_fall.get()),
+			9 => if self.slew_db.load(Ordering::Relaxed) { "dB" } else { "lin" }.into(),
+			10 => if self.slew_link.load(Ordering::Relaxed) { "on" } else { "off" }.into(),
+			11 => format!("{:.1}", self.accel_slope.get()),
+			12 => format!("{:.2e}", self.accel_limit.get()),
+			13 | 14 if !cfg!(feature = "mp3") => "no mp3".into(),
+			13 => format!("{}", self.mp3_bitrate.get()),
+			14 => format!("{}", self.mp3_quality.load(Ordering::Relaxed)),
+			15 => format!("{:.0}", self.codec_kbps.get()),
+			16 => format!("{:.0}", self.codec_bandwidth.get()),
+			17 => if self.codec_pre_echo.load(Ordering::Relaxed) { "on" } else { "off" }.into(),
+			18 => format!("{:.2}", self.codec_warble.get()),
+			19 => format!("{:.1}", self.crush_bits.get()),
+			20 => format!("{:.0}", self.crush_rate.get()),
+			21 => if self.crush_dither.load(Ordering::Relaxed) { "on" } else { "off" }.into(),
+			22 => if self.crush_aa.load(Ordering::Relaxed) { "on" } else { "off" }.into(),
+			23 => format!("{:.2}", self.crush_jitter.get()),
+			24 => format!("{:.1}", self.comp_threshold.get()),
+			25 => format!("{:.1}", self.comp_ratio.get()),
+			26 => format!("{:.1}", self.comp_knee.get()),
+			27 => format!("{:.1}", self.comp_attack.get()),
+			28 => format!("{:.0}", self.comp_release.get()),
+			29 => format!("{:.1}", self.comp_makeup.get()),
+			30 => if self.comp_rms.load(Ordering::Relaxed) { "rms" } else { "peak" }.into(),
+			31 => if self.comp_link.load(Ordering::Relaxed) { "on" } else { "off" }.into(),
+			32 => format!("{:.0}", self.comp_sc_hpf.get()),
+			33 => if self.comp_external.load(Ordering::Relaxed) { "ext" } else { "int" }.into(),
+			34 => format!("{:.1}", self.gate_threshold.get()),
+			35 => format!("{:.1}", self.gate_range.get()),
+			36 => format!("{:.1}", self.gate_ratio.get()),
+			37 => format!("{:.2}", self.gate_attack.get()),
+			38 => format!("{:.0}", self.gate_hold.get()),
+			39 => format!("{:.0}", self.gate_release.get()),
+			40 => format!("{:.1}", self.gate_hysteresis.get()),
+			41 => format!("{:.0}", self.gate_key_lo.get()),
+			42 => format!("{:.0}", self.gate_key_hi.get()),
+			43 => format!("{:.1}", self.trans_attack.get()),
+			44 => format!("{:.1}", self.trans_sustain.get()),
+			45 => format!("{:.2}", self.trans_sensitivity.get()),
+			46 => format!("{:.1}", self.trans_clip.get()),
+			47 => if self.ring_shift.load(Ordering::Relaxed) { "shift" } else { "ring" }.into(),
+			48 => format!("{:.1}", self.ring_freq.get()),
+			49 => LfoShape::from_index(self.ring_shape.load(Ordering::Relaxed)).name().to_string(),
+			50 => format!("{:+.1}", self.shift_hz.get()),
+			51 => format!("{:.2}", self.shift_feedback.get()),
+			52 => format!("{}", self.phaser_stages.load(Ordering::Relaxed)),
+			53 => format!("{:.2}", self.phaser_rate.get()),
+			54 => format!("{:.2}", self.phaser_depth.get()),
+			55 => format!("{:.0}", self.phaser_center.get()),
+			56 => format!("{:.2}", self.phaser_feedback.get()),
+			57 => format!("{:.0}", self.phaser_spread.get() * 360.0),
+			58 => format!("{:+.2}", self.pv_semitones.get()),
+			59 => if self.pv_formant.load(Ordering::Relaxed) { "on" } else { "off" }.into(),
+			60 => if self.pv_transient.load(Ordering::Relaxed) { "on" } else { "off" }.into(),
+			61 => if self.freeze.load(Ordering::Relaxed) { "on" } else { "off" }.into(),
+			62 => format!("{:.2}", self.freeze_blur.get()),
+			63 => format!("{:.2}", self.freeze_mix.get()),
+			64 => TapeSpeed::from_index(self.tape_speed.load(Ordering::Relaxed)).name().to_string(),
+			65 => format!("{:.1}", self.tape_drive.get()),
+			66 => format!("{:.2}", self.tape_wow.get()),
+			67 => format!("{:.2}", self.tape_flutter.get()),
+			68 => format!("{:.1}", self.tape_hiss.get()),
+			69 => format!("{}", self.chorus_voices.load(Ordering::Relaxed)),
+			70 => format!("{:.2}", self.chorus_rate.get()),
+			71 => format!("{:.2}", self.chorus_depth.get() * 1000.0),
+			72 => format!("{:.2}", self.chorus_detune.get()),
+			73 => format!("{:.2}", self.chorus_width.get()),
+			74 => format!("{:.2}", self.chorus_mix.get()),
+			75 => format!("{:.2}", self.flanger_delay.get() * 1000.0),
+			76 => format!("{:.2}", self.flanger_depth.get() * 1000.0),
+			77 => format!("{:.2}", self.flanger_rate.get()),
+			78 => format!("{:+.2}", self.flanger_feedback.get()),
+			79 => if self.flanger_tz.load(Ordering::Relaxed) { "on" } else { "off" }.into(),
+			80 => format!("{:.2}", self.flanger_mix.get()),
+			81 => format!("{:.2}", self.reverb_size.get()),
+			82 => format!("{:.2}", self.reverb_decay.get()),
+			83 => format!("{:.1}", self.reverb_predelay.get() * 1000.0),
+			84 => format!("{:.0}", self.reverb_damping.get()),
+			85 => format!("{:.2}", self.reverb_diffusion.get()),
+			86 => format!("{:.2}", self.reverb_mod.get()),
+			87 => format!("{:.2}", self.reverb_width.get()),
+			88 => if self.reverb_freeze.load(Ordering::Relaxed) { "on" } else { "off" }.into(),
+			89 => format!("{:.2}", self.reverb_mix.get()),
+			90 => format!("{:.0}", self.imager_width.get() * 100.0),
+			91 => if self.imager_mono.load(Ordering::Relaxed) { "on" } else { "off" }.into(),
+			92 => format!("{:.0}", self.imager_mono_freq.get()),
+			93 => format!("{:+.1}", self.imager_haas.get() * 1000.0),
+			94 => format!("{:+.2}", self.imager_balance.get()),
+			95 => if self.imager_swap.load(Ordering::Relaxed) { "on" } else { "off" }.into(),
+			96 => if self.imager_invert_l.load(Ordering::Relaxed) { "on" } else { "off" }.into(),
+			97 => if self.imager_invert_r.load(Ordering::Relaxed) { "on" } else { "off" }.into(),
+			98 => format!("{:.2}", self.tremolo_rate.get()),
+			99 => format!("{:.2}", self.tremolo_depth.get()),
+			100 => LfoShape::from_index(self.tremolo_shape.load(Ordering::Relaxed)).name().to_string(),
+			101 => format!("{:.1}", self.tremolo_smooth.get() * 1000.0),
+			102 => format!("{:.0}", self.tremolo_offset.get() * 360.0),
+			103 => if self.tremolo_sync.load(Ordering::Relaxed) { "on" } else { "off" }.into(),
+			104 => NoteDivision::from_index(self.tremolo_division.load(Ordering::Relaxed)).name().to_string(),
+			105 => format!("{}", self.ks_count.load(Ordering::Relaxed)),
+			106 => format!("{:.2}", self.ks_decay.get()),
+			107 => format!("{:.2}", self.ks_damping.get()),
+			108 => format!("{:.0}", self.ks_brightness.get()),
+			109 => if self.ks_midi.load(Ordering::Relaxed) { "on" } else { "off" }.into(),
+			110 => format!("{:.2}", self.ks_mix.get()),
+			111..=118 => resonator::note_name(self.ks_notes[i as usize - 111].load(Ordering::Relaxed)),
+			119 => format!("{}", self.vocoder_bands.load(Ordering::Relaxed)),
+			120 => format!("{:+.1}", self.vocoder_shift.get()),
+			121 => if self.vocoder_internal.load(Ordering::Relaxed) { "internal" } else { "sidechain" }.into(),
+			122 => format!("{:.1}", self.vocoder_pitch.get()),
+			123 => format!("{:.2}", self.vocoder_noise.get()),
+			124 => format!("{:.2}", self.vocoder_sibilance.get()),
+			125 => format!("{:.2}", self.vocoder_mix.get()),
+			126 => format!("{:.2} {}", self.formant_vowel.get(), formant::vowel_name(self.formant_vowel.get())),
+			127 => if self.formant_female.load(Ordering::Relaxed) { "female" } else { "male" }.into(),
+			128 => format!("{:.2}", self.formant_lfo_rate.get()),
+			129 => format!("{:.2}", self.formant_lfo_depth.get()),
+			130 => format!("{:+.2}", self.formant_env.get()),
+			131 => format!("{:.2}", self.formant_mix.get()),
+			132 => if self.wah.load(Ordering::Relaxed) { "on" } else { "off" }.into(),
+			133 => if self.wah_lowpass.load(Ordering::Relaxed) { "lowpass" } else { "bandpass" }.into(),
+			134 => format!("{:.0}", self.wah_min.get()),
+			135 => format!("{:.0}", self.wah_max.get()),
+			136 => format!("{:.1}", self.wah_sensitivity.get()),
+			137 => format!("{:.1}", self.wah_attack.get()),
+			138 => format!("{:.0}", self.wah_release.get()),
+			139 => if self.wah_down.load(Ordering::Relaxed) { "down" } else { "up" }.into(),
+			140 => format!("{:.2}", self.wah_resonance.get()),
+			141..=143 => DynEqKind::from_index(self.dyneq_kind[i as usize - 141].load(Ordering::Relaxed)).name().to_string(),
+			144..=146 => format!("{:.0}", self.dyneq_freq[i as usize - 144].get()),
+			147..=149 => format!("{:.2}", self.dyneq_q[i as usize - 147].get()),
+			150..=152 => format!("{:.1}", self.dyneq_threshold[i as usize - 150].get()),
+			153..=155 => format!("{:.1}", self.dyneq_ratio[i as usize - 153].get()),
+			156..=158 => format!("{:.1}", self.dyneq_attack[i as usize - 156].get()),
+			159..=161 => format!("{:.0}", self.dyneq_release[i as usize - 159].get()),
+			162 => if self.dyneq_deess.load(Ordering::Relaxed) { "on" } else { "off" }.into(),
+			163 => format!("{:.0}", self.deess_freq.get()),
+			164 => format!("{:.1}", self.deess_threshold.get()),
+			165 => format!("{:.1}", self.deess_range.get()),
+			166 => if self.deess_wideband.load(Ordering::Relaxed) { "wideband" } else { "split" }.into(),
+			167 => match self.dyneq_listen.load(Ordering::Relaxed) {
+				0 => "off".into(),
+				band => format!("band {}", band),
+			},
+			168..=175 => format!("{:+.2}", self.kernel_taps[i as usize - 168].get()),
+			176 => EffectKind::from_index(self.effect.load(Ordering::Relaxed)).name().to_string(),
+			177 => if self.phaser_second_order.load(Ordering::Relaxed) { "2nd" } else { "1st" }.into(),
 			_ => "0.0".into(),
 		}
 	}
@@ -165,10 +866,183 @@ impl PluginParameters for AndrewParams {
 	fn get_parameter_name( &self, i: i32 ) -> String {
 		match i {
 			0 => "dry_wet",
-			1 => "slew",
+			1 => "slew_rise",
 			2 => "delay_time",
 			3 => "delay_feedback",
 			4 => "cutoff",
+			5 => "kernel",
+			6 => "leak_freq",
+			7 => "calculus_gain",
+			8 => "slew_fall",
+			9 => "slew_db",
+			10 => "slew_link",
+			11 => "accel_slope",
+			12 => "accel_limit",
+			13 => "mp3_bitrate",
+			14 => "mp3_quality",
+			15 => "codec_kbps",
+			16 => "codec_bandwidth",
+			17 => "codec_pre_echo",
+			18 => "codec_warble",
+			19 => "crush_bits",
+			20 => "crush_rate",
+			21 => "crush_dither",
+			22 => "crush_aa",
+			23 => "crush_jitter",
+			24 => "comp_threshold",
+			25 => "comp_ratio",
+			26 => "comp_knee",
+			27 => "comp_attack",
+			28 => "comp_release",
+			29 => "comp_makeup",
+			30 => "comp_rms",
+			31 => "comp_link",
+			32 => "comp_sc_hpf",
+			33 => "comp_external",
+			34 => "gate_threshold",
+			35 => "gate_range",
+			36 => "gate_ratio",
+			37 => "gate_attack",
+			38 => "gate_hold",
+			39 => "gate_release",
+			40 => "gate_hysteresis",
+			41 => "gate_key_lo",
+			42 => "gate_key_hi",
+			43 => "trans_attack",
+			44 => "trans_sustain",
+			45 => "trans_sensitivity",
+			46 => "trans_clip",
+			47 => "ring_shift",
+			48 => "ring_freq",
+			49 => "ring_shape",
+			50 => "shift_hz",
+			51 => "shift_feedback",
+			52 => "phaser_stages",
+			53 => "phaser_rate",
+			54 => "phaser_depth",
+			55 => "phaser_center",
+			56 => "phaser_feedback",
+			57 => "phaser_spread",
+			58 => "pv_semitones",
+			59 => "pv_formant",
+			60 => "pv_transient",
+			61 => "freeze",
+			62 => "freeze_blur",
+			63 => "freeze_mix",
+			64 => "tape_speed",
+			65 => "tape_drive",
+			66 => "tape_wow",
+			67 => "tape_flutter",
+			68 => "tape_hiss",
+			69 => "chorus_voices",
+			70 => "chorus_rate",
+			71 => "chorus_depth",
+			72 => "chorus_detune",
+			73 => "chorus_width",
+			74 => "chorus_mix",
+			75 => "flanger_delay",
+			76 => "flanger_depth",
+			77 => "flanger_rate",
+			78 => "flanger_feedback",
+			79 => "flanger_tz",
+			80 => "flanger_mix",
+			81 => "reverb_size",
+			82 => "reverb_decay",
+			83 => "reverb_predelay",
+			84 => "reverb_damping",
+			85 => "reverb_diffusion",
+			86 => "reverb_mod",
+			87 => "reverb_width",
+			88 => "reverb_freeze",
+			89 => "reverb_mix",
+			90 => "imager_width",
+			91 => "imager_mono",
+			92 => "imager_mono_freq",
+			93 => "imager_haas",
+			94 => "imager_balance",
+			95 => "imager_swap",
+			96 => "imager_invert_l",
+			97 => "imager_invert_r",
+			98 => "tremolo_rate",
+			99 => "tremolo_depth",
+			100 => "tremolo_shape",
+			101 => "tremolo_smooth",
+			102 => "tremolo_offset",
+			103 => "tremolo_sync",
+			104 => "tremolo_division",
+			105 => "ks_count",
+			106 => "ks_decay",
+			107 => "ks_damping",
+			108 => "ks_brightness",
+			109 => "ks_midi",
+			110 => "ks_mix",
+			111 => "ks_note_1",
+			112 => "ks_note_2",
+			113 => "ks_note_3",
+			114 => "ks_note_4",
+			115 => "ks_note_5",
+			116 => "ks_note_6",
+			117 => "ks_note_7",
+			118 => "ks_note_8",
+			119 => "vocoder_bands",
+			120 => "vocoder_shift",
+			121 => "vocoder_carrier",
+			122 => "vocoder_pitch",
+			123 => "vocoder_noise",
+			124 => "vocoder_sibilance",
+			125 => "vocoder_mix",
+			126 => "formant_vowel",
+			127 => "formant_voice",
+			128 => "formant_lfo_rate",
+			129 => "formant_lfo_depth",
+			130 => "formant_env",
+			131 => "formant_mix",
+			132 => "wah",
+			133 => "wah_mode",
+			134 => "wah_min",
+			135 => "wah_max",
+			136 => "wah_sensitivity",
+			137 => "wah_attack",
+			138 => "wah_release",
+			139 => "wah_direction",
+			140 => "wah_resonance",
+			141 => "dyneq_kind_1",
+			142 => "dyneq_kind_2",
+			143 => "dyneq_kind_3",
+			144 => "dyneq_freq_1",
+			145 => "dyneq_freq_2",
+			146 => "dyneq_freq_3",
+			147 => "dyneq_q_1",
+			148 => "dyneq_q_2",
+			149 => "dyneq_q_3",
+			150 => "dyneq_threshold_1",
+			151 => "dyneq_threshold_2",
+			152 => "dyneq_threshold_3",
+			153 => "dyneq_ratio_1",
+			154 => "dyneq_ratio_2",
+			155 => "dyneq_ratio_3",
+			156 => "dyneq_attack_1",
+			157 => "dyneq_attack_2",
+			158 => "dyneq_attack_3",
+			159 => "dyneq_release_1",
+			160 => "dyneq_release_2",
+			161 => "dyneq_release_3",
+			162 => "dyneq_deess",
+			163 => "deess_freq",
+			164 => "deess_threshold",
+			165 => "deess_range",
+			166 => "deess_mode",
+			167 => "dyneq_listen",
+			168 => "kernel_tap_1",
+			169 => "kernel_tap_2",
+			170 => "kernel_tap_3",
+			171 => "kernel_tap_4",
+			172 => "kernel_tap_5",
+			173 => "kernel_tap_6",
+			174 => "kernel_tap_7",
+			175 => "kernel_tap_8",
+			176 => "effect",
+			177 => "phaser_order",
 			_ => "",
 		}.into()
 	}
@@ -176,26 +1050,339 @@ impl PluginParameters for AndrewParams {
 	fn set_parameter(&self, i: i32, val: f32) {
         match i {
 			0 => self.dry_wet.set(val),
-			1 => self.slew.set(20_f32 * 1000_f32.powf(val)),
+			1 => self.slew_rise.set(20_f32 * 1000_f32.powf(val)),
 			2 => self.delay_time.set(1.0 - val * 0.99),
 			3 => self.delay_feedback.set(val),
 			4 => self.cutoff.set(val * 0.99 + 0.01),
+			5 => self.kernel_shape.store((val * (KernelShape::COUNT - 1) as f32).round() as u8, Ordering::Relaxed),
+			6 => self.leak_freq.set(200_f32.powf(val)),
+			7 => self.calculus_gain.set(val * 48.0 - 24.0),
+			8 => self.slew_fall.set(20_f32 * 1000_f32.powf(val)),
+			9 => self.slew_db.store(val > 0.5, Ordering::Relaxed),
+			10 => self.slew_link.store(val > 0.5, Ordering::Relaxed),
+			11 => self.accel_slope.set(20_f32 * 1000_f32.powf(val)),
+			12 => self.accel_limit.set(1e4_f32 * 1e6_f32.powf(val)),
+			13 | 14 if !cfg!(feature = "mp3") => (),
+			13 => self.mp3_bitrate.set(MP3_BITRATES[(val * (MP3_BITRATES.len() - 1) as f32).round() as usize]),
+			14 => self.mp3_quality.store((val * 9.0).round() as u8, Ordering::Relaxed),
+			15 => self.codec_kbps.set(8_f32 * 40_f32.powf(val)),
+			16 => self.codec_bandwidth.set(1000_f32 * 20_f32.powf(val)),
+			17 => self.codec_pre_echo.store(val > 0.5, Ordering::Relaxed),
+			18 => self.codec_warble.set(val),
+			19 => self.crush_bits.set(1.0 + val * 23.0),
+			20 => self.crush_rate.set(100_f32 * 1920_f32.powf(val)),
+			21 => self.crush_dither.store(val > 0.5, Ordering::Relaxed),
+			22 => self.crush_aa.store(val > 0.5, Ordering::Relaxed),
+			23 => self.crush_jitter.set(val),
+			24 => self.comp_threshold.set(val * 60.0 - 60.0),
+			25 => self.comp_ratio.set(20_f32.powf(val)),
+			26 => self.comp_knee.set(val * 24.0),
+			27 => self.comp_attack.set(0.1 * 1000_f32.powf(val)),
+			28 => self.comp_release.set(10.0 * 200_f32.powf(val)),
+			29 => self.comp_makeup.set(val * 24.0),
+			30 => self.comp_rms.store(val > 0.5, Ordering::Relaxed),
+			31 => self.comp_link.store(val > 0.5, Ordering::Relaxed),
+			32 => self.comp_sc_hpf.set(20.0 * 50_f32.powf(val)),
+			33 => self.comp_external.store(val > 0.5, Ordering::Relaxed),
+			34 => self.gate_threshold.set(val * 80.0 - 80.0),
+			35 => self.gate_range.set(val * 80.0),
+			36 => self.gate_ratio.set(50_f32.powf(val)),
+			37 => self.gate_attack.set(0.01 * 5000_f32.powf(val)),
+			38 => self.gate_hold.set(val * 500.0),
+			39 => self.gate_release.set(5.0 * 400_f32.powf(val)),
+			40 => self.gate_hysteresis.set(val * 12.0),
+			41 => self.gate_key_lo.set(20.0 * 100_f32.powf(val)),
+			42 => self.gate_key_hi.set(1000.0 * 20_f32.powf(val)),
+			43 => self.trans_attack.set(val * 48.0 - 24.0),
+			44 => self.trans_sustain.set(val * 48.0 - 24.0),
+			45 => self.trans_sensitivity.set(0.25 * 16_f32.powf(val)),
+			46 => self.trans_clip.set(val * 24.0 - 24.0),
+			47 => self.ring_shift.store(val > 0.5, Ordering::Relaxed),
+			48 => self.ring_freq.set(0.1 * 50000_f32.powf(val)),
+			49 => self.ring_shape.store((val * (LfoShape::COUNT - 1) as f32).round() as u8, Ordering::Relaxed),
+			50 => self.shift_hz.set(2000.0 * (val * 2.0 - 1.0).powi(3)),
+			51 => self.shift_feedback.set(val),
+			52 => self.phaser_stages.store(2 + (val * 10.0).round() as u8, Ordering::Relaxed),
+			53 => self.phaser_rate.set(0.02 * 500_f32.powf(val)),
+			54 => self.phaser_depth.set(val),
+			55 => self.phaser_center.set(100.0 * 100_f32.powf(val)),
+			56 => self.phaser_feedback.set(val * 1.8 - 0.9),
+			57 => self.phaser_spread.set(val * 0.5),
+			58 => self.pv_semitones.set(val * 48.0 - 24.0),
+			59 => self.pv_formant.store(val > 0.5, Ordering::Relaxed),
+			60 => self.pv_transient.store(val > 0.5, Ordering::Relaxed),
+			61 => self.freeze.store(val > 0.5, Ordering::Relaxed),
+			62 => self.freeze_blur.set(val),
+			63 => self.freeze_mix.set(val),
+			64 => self.tape_speed.store((val * (TapeSpeed::COUNT - 1) as f32).round() as u8, Ordering::Relaxed),
+			65 => self.tape_drive.set(val * 36.0 - 12.0),
+			66 => self.tape_wow.set(val),
+			67 => self.tape_flutter.set(val),
+			68 => self.tape_hiss.set(val * 60.0 - 90.0),
+			69 => self.chorus_voices.store(1 + (val * 7.0).round() as u8, Ordering::Relaxed),
+			70 => self.chorus_rate.set(0.05 * 100_f32.powf(val)),
+			71 => self.chorus_depth.set(val * 0.008),
+			72 => self.chorus_detune.set(val),
+			73 => self.chorus_width.set(val),
+			74 => self.chorus_mix.set(val),
+			75 => self.flanger_delay.set(0.0001 * 100_f32.powf(val)),
+			76 => self.flanger_depth.set(val * 0.005),
+			77 => self.flanger_rate.set(0.02 * 500_f32.powf(val)),
+			78 => self.flanger_feedback.set(val * 1.9 - 0.95),
+			79 => self.flanger_tz.store(val > 0.5, Ordering::Relaxed),
+			80 => self.flanger_mix.set(val),
+			81 => self.reverb_size.set(0.25 * 8_f32.powf(val)),
+			82 => self.reverb_decay.set(0.1 * 200_f32.powf(val)),
+			83 => self.reverb_predelay.set(val * 0.25),
+			84 => self.reverb_damping.set(1000.0 * 20_f32.powf(val)),
+			85 => self.reverb_diffusion.set(val),
+			86 => self.reverb_mod.set(val),
+			87 => self.reverb_width.set(val),
+			88 => self.reverb_freeze.store(val > 0.5, Ordering::Relaxed),
+			89 => self.reverb_mix.set(val),
+			90 => self.imager_width.set(val * 2.0),
+			91 => self.imager_mono.store(val > 0.5, Ordering::Relaxed),
+			92 => self.imager_mono_freq.set(20.0 * 25_f32.powf(val)),
+			93 => self.imager_haas.set((val * 2.0 - 1.0) * 0.03),
+			94 => self.imager_balance.set(val * 2.0 - 1.0),
+			95 => self.imager_swap.store(val > 0.5, Ordering::Relaxed),
+			96 => self.imager_invert_l.store(val > 0.5, Ordering::Relaxed),
+			97 => self.imager_invert_r.store(val > 0.5, Ordering::Relaxed),
+			98 => self.tremolo_rate.set(0.05 * 400_f32.powf(val)),
+			99 => self.tremolo_depth.set(val),
+			100 => self.tremolo_shape.store((val * (LfoShape::COUNT - 1) as f32).round() as u8, Ordering::Relaxed),
+			101 => self.tremolo_smooth.set(0.0005 * 40_f32.powf(val)),
+			102 => self.tremolo_offset.set(val),
+			103 => self.tremolo_sync.store(val > 0.5, Ordering::Relaxed),
+			104 => self.tremolo_division.store((val * (NoteDivision::COUNT - 1) as f32).round() as u8, Ordering::Relaxed),
+			105 => self.ks_count.store(1 + (val * 7.0).round() as u8, Ordering::Relaxed),
+			106 => self.ks_decay.set(0.05 * 400_f32.powf(val)),
+			107 => self.ks_damping.set(val * 0.95),
+			108 => self.ks_brightness.set(200.0 * 100_f32.powf(val)),
+			109 => self.ks_midi.store(val > 0.5, Ordering::Relaxed),
+			110 => self.ks_mix.set(val),
+			111..=118 => self.ks_notes[i as usize - 111].store((val * 127.0).round() as u8, Ordering::Relaxed),
+			119 => self.vocoder_bands.store(8 + (val * 24.0).round() as u8, Ordering::Relaxed),
+			120 => self.vocoder_shift.set(val * 24.0 - 12.0),
+			121 => self.vocoder_internal.store(val > 0.5, Ordering::Relaxed),
+			122 => self.vocoder_pitch.set(40.0 * 25_f32.powf(val)),
+			123 => self.vocoder_noise.set(val),
+			124 => self.vocoder_sibilance.set(val),
+			125 => self.vocoder_mix.set(val),
+			126 => self.formant_vowel.set(val),
+			127 => self.formant_female.store(val > 0.5, Ordering::Relaxed),
+			128 => self.formant_lfo_rate.set(0.05 * 400_f32.powf(val)),
+			129 => self.formant_lfo_depth.set(val),
+			130 => self.formant_env.set(val * 2.0 - 1.0),
+			131 => self.formant_mix.set(val),
+			132 => self.wah.store(val > 0.5, Ordering::Relaxed),
+			133 => self.wah_lowpass.store(val > 0.5, Ordering::Relaxed),
+			134 => self.wah_min.set(50.0 * 40_f32.powf(val)),
+			135 => self.wah_max.set(200.0 * 100_f32.powf(val)),
+			136 => self.wah_sensitivity.set(val * 48.0),
+			137 => self.wah_attack.set(0.5 * 100_f32.powf(val)),
+			138 => self.wah_release.set(10.0 * 100_f32.powf(val)),
+			139 => self.wah_down.store(val > 0.5, Ordering::Relaxed),
+			140 => self.wah_resonance.set(0.5 * 40_f32.powf(val)),
+			141..=143 => self.dyneq_kind[i as usize - 141].store((val * (DynEqKind::COUNT - 1) as f32).round() as u8, Ordering::Relaxed),
+			144..=146 => self.dyneq_freq[i as usize - 144].set(20.0 * 1000_f32.powf(val)),
+			147..=149 => self.dyneq_q[i as usize - 147].set(0.3 * 50_f32.powf(val)),
+			150..=152 => self.dyneq_threshold[i as usize - 150].set(val * 60.0 - 60.0),
+			153..=155 => self.dyneq_ratio[i as usize - 153].set(20_f32.powf(val)),
+			156..=158 => self.dyneq_attack[i as usize - 156].set(0.1 * 1000_f32.powf(val)),
+			159..=161 => self.dyneq_release[i as usize - 159].set(10.0 * 200_f32.powf(val)),
+			162 => self.dyneq_deess.store(val > 0.5, Ordering::Relaxed),
+			163 => self.deess_freq.set(2000.0 * 6_f32.powf(val)),
+			164 => self.deess_threshold.set(val * 60.0 - 60.0),
+			165 => self.deess_range.set(val * 24.0),
+			166 => self.deess_wideband.store(val > 0.5, Ordering::Relaxed),
+			167 => self.dyneq_listen.store((val * DYNEQ_BANDS as f32).round() as u8, Ordering::Relaxed),
+			168..=175 => self.kernel_taps[i as usize - 168].set(val * 2.0 - 1.0),
+			176 => {
+				let effect = (val * (EffectKind::COUNT - 1) as f32).round() as u8;
+				if self.effect.swap(effect, Ordering::Relaxed) != effect {
+					self.build_chain();
+				}
+			},
+			177 => self.phaser_second_order.store(val > 0.5, Ordering::Relaxed),
             _ => (),
         }
 		self.updated.store(true, Ordering::Relaxed);
+		self.chains.collect();
     }
 }
 
+impl AndrewParams {
+	// builds the chain for the current effect here, away from the audio
+	// thread, which picks it up at the start of its next block
+	fn build_chain( &self ) {
+		let chain = Chain::build(EffectKind::from_index(self.effect.load(Ordering::Relaxed)), self.this.clone());
+		report_latency(&self.host, chain.latency);
+		self.chains.offer(chain);
+	}
+}
+
 impl Default for AndrewParams {
 	fn default() -> Self {
 		AndrewParams {
+			this: Weak::new(),
+			host: HostCallback::default(),
+			chains: ChainSwap::default(),
 			updated: AtomicBool::new(true),
 			sample_rate: AtomicFloat::new(44100.0),
+			effect: AtomicU8::new(0),
 			dry_wet: AtomicFloat::new(1.0),
-			slew: AtomicFloat::new(20_f32 * 1000_f32.powf(1.0)),
+			slew_rise: AtomicFloat::new(20_f32 * 1000_f32.powf(1.0)),
 			delay_time: AtomicFloat::new(0.01),
 			delay_feedback: AtomicFloat::new(1.0),
 			cutoff: AtomicFloat::new(1.0),
+			kernel_shape: AtomicU8::new(0),
+			// a short triangle
+			kernel_taps: [0.25, 0.5, 0.75, 1.0, 1.0, 0.75, 0.5, 0.25].map(AtomicFloat::new),
+			leak_freq: AtomicFloat::new(20.0),
+			calculus_gain: AtomicFloat::new(0.0),
+			slew_fall: AtomicFloat::new(20_f32 * 1000_f32.powf(1.0)),
+			slew_db: AtomicBool::new(false),
+			slew_link: AtomicBool::new(false),
+			accel_slope: AtomicFloat::new(20_f32 * 1000_f32.powf(1.0)),
+			accel_limit: AtomicFloat::new(1e4_f32 * 1e6_f32.powf(1.0)),
+			mp3_bitrate: AtomicFloat::new(32.0),
+			mp3_quality: AtomicU8::new(9),
+			codec_kbps: AtomicFloat::new(64.0),
+			codec_bandwidth: AtomicFloat::new(16000.0),
+			codec_pre_echo: AtomicBool::new(false),
+			codec_warble: AtomicFloat::new(0.0),
+			crush_bits: AtomicFloat::new(24.0),
+			crush_rate: AtomicFloat::new(192000.0),
+			crush_dither: AtomicBool::new(false),
+			crush_aa: AtomicBool::new(true),
+			crush_jitter: AtomicFloat::new(0.0),
+			comp_threshold: AtomicFloat::new(-12.0),
+			comp_ratio: AtomicFloat::new(4.0),
+			comp_knee: AtomicFloat::new(6.0),
+			comp_attack: AtomicFloat::new(10.0),
+			comp_release: AtomicFloat::new(100.0),
+			comp_makeup: AtomicFloat::new(0.0),
+			comp_rms: AtomicBool::new(false),
+			comp_link: AtomicBool::new(true),
+			comp_sc_hpf: AtomicFloat::new(20.0),
+			comp_external: AtomicBool::new(false),
+			gate_threshold: AtomicFloat::new(-50.0),
+			gate_range: AtomicFloat::new(40.0),
+			gate_ratio: AtomicFloat::new(50.0),
+			gate_attack: AtomicFloat::new(1.0),
+			gate_hold: AtomicFloat::new(50.0),
+			gate_release: AtomicFloat::new(100.0),
+			gate_hysteresis: AtomicFloat::new(4.0),
+			gate_key_lo: AtomicFloat::new(20.0),
+			gate_key_hi: AtomicFloat::new(20000.0),
+			trans_attack: AtomicFloat::new(0.0),
+			trans_sustain: AtomicFloat::new(0.0),
+			trans_sensitivity: AtomicFloat::new(1.0),
+			trans_clip: AtomicFloat::new(0.0),
+			ring_shift: AtomicBool::new(false),
+			ring_freq: AtomicFloat::new(440.0),
+			ring_shape: AtomicU8::new(0),
+			shift_hz: AtomicFloat::new(0.0),
+			shift_feedback: AtomicFloat::new(0.0),
+			phaser_stages: AtomicU8::new(4),
+			phaser_rate: AtomicFloat::new(0.5),
+			phaser_depth: AtomicFloat::new(0.5),
+			phaser_center: AtomicFloat::new(800.0),
+			phaser_feedback: AtomicFloat::new(0.0),
+			phaser_spread: AtomicFloat::new(0.0),
+			phaser_second_order: AtomicBool::new(false),
+			pv_semitones: AtomicFloat::new(0.0),
+			pv_formant: AtomicBool::new(false),
+			pv_transient: AtomicBool::new(true),
+			freeze: AtomicBool::new(false),
+			freeze_blur: AtomicFloat::new(0.0),
+			freeze_mix: AtomicFloat::new(1.0),
+			tape_speed: AtomicU8::new(1),
+			tape_drive: AtomicFloat::new(0.0),
+			tape_wow: AtomicFloat::new(0.3),
+			tape_flutter: AtomicFloat::new(0.3),
+			tape_hiss: AtomicFloat::new(-70.0),
+			chorus_voices: AtomicU8::new(3),
+			chorus_rate: AtomicFloat::new(0.8),
+			chorus_depth: AtomicFloat::new(0.003),
+			chorus_detune: AtomicFloat::new(0.3),
+			chorus_width: AtomicFloat::new(0.7),
+			chorus_mix: AtomicFloat::new(0.5),
+			flanger_delay: AtomicFloat::new(0.001),
+			flanger_depth: AtomicFloat::new(0.002),
+			flanger_rate: AtomicFloat::new(0.25),
+			flanger_feedback: AtomicFloat::new(0.5),
+			flanger_tz: AtomicBool::new(false),
+			flanger_mix: AtomicFloat::new(0.5),
+			reverb_size: AtomicFloat::new(1.0),
+			reverb_decay: AtomicFloat::new(2.0),
+			reverb_predelay: AtomicFloat::new(0.01),
+			reverb_damping: AtomicFloat::new(8000.0),
+			reverb_diffusion: AtomicFloat::new(0.7),
+			reverb_mod: AtomicFloat::new(0.3),
+			reverb_width: AtomicFloat::new(1.0),
+			reverb_freeze: AtomicBool::new(false),
+			reverb_mix: AtomicFloat::new(0.3),
+			imager_width: AtomicFloat::new(1.0),
+			imager_mono: AtomicBool::new(false),
+			imager_mono_freq: AtomicFloat::new(120.0),
+			imager_haas: AtomicFloat::new(0.0),
+			imager_balance: AtomicFloat::new(0.0),
+			imager_swap: AtomicBool::new(false),
+			imager_invert_l: AtomicBool::new(false),
+			imager_invert_r: AtomicBool::new(false),
+			tremolo_rate: AtomicFloat::new(4.0),
+			tremolo_depth: AtomicFloat::new(0.5),
+			tremolo_shape: AtomicU8::new(0),
+			tremolo_smooth: AtomicFloat::new(0.002),
+			tremolo_offset: AtomicFloat::new(0.0),
+			tremolo_sync: AtomicBool::new(false),
+			tremolo_division: AtomicU8::new(3),
+			ks_count: AtomicU8::new(4),
+			ks_decay: AtomicFloat::new(2.0),
+			ks_damping: AtomicFloat::new(0.3),
+			ks_brightness: AtomicFloat::new(4000.0),
+			ks_midi: AtomicBool::new(false),
+			ks_mix: AtomicFloat::new(0.5),
+			// a c major spread over two octaves
+			ks_notes: [48, 55, 60, 64, 67, 72, 76, 79].map(AtomicU8::new),
+			vocoder_bands: AtomicU8::new(16),
+			vocoder_shift: AtomicFloat::new(0.0),
+			vocoder_internal: AtomicBool::new(false),
+			vocoder_pitch: AtomicFloat::new(110.0),
+			vocoder_noise: AtomicFloat::new(0.1),
+			vocoder_sibilance: AtomicFloat::new(0.5),
+			vocoder_mix: AtomicFloat::new(1.0),
+			formant_vowel: AtomicFloat::new(0.0),
+			formant_female: AtomicBool::new(false),
+			formant_lfo_rate: AtomicFloat::new(0.5),
+			formant_lfo_depth: AtomicFloat::new(0.0),
+			formant_env: AtomicFloat::new(0.0),
+			formant_mix: AtomicFloat::new(1.0),
+			wah: AtomicBool::new(false),
+			wah_lowpass: AtomicBool::new(false),
+			wah_min: AtomicFloat::new(300.0),
+			wah_max: AtomicFloat::new(2500.0),
+			wah_sensitivity: AtomicFloat::new(18.0),
+			wah_attack: AtomicFloat::new(5.0),
+			wah_release: AtomicFloat::new(120.0),
+			wah_down: AtomicBool::new(false),
+			wah_resonance: AtomicFloat::new(4.0),
+			// lows, mids and highs
+			dyneq_kind: [DynEqKind::LowShelf, DynEqKind::Peak, DynEqKind::HighShelf].map(|kind| AtomicU8::new(kind as u8)),
+			dyneq_freq: [120.0, 1000.0, 6000.0].map(AtomicFloat::new),
+			dyneq_q: [0.7, 1.0, 0.7].map(AtomicFloat::new),
+			dyneq_threshold: [0.0; DYNEQ_BANDS].map(AtomicFloat::new),
+			dyneq_ratio: [2.0; DYNEQ_BANDS].map(AtomicFloat::new),
+			dyneq_attack: [5.0; DYNEQ_BANDS].map(AtomicFloat::new),
+			dyneq_release: [100.0; DYNEQ_BANDS].map(AtomicFloat::new),
+			dyneq_deess: AtomicBool::new(false),
+			deess_freq: AtomicFloat::new(6000.0),
+			deess_threshold: AtomicFloat::new(-30.0),
+			deess_range: AtomicFloat::new(12.0),
+			deess_wideband: AtomicBool::new(false),
+			dyneq_listen: AtomicU8::new(0),
 		}
 	}
 }
@@ -203,9 +1390,31 @@ impl Default for AndrewParams {
 plugin_main!(AndrewVst); // Important!
 
 
+#[cfg(test)]
+mod tests {
+	use super::*;
 
-// #[cfg(test)]
-// mod tests {
-// 	use super::*;
+	// what a host sets is what it reads back, give or take the steps
+	// of a switch or a list
+	#[test]
+	fn params_read_back_normalised() {
+		let params = AndrewParams::default();
+		let count = AndrewVst::default().get_info().parameters;
+		for i in 0..count {
+			if (13..=14).contains(&i) && !cfg!(feature = "mp3") { continue }
+			for val in [0.0, 0.3, 0.5, 0.7, 1.0] {
+				params.set_parameter(i, val);
+				let read = params.get_parameter(i);
+				assert!((0.0..=1.0).contains(&read), "{} reads {} for {}", params.get_parameter_name(i), read, val);
+				assert!((read - val).abs() <= 0.5, "{} reads {} for {}", params.get_parameter_name(i), read, val);
 
-// }
+				// and setting what was read changes nothing
+				params.set_parameter(i, read);
+				assert!((params.get_parameter(i) - read).abs() < 1e-4, "{} moves from {}", params.get_parameter_name(i), read);
+				if val == 0.0 || val == 1.0 {
+					assert!((read - val).abs() < 1e-4, "{} reads {} for {}", params.get_parameter_name(i), read, val);
+				}
+			}
+		}
+	}
+}
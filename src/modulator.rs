@@ -1,5 +1,5 @@
-
 use std::{f32::consts::PI, time::Instant};
+use crate::noise::Noise;
 const TAU : f32 = PI * 2.0;
 
 trait Modulator {
@@ -9,19 +9,124 @@ trait Modulator {
 }
 
 
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LfoShape {
+	Sine,
+	Triangle,
+	Square,
+	Saw,
+	// a fresh random level every cycle, eased into from the last
+	Random,
+}
+
+impl LfoShape {
+	pub const COUNT: u8 = 5;
+
+	pub fn from_index( i: u8 ) -> Self {
+		match i {
+			0 => LfoShape::Sine,
+			1 => LfoShape::Triangle,
+			2 => LfoShape::Square,
+			3 => LfoShape::Saw,
+			_ => LfoShape::Random,
+		}
+	}
+
+	pub fn name( &self ) -> &'static str {
+		match self {
+			LfoShape::Sine => "sine",
+			LfoShape::Triangle => "triangle",
+			LfoShape::Square => "square",
+			LfoShape::Saw => "saw",
+			LfoShape::Random => "random",
+		}
+	}
+
+	// value in -1..1 at a phase in 0..1. random has no value of its own,
+	// its steps are drawn by the Lfo running it
+	#[inline]
+	pub fn at( &self, phase: f32 ) -> f32 {
+		match self {
+			LfoShape::Sine => (phase * TAU).sin(),
+			LfoShape::Triangle => 4.0 * ((phase + 0.75).fract() - 0.5).abs() - 1.0,
+			LfoShape::Square => if phase < 0.5 { 1.0 } else { -1.0 },
+			LfoShape::Saw => 2.0 * phase - 1.0,
+			LfoShape::Random => 0.0,
+		}
+	}
+}
+
+
+// phase accumulator, fast enough to double as an audio rate carrier.
+// a negative frequency runs the phase backwards
+#[derive(Clone)]
 pub struct Lfo { 
 	cached: f32,
-	time: u32,
+	phase: f32,
+	freq: f32,
+	sample_rate: f32,
+	shape: LfoShape,
+	// the random level eased from and the one eased to
+	steps: [f32; 2],
+	noise: Noise,
 }
 
 impl Lfo {
 	pub fn get( &self ) -> f32 {
-		(( self.time as f32 / 44100.0) as f32 * TAU ).sin()
+		match self.shape {
+			LfoShape::Random => {
+				let ease = 0.5 - 0.5 * (PI * self.phase).cos();
+				self.steps[0] + (self.steps[1] - self.steps[0]) * ease
+			}
+			shape => shape.at(self.phase),
+		}
+	}
+
+	// phase in 0..1
+	pub fn phase( &self ) -> f32 {
+		self.phase
+	}
+
+	pub fn set_phase( &mut self, phase: f32 ) {
+		self.phase = phase.rem_euclid(1.0);
 	}
 
 	pub fn forward( &mut self, time: u32 ) {
-		self.time += time;
-		self.time %= 44100;
+		self.advance(self.freq * time as f32 / self.sample_rate);
+	}
+
+	// the current value, then steps on a sample
+	#[inline]
+	pub fn tick( &mut self ) -> f32 {
+		let val = self.get();
+		self.advance(self.freq / self.sample_rate);
+		val
+	}
+
+	// moves the phase on, drawing the next random step each time it wraps
+	#[inline]
+	fn advance( &mut self, step: f32 ) {
+		let phase = self.phase + step;
+		if !(0.0..1.0).contains(&phase) {
+			self.steps = if step > 0.0 {
+				[self.steps[1], self.noise.white()]
+			} else {
+				[self.noise.white(), self.steps[0]]
+			};
+		}
+		self.phase = phase.rem_euclid(1.0);
+	}
+
+	pub fn set_freq( &mut self, freq: f32 ) {
+		self.freq = freq;
+	}
+
+	pub fn set_sample_rate( &mut self, sample_rate: f32 ) {
+		self.sample_rate = sample_rate;
+	}
+
+	pub fn set_shape( &mut self, shape: LfoShape ) {
+		self.shape = shape;
 	}
 }
 
@@ -29,8 +134,82 @@ impl Default for Lfo {
 	fn default() -> Self {
 		Lfo {
 			cached: 0.0,
-			time: 0,
+			phase: 0.0,
+			freq: 1.0,
+			sample_rate: 44100.0,
+			shape: LfoShape::Sine,
+			steps: [0.0; 2],
+			noise: Noise::default(),
 		}
 	}
 }
 
+
+// note lengths an lfo can lock to the host tempo with
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum NoteDivision {
+	Whole,
+	Half,
+	Quarter,
+	Eighth,
+	Sixteenth,
+	ThirtySecond,
+	DottedQuarter,
+	DottedEighth,
+	QuarterTriplet,
+	EighthTriplet,
+	SixteenthTriplet,
+}
+
+impl NoteDivision {
+	pub const COUNT: u8 = 11;
+
+	pub fn from_index( i: u8 ) -> Self {
+		match i {
+			0 => NoteDivision::Whole,
+			1 => NoteDivision::Half,
+			2 => NoteDivision::Quarter,
+			3 => NoteDivision::Eighth,
+			4 => NoteDivision::Sixteenth,
+			5 => NoteDivision::ThirtySecond,
+			6 => NoteDivision::DottedQuarter,
+			7 => NoteDivision::DottedEighth,
+			8 => NoteDivision::QuarterTriplet,
+			9 => NoteDivision::EighthTriplet,
+			_ => NoteDivision::SixteenthTriplet,
+		}
+	}
+
+	pub fn name( &self ) -> &'static str {
+		match self {
+			NoteDivision::Whole => "1/1",
+			NoteDivision::Half => "1/2",
+			NoteDivision::Quarter => "1/4",
+			NoteDivision::Eighth => "1/8",
+			NoteDivision::Sixteenth => "1/16",
+			NoteDivision::ThirtySecond => "1/32",
+			NoteDivision::DottedQuarter => "1/4 d",
+			NoteDivision::DottedEighth => "1/8 d",
+			NoteDivision::QuarterTriplet => "1/4 t",
+			NoteDivision::EighthTriplet => "1/8 t",
+			NoteDivision::SixteenthTriplet => "1/16 t",
+		}
+	}
+
+	// length in quarter notes, what the host counts its position in
+	pub fn quarters( &self ) -> f32 {
+		match self {
+			NoteDivision::Whole => 4.0,
+			NoteDivision::Half => 2.0,
+			NoteDivision::Quarter => 1.0,
+			NoteDivision::Eighth => 0.5,
+			NoteDivision::Sixteenth => 0.25,
+			NoteDivision::ThirtySecond => 0.125,
+			NoteDivision::DottedQuarter => 1.5,
+			NoteDivision::DottedEighth => 0.75,
+			NoteDivision::QuarterTriplet => 2.0 / 3.0,
+			NoteDivision::EighthTriplet => 1.0 / 3.0,
+			NoteDivision::SixteenthTriplet => 1.0 / 6.0,
+		}
+	}
+}
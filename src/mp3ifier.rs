@@ -1,53 +1,332 @@
-
 use lame::Lame;
-use puremp3::{*, self, FrameHeader, Mp3Decoder, decoder::process_frame};
-
-
-use std::{default, io::Read};
-use std::sync::{Arc, Condvar, Mutex, mpsc::channel};
-
-pub struct Mp3ifier<'a> {
-	input_buffer: Rc<(Vec<f32>, Vec<f32>)>,
-	mp3_buffer: Vec<u8>,
-	output_buffer: Rc<(Vec<f32>, Vec<f32>)>,
-	lame: Option<Lame>,
-	decoder: Mp3Decoder<&'a [u8]>,
-	format: FrameHeader,
-}
-
-impl<'a> Mp3ifier<'a> {
-	fn new( kbs: BitRate,  ) -> Self {
-		let mut lame = Lame::new();
-		let mut kbs = kbs;
-		// let format = FrameHeader {
-		// 	bitrate: Kbps24,
-		// 	sample_rate: SampleRate::Hz44100,
-		// 	layer: MpegLayer::Layer3,
-		// 	original: true,
-		// 	crc: false,
-		// 	padding: false,
-		// 	channels: Channels::Stereo,
-		// 	copyright: false,
-		// 	version: MpegVersion::Mpeg2,
-		// 	emphasis: Emphasis::None,
-		// 	data_size: 0,
-		// 	sample_rate_table: 0,
-		// };
-		if let Some(lame) = lame.as_mut() {
-			lame.init_params();
-			lame.set_quality(9);
-			lame.set_kilobitrate(16);
-			lame.set_sample_rate(/*self.sample_rate*/44100);
-		}
-
-		
+use puremp3::Mp3Decoder;
+
+use std::collections::VecDeque;
+use std::io::Cursor;
+use std::sync::{Weak, atomic::Ordering, mpsc::{channel, Receiver, Sender}};
+use std::thread;
+
+use crate::AndrewParams;
+use crate::andrew_effect::AndrewEffect;
+
+// samples handed to the encoder thread at a time, one mpeg-1 frame
+const BLOCK_LEN: usize = 1152;
+
+// lame's encoder delay plus the decoder's synthesis delay, trimmed off
+// the front of every encoder session. counted at the rate of the stream,
+// which lame drops below ours at low bitrates
+const CODEC_DELAY: usize = 576 + 529;
+
+// dry samples the worker will hold for a codec that isn't handing audio
+// back. any more and the output would have run dry anyway
+const MAX_PENDING: usize = MP3_LATENCY - BLOCK_LEN;
+
+// frames in a row the decoder can fail on before the session is given up
+const MAX_DECODE_ERRORS: usize = 8;
+
+// how far the output runs behind the input. has to cover a block, the
+// encoder's lookahead and a large host buffer, or the output runs dry
+pub const MP3_LATENCY: usize = 8 * BLOCK_LEN;
+
+#[derive(Clone, Copy, PartialEq)]
+struct Settings {
+	kbps: i32,
+	quality: u8,
+	sample_rate: u32,
+}
+
+enum Job {
+	Settings(Settings),
+	Block(Vec<[f32; 2]>),
+}
+
+
+// runs the input through lame and back. all of the codec work happens on
+// a worker thread, the audio thread only swaps blocks over channels and
+// plays back from a fifo that is primed with MP3_LATENCY samples
+pub struct Mp3ifier {
+	// block being filled for the encoder
+	input: Vec<[f32; 2]>,
+	// decoded samples waiting to be played
+	output: VecDeque<[f32; 2]>,
+	// samples the output came up short by, dropped once they turn up
+	deficit: usize,
+	// emptied blocks, reused so the audio thread doesn't allocate
+	spare: Vec<Vec<[f32; 2]>>,
+	settings: Settings,
+
+	job_tx: Sender<Job>,
+	done_rx: Receiver<Vec<[f32; 2]>>,
+
+	params: Weak<AndrewParams>,
+}
+
+impl AndrewEffect for Mp3ifier {
+	fn process_stereo(&mut self, in_bufs: [&[f32]; 2], out_bufs: [&mut [f32]; 2]) {
+		let [out_l, out_r] = out_bufs;
+
+		for mut chunk in self.done_rx.try_iter() {
+			let skip = self.deficit.min(chunk.len());
+			self.deficit -= skip;
+			self.output.extend(&chunk[skip..]);
+			chunk.clear();
+			self.spare.push(chunk);
+		}
+
+		for i in 0..in_bufs[0].len() {
+			self.input.push([in_bufs[0][i], in_bufs[1][i]]);
+			if self.input.len() == BLOCK_LEN {
+				let next = self.spare.pop().unwrap_or_else(|| Vec::with_capacity(BLOCK_LEN));
+				let block = std::mem::replace(&mut self.input, next);
+				let _ = self.job_tx.send(Job::Block(block));
+			}
+
+			let [l, r] = match self.output.pop_front() {
+				Some(samp) => samp,
+				None => {
+					self.deficit += 1;
+					[0.0; 2]
+				},
+			};
+			out_l[i] = l;
+			out_r[i] = r;
+		}
+	}
+
+	fn update_params(&mut self) {
+		if let Some(params) = self.params.upgrade() {
+			let settings = Settings {
+				kbps: params.mp3_bitrate.get() as i32,
+				quality: params.mp3_quality.load(Ordering::Relaxed),
+				sample_rate: params.sample_rate.get() as u32,
+			};
+			if settings != self.settings {
+				self.settings = settings;
+				let _ = self.job_tx.send(Job::Settings(settings));
+			}
+		}
+	}
+
+	fn get_latency(&self) -> usize {
+		MP3_LATENCY
+	}
+}
+
+impl Mp3ifier {
+	pub fn new( params: Weak<AndrewParams> ) -> Self {
+		let settings = Settings {
+			kbps: 32,
+			quality: 9,
+			sample_rate: 44100,
+		};
+
+		let (job_tx, job_rx) = channel();
+		let (done_tx, done_rx) = channel();
+		thread::spawn(move || mp3_worker(job_rx, done_tx));
+		let _ = job_tx.send(Job::Settings(settings));
+
+		let mut output = VecDeque::with_capacity(MP3_LATENCY * 2);
+		output.extend(std::iter::repeat_n([0.0; 2], MP3_LATENCY));
+
 		Mp3ifier {
-			input_buffer: (vec![], vec![]), 
-			mp3_buffer: (vec![]),
-			output_buffer: (vec![], vec![]), 
+			input: Vec::with_capacity(BLOCK_LEN),
+			output,
+			deficit: 0,
+			spare: (0..MP3_LATENCY / BLOCK_LEN).map(|_| Vec::with_capacity(BLOCK_LEN)).collect(),
+			settings,
+			job_tx,
+			done_rx,
+			params,
+		}
+	}
+}
+
+
+// hands back one sample for every sample it is given. whatever an encoder
+// session still holds when it is replaced is played back dry, so a change
+// of settings doesn't shift the timing. the same goes for a session that
+// stops handing audio back, say a stream the decoder can't read, after
+// which the dry input carries on as late as the codec's would have been
+fn mp3_worker( jobs: Receiver<Job>, done: Sender<Vec<[f32; 2]>> ) {
+	let mut session: Option<Session> = None;
+	let mut pending_dry: VecDeque<[f32; 2]> = VecDeque::new();
+
+	for job in jobs {
+		match job {
+			Job::Settings(settings) => {
+				session = Session::new(settings);
+				if !pending_dry.is_empty() && done.send(pending_dry.drain(..).collect()).is_err() {
+					break
+				}
+			},
+			Job::Block(mut block) => {
+				pending_dry.extend(&block);
+				if let Some(live) = &mut session {
+					live.run(&block);
+					if live.errors >= MAX_DECODE_ERRORS || pending_dry.len() > MAX_PENDING {
+						session = None;
+					}
+				}
+
+				block.clear();
+				match &mut session {
+					Some(live) => {
+						let n = live.decoded.len().min(pending_dry.len());
+						block.extend(live.decoded.drain(..n));
+						pending_dry.drain(..n);
+					},
+					// no encoder for this sample rate, or it gave up
+					None => block.extend(pending_dry.drain(..)),
+				}
+				if done.send(block).is_err() { break }
+			},
+		}
+	}
+}
+
+
+struct Session {
+	lame: Lame,
+	decoder: Mp3Decoder<Cursor<Vec<u8>>>,
+	// encoded bytes that don't make up a whole frame yet
+	mp3: Vec<u8>,
+	scratch: Vec<u8>,
+	pcm: [Vec<i16>; 2],
+	first_frame: bool,
+	// frames in a row the decoder couldn't read
+	errors: usize,
+	// codec delay still to be trimmed, at our rate. only known
+	// once the first frame says what rate the stream is at
+	trim: Option<usize>,
+
+	// low bitrates make lame drop the sample rate, so the
+	// decoded frames are resampled back to the host's rate
+	sample_rate: u32,
+	phase: f64,
+	prev: [f32; 2],
+
+	decoded: VecDeque<[f32; 2]>,
+}
+
+impl Session {
+	fn new( settings: Settings ) -> Option<Self> {
+		let mut lame = Lame::new()?;
+		lame.set_sample_rate(settings.sample_rate).ok()?;
+		lame.set_channels(2).ok()?;
+		lame.set_quality(settings.quality).ok()?;
+		lame.set_kilobitrate(settings.kbps).ok()?;
+		lame.init_params().ok()?;
+
+		Some(Session {
 			lame,
-			decoder,
-			format,
+			decoder: Mp3Decoder::new(Cursor::new(Vec::new())),
+			mp3: Vec::new(),
+			// worst case size from the lame docs
+			scratch: vec![0; BLOCK_LEN * 5 / 4 + 7200],
+			pcm: [Vec::with_capacity(BLOCK_LEN), Vec::with_capacity(BLOCK_LEN)],
+			first_frame: true,
+			errors: 0,
+			trim: None,
+			sample_rate: settings.sample_rate,
+			phase: 1.0,
+			prev: [0.0; 2],
+			decoded: VecDeque::new(),
+		})
+	}
+
+	fn run( &mut self, block: &[[f32; 2]] ) {
+		for (chan, pcm) in self.pcm.iter_mut().enumerate() {
+			pcm.clear();
+			pcm.extend(block.iter().map(|samp| (samp[chan].clamp(-1.0, 1.0) * i16::MAX as f32) as i16));
+		}
+
+		match self.lame.encode(&self.pcm[0], &self.pcm[1], &mut self.scratch) {
+			Ok(len) => self.mp3.extend_from_slice(&self.scratch[..len]),
+			Err(_) => return,
+		}
+
+		// only whole frames go to the decoder, it can't pick up
+		// where it left off if a frame is cut short
+		while self.mp3.len() >= 4 {
+			let len = match frame_len(&self.mp3) {
+				Some(len) => len,
+				None => {
+					// lost sync, skip forward a byte
+					self.mp3.remove(0);
+					continue
+				},
+			};
+			if len > self.mp3.len() { break }
+
+			let frame: Vec<u8> = self.mp3.drain(..len).collect();
+			// lame reserves the first frame for a xing / info tag, which is
+			// either blank or the tag itself. either way it holds no audio
+			if std::mem::replace(&mut self.first_frame, false)
+				&& (frame[4..].iter().all(|b| *b == 0) || frame.windows(4).any(|w| w == b"Xing" || w == b"Info")) {
+				continue
+			}
+			self.decode(&frame);
 		}
 	}
-}
\ No newline at end of file
+
+	fn decode( &mut self, frame: &[u8] ) {
+		let reader = self.decoder.get_mut();
+		let pos = reader.position() as usize;
+		reader.get_mut().drain(..pos);
+		reader.get_mut().extend_from_slice(frame);
+		reader.set_position(0);
+
+		let frame = match self.decoder.next_frame() {
+			Ok(frame) => frame,
+			Err(_) => {
+				self.errors += 1;
+				return
+			},
+		};
+		self.errors = 0;
+
+		let stream_rate = frame.header.sample_rate.hz() as f64;
+		let step = stream_rate / self.sample_rate as f64;
+		let trim = self.trim.get_or_insert((CODEC_DELAY as f64 / step).round() as usize);
+		for i in 0..frame.num_samples {
+			let cur = [frame.samples[0][i], frame.samples[1][i]];
+			while self.phase <= 1.0 {
+				let t = self.phase as f32;
+				self.phase += step;
+				if *trim > 0 {
+					*trim -= 1;
+					continue
+				}
+				self.decoded.push_back([
+					self.prev[0] + (cur[0] - self.prev[0]) * t,
+					self.prev[1] + (cur[1] - self.prev[1]) * t,
+				]);
+			}
+			self.phase -= 1.0;
+			self.prev = cur;
+		}
+	}
+}
+
+
+// length in bytes of the layer iii frame whose header starts the slice
+fn frame_len( bytes: &[u8] ) -> Option<usize> {
+	const MPEG1_KBPS: [usize; 15] = [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320];
+	const MPEG2_KBPS: [usize; 15] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+	const MPEG1_RATES: [usize; 3] = [44100, 48000, 32000];
+
+	if bytes[0] != 0xff || bytes[1] & 0xe0 != 0xe0 { return None }
+	let version = (bytes[1] >> 3) & 0b11;
+	let layer = (bytes[1] >> 1) & 0b11;
+	let bitrate = (bytes[2] >> 4) as usize;
+	let rate = ((bytes[2] >> 2) & 0b11) as usize;
+	let padding = ((bytes[2] >> 1) & 1) as usize;
+	if version == 1 || layer != 1 || bitrate == 0 || bitrate == 15 || rate == 3 { return None }
+
+	let (kbps, rate, slot) = match version {
+		3 => (MPEG1_KBPS[bitrate], MPEG1_RATES[rate], 144),
+		2 => (MPEG2_KBPS[bitrate], MPEG1_RATES[rate] / 2, 72),
+		_ => (MPEG2_KBPS[bitrate], MPEG1_RATES[rate] / 4, 72),
+	};
+	Some(slot * kbps * 1000 / rate + padding)
+}
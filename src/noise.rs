@@ -0,0 +1,38 @@
+// xorshift noise. cheap, and seeded so renders come out the same every time
+#[derive(Clone)]
+pub struct Noise {
+	state: u32,
+}
+
+impl Noise {
+	pub fn new( seed: u32 ) -> Self {
+		Noise { state: seed.max(1) }
+	}
+
+	// uniform in 0..1
+	#[inline]
+	pub fn uniform( &mut self ) -> f32 {
+		self.state ^= self.state << 13;
+		self.state ^= self.state >> 17;
+		self.state ^= self.state << 5;
+		(self.state >> 8) as f32 / (1 << 24) as f32
+	}
+
+	// uniform in -1..1
+	#[inline]
+	pub fn white( &mut self ) -> f32 {
+		self.uniform() * 2.0 - 1.0
+	}
+
+	// triangular in -1..1, the sum of two uniforms
+	#[inline]
+	pub fn triangular( &mut self ) -> f32 {
+		self.uniform() - self.uniform()
+	}
+}
+
+impl Default for Noise {
+	fn default() -> Self {
+		Noise::new(0x2545_f491)
+	}
+}
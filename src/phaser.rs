@@ -0,0 +1,183 @@
+use std::sync::{Weak, atomic::Ordering};
+
+use crate::AndrewParams;
+use crate::andrew_effect::AndrewEffect;
+use crate::biquad::{BiQuadraticFilter, FilterKind::*};
+use crate::modulator::Lfo;
+
+pub const PHASER_MAX_STAGES: usize = 12;
+
+// octaves the sweep reaches either side of the center at full depth
+const PHASER_OCTAVES: f32 = 3.0;
+
+// samples between retunes of the allpasses
+const PHASER_CONTROL_LEN: usize = 16;
+
+// q of the second order stages, how sharply each turns its 360 degrees
+const PHASER_Q: f32 = 0.7;
+
+
+// a chain of allpasses swept by an lfo and mixed back in with the dry
+// signal. each pair of first order stages cuts one notch, or each second
+// order one does. the right lfo runs `spread` of a cycle ahead of the left
+pub struct PhaserEffect {
+	stages: [Vec<BiQuadraticFilter>; 2],
+	lfo: [Lfo; 2],
+	feedback_samp: [f32; 2],
+	// samples until the next retune
+	countdown: [usize; 2],
+
+	num_stages: usize,
+	second_order: bool,
+	center: f32,
+	depth: f32,
+	feedback: f32,
+	spread: f32,
+	sample_rate: f32,
+
+	params: Weak<AndrewParams>,
+}
+
+impl AndrewEffect for PhaserEffect {
+	fn process(&mut self, chan_id: usize, in_buf: &[f32], out_buf: &mut [f32]) {
+		for (samp, out) in in_buf.iter().zip(out_buf.iter_mut()) {
+			if self.countdown[chan_id] == 0 {
+				self.countdown[chan_id] = PHASER_CONTROL_LEN;
+				let sweep = self.lfo[chan_id].get() * self.depth * PHASER_OCTAVES;
+				let freq = (self.center * sweep.exp2()).min(0.45 * self.sample_rate);
+				for stage in self.stages[chan_id].iter_mut() {
+					stage.update_center_freq(freq);
+				}
+			}
+			self.countdown[chan_id] -= 1;
+			self.lfo[chan_id].forward(1);
+
+			let mut wet = samp + self.feedback * self.feedback_samp[chan_id];
+			for stage in self.stages[chan_id][..self.num_stages].iter_mut() {
+				wet = stage.filter(wet);
+			}
+			self.feedback_samp[chan_id] = wet;
+
+			*out = 0.5 * (samp + wet);
+		}
+	}
+
+	fn update_params(&mut self) {
+		if let Some(params) = self.params.upgrade() {
+			self.num_stages = (params.phaser_stages.load(Ordering::Relaxed) as usize).clamp(2, PHASER_MAX_STAGES);
+			self.center = params.phaser_center.get();
+			self.depth = params.phaser_depth.get();
+			self.feedback = params.phaser_feedback.get();
+
+			// stages of the other order start again from silence
+			let second_order = params.phaser_second_order.load(Ordering::Relaxed);
+			let sample_rate = params.sample_rate.get();
+			if sample_rate != self.sample_rate || second_order != self.second_order {
+				self.sample_rate = sample_rate;
+				self.second_order = second_order;
+				for stage in self.stages.iter_mut().flatten() {
+					*stage = PhaserEffect::stage(second_order, self.center, sample_rate);
+				}
+			}
+
+			for lfo in self.lfo.iter_mut() {
+				lfo.set_freq(params.phaser_rate.get());
+				lfo.set_sample_rate(sample_rate);
+			}
+			let spread = params.phaser_spread.get();
+			if spread != self.spread {
+				self.spread = spread;
+				let phase = self.lfo[0].phase();
+				self.lfo[1].set_phase(phase + spread);
+			}
+		}
+	}
+
+	fn get_latency(&self) -> usize {0}
+}
+
+impl PhaserEffect {
+	pub fn new( params: Weak<AndrewParams> ) -> Self {
+		let stages = vec![PhaserEffect::stage(false, 800.0, 44100.0); PHASER_MAX_STAGES];
+		PhaserEffect {
+			stages: [stages.clone(), stages],
+			lfo: [Lfo::default(), Lfo::default()],
+			feedback_samp: [0.0; 2],
+			countdown: [0; 2],
+			num_stages: 4,
+			second_order: false,
+			center: 800.0,
+			depth: 0.5,
+			feedback: 0.0,
+			spread: 0.0,
+			sample_rate: 44100.0,
+			params,
+		}
+	}
+
+	fn stage( second_order: bool, freq: f32, sample_rate: f32 ) -> BiQuadraticFilter {
+		let kind = if second_order { ALLPASS } else { ALLPASS1 };
+		BiQuadraticFilter::new(kind, freq, sample_rate, PHASER_Q, 0.0)
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::f32::consts::{PI, TAU};
+	use std::sync::Arc;
+
+	fn sine( freq: f32 ) -> Vec<f32> {
+		(0..22050).map(|i| (TAU * freq * i as f32 / 44100.0).sin()).collect()
+	}
+
+	fn peak( buf: &[f32] ) -> f32 {
+		buf[11025..].iter().fold(0f32, |peak, samp| peak.max(samp.abs()))
+	}
+
+	#[test]
+	fn allpass_stages_pass_every_frequency_at_unity() {
+		for second_order in [false, true] {
+			for freq in [50.0, 400.0, 800.0, 3000.0, 15000.0] {
+				let mut stage = PhaserEffect::stage(second_order, 800.0, 44100.0);
+				let out: Vec<f32> = sine(freq).iter().map(|x| stage.filter(*x)).collect();
+				assert!((peak(&out) - 1.0).abs() < 0.01, "{} at {} hz", peak(&out), freq);
+			}
+		}
+	}
+
+	// the sweep held still on the center, so the notches stay put
+	fn response( stages: u8, second_order: bool, freq: f32 ) -> f32 {
+		let params = Arc::new(AndrewParams::default());
+		params.phaser_stages.store(stages, Ordering::Relaxed);
+		params.phaser_second_order.store(second_order, Ordering::Relaxed);
+		params.phaser_depth.set(0.0);
+		let mut phaser = PhaserEffect::new(Arc::downgrade(&params));
+		phaser.update_params();
+
+		let input = sine(freq);
+		let mut output = vec![0.0; input.len()];
+		phaser.process(0, &input, &mut output);
+		peak(&output)
+	}
+
+	#[test]
+	fn notches_land_where_the_stages_turn_through_180() {
+		// each first order stage turns by 2 atan(tan(w / 2) / tan(wc / 2)),
+		// four of them reach 180 and 540 degrees at 45 and 135 each
+		let t = (PI * 800.0 / 44100.0).tan();
+		let notch = |stage_turn: f32| (t * stage_turn.tan()).atan() * 44100.0 / PI;
+		for freq in [notch(PI / 8.0), notch(3.0 * PI / 8.0)] {
+			assert!(response(4, false, freq) < 0.02, "{} at {} hz", response(4, false, freq), freq);
+		}
+		// and at the center they're round to 360, back in phase with the dry
+		assert!(response(4, false, 800.0) > 0.98);
+
+		// three second order stages are at 540 right on the center
+		assert!(response(3, true, 800.0) < 0.02);
+		// with the dry well away either side of it
+		assert!(response(3, true, 20.0) > 0.95);
+		assert!(response(3, true, 15000.0) > 0.95);
+	}
+}
@@ -0,0 +1,427 @@
+use std::f32::consts::{PI, TAU};
+use std::sync::{Weak, atomic::Ordering};
+
+use crate::AndrewParams;
+use crate::andrew_effect::AndrewEffect;
+use crate::delay_line::hermite;
+use crate::fft::{Complex, Fft};
+
+const PV_FFT_LEN: usize = 2048;
+
+// synthesis hop, the analysis hop is this over the pitch ratio
+const PV_HOP: usize = PV_FFT_LEN / 4;
+
+// hann windows on both sides overlap add to 1.5 at a quarter frame hop
+const PV_OLA_GAIN: f32 = 2.0 / 3.0;
+
+// the furthest the pitch can go either way, a ratio of 4
+pub const PV_MAX_SEMITONES: f32 = 24.0;
+const PV_MIN_RATIO: f32 = 0.25;
+
+// enough that a frame centred on the sample being played at the lowest
+// ratio has all its input in, plus the interpolator's lookahead
+const PV_LATENCY: usize = PV_FFT_LEN / 2 + (PV_FFT_LEN / 2 + 3) * 4 + 1;
+
+// input kept around, has to cover the latency and a frame behind it
+const PV_INPUT_LEN: usize = 4 * PV_FFT_LEN;
+
+// rise in spectral flux, against the frame's energy, that counts as an onset
+const PV_ONSET_FLUX: f32 = 0.6;
+
+// quefrency cutoff of the spectral envelope, in seconds
+const PV_LIFTER_TIME: f32 = 0.0015;
+
+// the formant correction is kept within 20 db either way
+const PV_MAX_FORMANT_GAIN: f32 = 10.0;
+
+
+// phase vocoder pitch shifter. the signal is time stretched by the pitch
+// ratio, with the analysis hop shrunk or grown against a fixed synthesis
+// hop, then read back at the ratio so the output keeps pace with the input
+// at the new pitch. phases are locked to the nearest spectral peak, reset
+// on onsets so attacks stay sharp, and the spectral envelope can be moved
+// back to where it was so voices don't chipmunk
+pub struct PitchEffect {
+	chans: [PitchChannel; 2],
+	fft: Fft,
+	window: Vec<f32>,
+	bins: Vec<Complex>,
+	cepstrum: Vec<Complex>,
+	mag: Vec<f32>,
+	phase: Vec<f32>,
+	envelope: Vec<f32>,
+	peaks: Vec<usize>,
+
+	ratio: f32,
+	formant: bool,
+	transient: bool,
+	sample_rate: f32,
+
+	params: Weak<AndrewParams>,
+}
+
+struct PitchChannel {
+	// ring of recent input, indexed by absolute sample time
+	input: Vec<f32>,
+	now: i64,
+	// the stretched signal, stretched[0] sits at `base`
+	stretched: Vec<f32>,
+	base: i64,
+	// read position in the stretched signal
+	pos: f64,
+	// where the next frame lands in the stretched signal
+	next_frame: i64,
+	// input time of the last analysis frame, none after a reset
+	prev_time: Option<i64>,
+	prev_phase: Vec<f32>,
+	synth_phase: Vec<f32>,
+	prev_mag: Vec<f32>,
+}
+
+impl PitchChannel {
+	fn new() -> Self {
+		PitchChannel {
+			input: vec![0.0; PV_INPUT_LEN],
+			now: 0,
+			stretched: vec![0.0; 2 * PV_FFT_LEN],
+			base: 0,
+			pos: 0.0,
+			next_frame: 0,
+			prev_time: None,
+			prev_phase: vec![0.0; PV_FFT_LEN / 2 + 1],
+			synth_phase: vec![0.0; PV_FFT_LEN / 2 + 1],
+			prev_mag: vec![0.0; PV_FFT_LEN / 2 + 1],
+		}
+	}
+
+	#[inline]
+	fn input_at( &self, time: i64 ) -> f32 {
+		if time < 0 || time > self.now { 0.0 } else { self.input[time as usize % PV_INPUT_LEN] }
+	}
+
+	#[inline]
+	fn stretched_at( &self, i: i64 ) -> f32 {
+		self.stretched.get((i - self.base) as usize).copied().unwrap_or(0.0)
+	}
+}
+
+impl AndrewEffect for PitchEffect {
+	fn process(&mut self, chan_id: usize, in_buf: &[f32], out_buf: &mut [f32]) {
+		let ratio = self.ratio as f64;
+
+		for (samp, out) in in_buf.iter().zip(out_buf.iter_mut()) {
+			let chan = &mut self.chans[chan_id];
+			chan.now += 1;
+			let now = chan.now;
+			chan.input[now as usize % PV_INPUT_LEN] = *samp;
+
+			// run frames until the interpolator's four points are all final.
+			// each frame is centred on the input the read position maps to
+			loop {
+				let chan = &self.chans[chan_id];
+				if chan.next_frame > chan.pos.floor() as i64 + 2 { break }
+				let play_time = (now - PV_LATENCY as i64) as f64;
+				let center = chan.next_frame as f64 + (PV_FFT_LEN / 2) as f64;
+				let time = (play_time + (center - chan.pos) / ratio).round() as i64 - (PV_FFT_LEN / 2) as i64;
+				self.run_frame(chan_id, time);
+			}
+
+			let chan = &mut self.chans[chan_id];
+			let i = chan.pos.floor() as i64;
+			let t = (chan.pos - i as f64) as f32;
+			*out = hermite(
+				t,
+				chan.stretched_at(i - 1),
+				chan.stretched_at(i),
+				chan.stretched_at(i + 1),
+				chan.stretched_at(i + 2),
+			);
+			chan.pos += ratio;
+
+			// drop what the read position has left behind
+			let done = chan.pos.floor() as i64 - 2 - chan.base;
+			if done > PV_FFT_LEN as i64 {
+				chan.stretched.drain(..done as usize);
+				chan.base += done;
+			}
+		}
+	}
+
+	fn update_params(&mut self) {
+		if let Some(params) = self.params.upgrade() {
+			let semitones = params.pv_semitones.get().clamp(-PV_MAX_SEMITONES, PV_MAX_SEMITONES);
+			self.ratio = (semitones / 12.0).exp2().max(PV_MIN_RATIO);
+			self.formant = params.pv_formant.load(Ordering::Relaxed);
+			self.transient = params.pv_transient.load(Ordering::Relaxed);
+			self.sample_rate = params.sample_rate.get();
+		}
+	}
+
+	fn get_latency(&self) -> usize {
+		PV_LATENCY
+	}
+}
+
+impl PitchEffect {
+	pub fn new( params: Weak<AndrewParams> ) -> Self {
+		let bins = PV_FFT_LEN / 2 + 1;
+		PitchEffect {
+			chans: [PitchChannel::new(), PitchChannel::new()],
+			fft: Fft::new(PV_FFT_LEN),
+			window: (0..PV_FFT_LEN)
+				.map(|i| 0.5 - 0.5 * (TAU * i as f32 / PV_FFT_LEN as f32).cos())
+				.collect(),
+			bins: vec![Complex::default(); PV_FFT_LEN],
+			cepstrum: vec![Complex::default(); PV_FFT_LEN],
+			mag: vec![0.0; bins],
+			phase: vec![0.0; bins],
+			envelope: vec![0.0; bins],
+			peaks: Vec::with_capacity(bins),
+			ratio: 1.0,
+			formant: false,
+			transient: true,
+			sample_rate: 44100.0,
+			params,
+		}
+	}
+
+	// analyses the frame starting at input `time` and
+	// overlap adds it at the channel's next synthesis slot
+	fn run_frame( &mut self, chan_id: usize, time: i64 ) {
+		let n = PV_FFT_LEN;
+		let half = n / 2;
+		let chan = &mut self.chans[chan_id];
+
+		for (i, (bin, w)) in self.bins.iter_mut().zip(self.window.iter()).enumerate() {
+			*bin = Complex::new(chan.input_at(time + i as i64) * w, 0.0);
+		}
+		self.fft.forward(&mut self.bins);
+		for k in 0..=half {
+			self.mag[k] = self.bins[k].norm();
+			self.phase[k] = self.bins[k].arg();
+		}
+
+		// onsets show up as a jump in energy across the spectrum
+		let energy: f32 = self.mag.iter().sum();
+		let flux: f32 = self.mag.iter()
+			.zip(chan.prev_mag.iter())
+			.map(|(m, p)| (m - p).max(0.0))
+			.sum();
+		let onset = self.transient && flux > PV_ONSET_FLUX * energy && energy > 1e-3;
+		chan.prev_mag.copy_from_slice(&self.mag);
+
+		match chan.prev_time {
+			Some(prev_time) if !onset && time > prev_time => {
+				let hop = (time - prev_time) as f32;
+
+				// peaks are local maxima over two bins either side
+				self.peaks.clear();
+				for k in 2..half - 1 {
+					let m = self.mag[k];
+					if m > self.mag[k - 1] && m >= self.mag[k + 1] && m > self.mag[k - 2] && m >= self.mag[k + 2] {
+						self.peaks.push(k);
+					}
+				}
+
+				// advance the peaks by their measured frequency
+				for &p in self.peaks.iter() {
+					let omega = TAU * p as f32 / n as f32;
+					let dev = wrap_phase(self.phase[p] - chan.prev_phase[p] - omega * hop);
+					let freq = omega + dev / hop;
+					chan.synth_phase[p] += freq * PV_HOP as f32;
+				}
+
+				// every other bin keeps its offset from the peak it belongs to
+				let mut region = 0;
+				for k in 0..=half {
+					while region + 1 < self.peaks.len() && k >= (self.peaks[region] + self.peaks[region + 1]).div_ceil(2) {
+						region += 1;
+					}
+					match self.peaks.get(region) {
+						Some(&p) if p != k => chan.synth_phase[k] = chan.synth_phase[p] + self.phase[k] - self.phase[p],
+						Some(_) => (),
+						None => chan.synth_phase[k] = self.phase[k],
+					}
+				}
+			},
+			_ => chan.synth_phase.copy_from_slice(&self.phase),
+		}
+		chan.prev_phase.copy_from_slice(&self.phase);
+		chan.prev_time = Some(time);
+
+		if self.formant {
+			self.estimate_envelope();
+		}
+		let chan = &mut self.chans[chan_id];
+
+		// after reading back at the ratio, bin k ends up at k * ratio. bins
+		// that would land past nyquist are dropped rather than aliased, and
+		// with formants kept each bin takes the envelope of where it lands
+		let ratio = self.ratio;
+		let top = ((half as f32 / ratio) as usize).min(half);
+		for k in 0..=half {
+			let mut mag = if k <= top { self.mag[k] } else { 0.0 };
+			if self.formant && mag > 0.0 {
+				let target = k as f32 * ratio;
+				let i = (target as usize).min(half - 1);
+				let t = target - i as f32;
+				let env = self.envelope[i] + (self.envelope[i + 1] - self.envelope[i]) * t;
+				mag *= (env - self.envelope[k]).exp().clamp(1.0 / PV_MAX_FORMANT_GAIN, PV_MAX_FORMANT_GAIN);
+			}
+			self.bins[k] = Complex::from_polar(mag, chan.synth_phase[k]);
+		}
+		for k in half + 1..n {
+			self.bins[k] = self.bins[n - k].conj();
+		}
+		self.fft.inverse(&mut self.bins);
+
+		let start = chan.next_frame - chan.base;
+		let end = start as usize + n;
+		if chan.stretched.len() < end {
+			chan.stretched.resize(end, 0.0);
+		}
+		let scale = PV_OLA_GAIN / n as f32;
+		for (i, (bin, w)) in self.bins.iter().zip(self.window.iter()).enumerate() {
+			chan.stretched[start as usize + i] += bin.re * w * scale;
+		}
+		chan.next_frame += PV_HOP as i64;
+
+		// wrapping keeps the phases from losing precision over a long run
+		for phase in chan.synth_phase.iter_mut() {
+			*phase = wrap_phase(*phase);
+		}
+	}
+
+	// smoothed log magnitude, by liftering the real cepstrum
+	fn estimate_envelope( &mut self ) {
+		let n = PV_FFT_LEN;
+		let half = n / 2;
+		for k in 0..n {
+			let m = self.mag[if k <= half { k } else { n - k }];
+			self.cepstrum[k] = Complex::new((m + 1e-9).ln(), 0.0);
+		}
+		self.fft.inverse(&mut self.cepstrum);
+
+		let cutoff = ((PV_LIFTER_TIME * self.sample_rate) as usize).clamp(1, half - 1);
+		for (q, c) in self.cepstrum.iter_mut().enumerate() {
+			if q > cutoff && q < n - cutoff {
+				*c = Complex::default();
+			} else {
+				*c = c.scale(1.0 / n as f32);
+			}
+		}
+		self.fft.forward(&mut self.cepstrum);
+
+		for (env, c) in self.envelope.iter_mut().zip(self.cepstrum.iter()) {
+			*env = c.re;
+		}
+	}
+}
+
+
+// wraps a phase into -pi..pi
+#[inline]
+fn wrap_phase( phase: f32 ) -> f32 {
+	phase - TAU * ((phase + PI) / TAU).floor()
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::Arc;
+
+	fn render( semitones: f32, formant: bool, input: &[f32] ) -> Vec<f32> {
+		let params = Arc::new(AndrewParams::default());
+		params.pv_semitones.set(semitones);
+		params.pv_formant.store(formant, Ordering::Relaxed);
+		let mut pitch = PitchEffect::new(Arc::downgrade(&params));
+		pitch.update_params();
+
+		let mut output = vec![0.0; input.len()];
+		for (ins, outs) in input.chunks(512).zip(output.chunks_mut(512)) {
+			pitch.process(0, ins, outs);
+		}
+		output
+	}
+
+	// from the rising zero crossings, placed between samples
+	fn measure_freq( buf: &[f32] ) -> f32 {
+		let crossings: Vec<f32> = buf.windows(2)
+			.enumerate()
+			.filter(|(_, pair)| pair[0] < 0.0 && pair[1] >= 0.0)
+			.map(|(i, pair)| i as f32 + pair[0] / (pair[0] - pair[1]))
+			.collect();
+		let span = crossings[crossings.len() - 1] - crossings[0];
+		(crossings.len() - 1) as f32 * 44100.0 / span
+	}
+
+	#[test]
+	fn sine_comes_out_shifted() {
+		let input: Vec<f32> = (0..44100).map(|i| 0.5 * (TAU * 440.0 * i as f32 / 44100.0).sin()).collect();
+		for (semitones, want) in [(12.0, 880.0), (-12.0, 220.0), (0.0, 440.0), (7.0, 440.0 * 1.4983)] {
+			let output = render(semitones, false, &input);
+			let freq = measure_freq(&output[PV_LATENCY + 4096..]);
+			assert!((freq / want - 1.0).abs() < 0.002, "{} semitones gave {} hz, not {}", semitones, freq, want);
+		}
+	}
+
+	#[test]
+	fn bursts_come_out_as_late_as_reported() {
+		// a short hann shaped burst of 1k, far enough in for the frames to have settled
+		let at = 8192;
+		let len = 1024;
+		let input: Vec<f32> = (0..32768)
+			.map(|i| {
+				if !(at..at + len).contains(&i) { return 0.0 }
+				let t = (i - at) as f32 / len as f32;
+				(0.5 - 0.5 * (TAU * t).cos()) * (TAU * 1000.0 * i as f32 / 44100.0).sin()
+			})
+			.collect();
+
+		// where the energy of the burst sits
+		let middle = |buf: &[f32]| {
+			let energy: f32 = buf.iter().map(|x| x * x).sum();
+			buf.iter().enumerate().map(|(i, x)| i as f32 * x * x).sum::<f32>() / energy
+		};
+		let expected = middle(&input) + PV_LATENCY as f32;
+		for semitones in [0.0, 12.0, -12.0] {
+			let output = render(semitones, false, &input);
+			let found = middle(&output);
+			assert!((found - expected).abs() < 16.0, "{} semitones centred at {}, not {}", semitones, found, expected);
+		}
+	}
+
+	#[test]
+	fn formant_correction_keeps_the_envelope_put() {
+		// harmonics of 200 hz shaped by a single formant at 1k
+		let formant = |freq: f32| (-((freq - 1000.0) / 300.0).powi(2)).exp();
+		let input: Vec<f32> = (0..44100)
+			.map(|i| {
+				let t = i as f32 / 44100.0;
+				(1..40).map(|h| 200.0 * h as f32).map(|f| 0.05 * formant(f) * (TAU * f * t).sin()).sum()
+			})
+			.collect();
+
+		// the middle of the output's spectrum, from its level at each new harmonic
+		let centroid = |buf: &[f32]| {
+			let (weighted, total) = (1..12).map(|h| 400.0 * h as f32)
+				.map(|f| {
+					let (re, im) = buf.iter().enumerate().fold((0.0, 0.0), |(re, im), (i, x)| {
+						let w = TAU * f * i as f32 / 44100.0;
+						(re + x * w.cos(), im + x * w.sin())
+					});
+					(f, (re * re + im * im).sqrt())
+				})
+				.fold((0.0, 0.0), |(weighted, total), (f, mag)| (weighted + f * mag, total + mag));
+			weighted / total
+		};
+
+		let shifted = render(12.0, false, &input);
+		let corrected = render(12.0, true, &input);
+		let steady = PV_LATENCY + 4096..PV_LATENCY + 4096 + 8820;
+		let (shifted, corrected) = (centroid(&shifted[steady.clone()]), centroid(&corrected[steady]));
+		assert!(shifted > 1700.0, "uncorrected centred at {}", shifted);
+		assert!(corrected < 1400.0, "corrected centred at {}", corrected);
+	}
+}
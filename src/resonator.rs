@@ -0,0 +1,327 @@
+use std::sync::{Weak, atomic::Ordering};
+
+use crate::AndrewParams;
+use crate::andrew_effect::AndrewEffect;
+use crate::biquad::{BiQuadraticFilter, FilterKind::*};
+use crate::delay_line::DelayLine;
+
+pub const KS_MAX_VOICES: usize = 8;
+
+// lowest pitch a string can be tuned to, which sizes the delay lines
+const KS_MIN_FREQ: f32 = 20.0;
+
+// how long a string rings on after its midi note is let go, in seconds
+const KS_RELEASE: f32 = 0.08;
+
+// most the loop filter may hold back, so the top end never dies outright
+const KS_MAX_DAMPING: f32 = 0.95;
+
+// loop gain ceiling, what the lows below the pitch decay at in the worst case
+const KS_MAX_FEEDBACK: f32 = 0.9995;
+
+
+// midi note number as a name, middle c is C4
+pub fn note_name( note: u8 ) -> String {
+	const NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+	format!("{}{}", NAMES[note as usize % 12], note as i32 / 12 - 1)
+}
+
+pub fn note_freq( note: f32 ) -> f32 {
+	440.0 * 2f32.powf((note - 69.0) / 12.0)
+}
+
+
+// one string, a delay line fed back through a one pole lowpass
+#[derive(Clone)]
+struct KsVoice {
+	lines: [DelayLine; 2],
+	lowpass: [f32; 2],
+	// the loop filter's coefficient, damping or less
+	damping: f32,
+	freq: f32,
+	// midi note playing it, if any
+	note: Option<u8>,
+	velocity: f32,
+	held: bool,
+	// when it was last struck, to steal the oldest
+	struck: u64,
+
+	// worked out from the above by KsEffect::tune
+	delay: f32,
+	feedback: f32,
+	input_gain: f32,
+}
+
+
+// karplus-strong bank. the input, shaped by `brightness`, excites up to
+// eight strings. each string's loop is a fractional delay and a one pole
+// lowpass set by `damping`; the delay is shortened by the filter's own
+// phase delay at the pitch, so strings land in tune at any sample rate,
+// and the loop gain is set so the fundamental dies away in `decay`.
+// the strings are tuned by the note params, or by midi in midi mode
+pub struct KsEffect {
+	voices: Vec<KsVoice>,
+	exciter: [BiQuadraticFilter; 2],
+	dc_block: [BiQuadraticFilter; 2],
+
+	count: usize,
+	decay: f32,
+	damping: f32,
+	midi: bool,
+	mix: f32,
+	sample_rate: f32,
+	// counts notes struck, for stealing
+	clock: u64,
+
+	params: Weak<AndrewParams>,
+}
+
+impl AndrewEffect for KsEffect {
+	fn process(&mut self, chan_id: usize, in_buf: &[f32], out_buf: &mut [f32]) {
+		let norm = 1.0 / (self.count as f32).sqrt();
+
+		for (samp, out) in in_buf.iter().zip(out_buf.iter_mut()) {
+			let excite = self.exciter[chan_id].filter(self.dc_block[chan_id].filter(*samp));
+
+			let mut wet = 0.0;
+			for voice in self.voices.iter_mut().take(self.count) {
+				// read before the push, so a sample less back makes the full delay
+				let looped = voice.lines[chan_id].read(voice.delay - 1.0);
+				let lowpass = &mut voice.lowpass[chan_id];
+				*lowpass = (1.0 - voice.damping) * looped + voice.damping * *lowpass;
+
+				let y = excite * voice.input_gain + *lowpass * voice.feedback;
+				voice.lines[chan_id].push(y);
+				wet += y;
+			}
+
+			*out = samp * (1.0 - self.mix) + wet * norm * self.mix;
+		}
+	}
+
+	fn midi(&mut self, data: [u8; 3]) {
+		if !self.midi { return }
+		let [status, note, velocity] = data;
+		match status & 0xf0 {
+			0x90 if velocity > 0 => {
+				// a free string if there is one, otherwise the oldest
+				self.clock += 1;
+				let voices = &mut self.voices[..self.count];
+				let voice = match voices.iter().position(|voice| voice.note == Some(note) || !voice.held) {
+					Some(i) => i,
+					None => (0..voices.len()).min_by_key(|&i| voices[i].struck).unwrap_or(0),
+				};
+				let voice = &mut voices[voice];
+				voice.note = Some(note);
+				voice.freq = note_freq(note as f32);
+				voice.velocity = velocity as f32 / 127.0;
+				voice.held = true;
+				voice.struck = self.clock;
+				self.tune();
+			}
+			0x80 | 0x90 => {
+				for voice in self.voices.iter_mut().filter(|voice| voice.note == Some(note)) {
+					voice.held = false;
+				}
+				self.tune();
+			}
+			_ => (),
+		}
+	}
+
+	fn update_params(&mut self) {
+		if let Some(params) = self.params.upgrade() {
+			self.count = (params.ks_count.load(Ordering::Relaxed) as usize).clamp(1, KS_MAX_VOICES);
+			self.decay = params.ks_decay.get().max(0.01);
+			self.damping = params.ks_damping.get().clamp(0.0, KS_MAX_DAMPING);
+			self.mix = params.ks_mix.get();
+
+			// going into or out of midi mode starts from quiet strings
+			let midi = params.ks_midi.load(Ordering::Relaxed);
+			if midi != self.midi {
+				self.midi = midi;
+				for voice in self.voices.iter_mut() {
+					voice.note = None;
+					voice.held = !midi;
+				}
+			}
+			if !self.midi {
+				for (voice, note) in self.voices.iter_mut().zip(params.ks_notes.iter()) {
+					voice.freq = note_freq(note.load(Ordering::Relaxed) as f32);
+					voice.velocity = 1.0;
+					voice.held = true;
+				}
+			}
+
+			let sample_rate = params.sample_rate.get();
+			if sample_rate != self.sample_rate {
+				self.sample_rate = sample_rate;
+				let max_delay = (sample_rate / KS_MIN_FREQ) as usize + 4;
+				for voice in self.voices.iter_mut() {
+					voice.lines = [DelayLine::new(max_delay), DelayLine::new(max_delay)];
+					voice.lowpass = [0.0; 2];
+				}
+				for filter in self.dc_block.iter_mut() {
+					filter.recfg(HIGHPASS, 20.0, sample_rate, 0.7, 0.0);
+				}
+			}
+			let brightness = params.ks_brightness.get().min(0.45 * sample_rate);
+			for filter in self.exciter.iter_mut() {
+				filter.recfg(LOWPASS, brightness, sample_rate, 0.7, 0.0);
+			}
+			self.tune();
+		}
+	}
+
+	fn get_latency(&self) -> usize {0}
+
+	// the longest a string rings for
+	fn get_tail_size(&self) -> usize {
+		(self.decay * self.sample_rate) as usize
+	}
+}
+
+impl KsEffect {
+	pub fn new( params: Weak<AndrewParams> ) -> Self {
+		let max_delay = (44100.0 / KS_MIN_FREQ) as usize + 4;
+		let voice = KsVoice {
+			lines: [DelayLine::new(max_delay), DelayLine::new(max_delay)],
+			lowpass: [0.0; 2],
+			damping: 0.0,
+			freq: 220.0,
+			note: None,
+			velocity: 1.0,
+			held: true,
+			struck: 0,
+			delay: 200.0,
+			feedback: 0.0,
+			input_gain: 0.0,
+		};
+		let filter = BiQuadraticFilter::new(HIGHPASS, 20.0, 44100.0, 0.7, 0.0);
+		let mut ks = KsEffect {
+			voices: vec![voice; KS_MAX_VOICES],
+			exciter: [filter.clone(), filter.clone()],
+			dc_block: [filter.clone(), filter],
+			count: 4,
+			decay: 2.0,
+			damping: 0.3,
+			midi: false,
+			mix: 0.5,
+			sample_rate: 44100.0,
+			clock: 0,
+			params,
+		};
+		ks.tune();
+		ks
+	}
+
+	// sets each string's delay, feedback and drive from its pitch
+	fn tune( &mut self ) {
+		let max_freq = 0.45 * self.sample_rate;
+		let release = KS_RELEASE.min(self.decay);
+
+		for voice in self.voices.iter_mut() {
+			let freq = voice.freq.clamp(KS_MIN_FREQ, max_freq);
+			let w = std::f32::consts::TAU * freq / self.sample_rate;
+
+			// what the fundamental loses each time round
+			let decay = if voice.held { self.decay } else { release };
+			let loop_gain = 10f32.powf(-3.0 / (decay * freq));
+
+			// the lowpass can't take more off the pitch than the decay
+			// leaves room for, or the feedback has to go over one to
+			// make up for it and the lows underneath ring on forever
+			let d = self.damping.min(KsEffect::max_damping(w, loop_gain / KS_MAX_FEEDBACK));
+			voice.damping = d;
+
+			// the lowpass's gain and phase delay at the pitch
+			let (re, im) = (1.0 - d * w.cos(), d * w.sin());
+			let filter_gain = (1.0 - d) / (re * re + im * im).sqrt();
+			let filter_delay = im.atan2(re) / w;
+			voice.delay = (self.sample_rate / freq - filter_delay).max(2.0);
+
+			// topped up for the filter's share of the loss
+			voice.feedback = (loop_gain / filter_gain).min(KS_MAX_FEEDBACK);
+
+			// keeps noise going in about as loud as it comes out,
+			// however long the string rings
+			let ringing = voice.feedback * filter_gain;
+			voice.input_gain = (1.0 - ringing * ringing).sqrt() * if voice.held { voice.velocity } else { 0.0 };
+		}
+	}
+
+	// the most damping that still passes `gain` at `w`, where
+	// (1 - d)^2 = gain^2 |1 - d e^-jw|^2, taking the root under one
+	fn max_damping( w: f32, gain: f32 ) -> f32 {
+		if gain >= 1.0 { return 0.0 }
+		let g2 = gain * gain;
+		let a = 1.0 - g2;
+		let b = -2.0 * (1.0 - g2 * w.cos());
+		let disc = (b * b - 4.0 * a * a).max(0.0);
+		(-b - disc.sqrt()) / (2.0 * a)
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::f32::consts::TAU;
+	use std::sync::Arc;
+
+	// rings one string from a click and finds the strongest frequency
+	// within 20 cents of the note, a tenth of a cent at a time
+	fn rung_pitch( note: u8, damping: f32, sample_rate: f32 ) -> f32 {
+		let params = Arc::new(AndrewParams::default());
+		params.sample_rate.set(sample_rate);
+		params.ks_count.store(1, Ordering::Relaxed);
+		params.ks_notes[0].store(note, Ordering::Relaxed);
+		params.ks_damping.set(damping);
+		params.ks_decay.set(4.0);
+		params.ks_mix.set(1.0);
+		let mut ks = KsEffect::new(Arc::downgrade(&params));
+		ks.update_params();
+
+		let len = (sample_rate * 0.5) as usize;
+		let mut input = vec![0.0; len];
+		input[0] = 1.0;
+		let mut output = vec![0.0; len];
+		ks.process(0, &input, &mut output);
+
+		// a hann window, then each candidate correlated against a rotating phasor
+		let windowed: Vec<f64> = output.iter()
+			.enumerate()
+			.map(|(i, samp)| *samp as f64 * (0.5 - 0.5 * (TAU * i as f32 / len as f32).cos()) as f64)
+			.collect();
+		let level = |freq: f32| {
+			let (step_im, step_re) = (std::f64::consts::TAU * freq as f64 / sample_rate as f64).sin_cos();
+			let (mut re, mut im, mut sum_re, mut sum_im) = (1.0, 0.0, 0.0, 0.0);
+			for samp in windowed.iter() {
+				sum_re += samp * re;
+				sum_im += samp * im;
+				(re, im) = (re * step_re - im * step_im, re * step_im + im * step_re);
+			}
+			sum_re * sum_re + sum_im * sum_im
+		};
+
+		let freq = note_freq(note as f32);
+		(-200..=200)
+			.map(|tenth| freq * 2f32.powf(tenth as f32 / 12000.0))
+			.map(|freq| (freq, level(freq)))
+			.max_by(|a, b| a.1.total_cmp(&b.1))
+			.unwrap()
+			.0
+	}
+
+	#[test]
+	fn strings_land_in_tune() {
+		for sample_rate in [44100.0, 96000.0] {
+			for note in [33, 57, 69, 88] {
+				for damping in [0.0, 0.5, 0.9] {
+					let cents = 1200.0 * (rung_pitch(note, damping, sample_rate) / note_freq(note as f32)).log2();
+					assert!(cents.abs() < 0.5, "note {} at {} damping {}: {} cents out", note, sample_rate, damping, cents);
+				}
+			}
+		}
+	}
+}
@@ -0,0 +1,313 @@
+use std::sync::{Weak, atomic::Ordering};
+
+use crate::AndrewParams;
+use crate::andrew_effect::{AndrewEffect, Smoothed};
+use crate::biquad::{BiQuadraticFilter, FilterKind::*};
+use crate::delay_line::DelayLine;
+use crate::modulator::Lfo;
+
+const FDN_LINES: usize = 8;
+
+// line lengths at a size of one, in seconds. spread out and far from
+// sharing factors so the echoes don't pile up on each other
+const FDN_LENGTHS: [f32; FDN_LINES] = [0.0297, 0.0371, 0.0411, 0.0437, 0.0533, 0.0599, 0.0671, 0.0733];
+pub const REVERB_MAX_SIZE: f32 = 2.0;
+
+// input diffusers per side, dattorro's lengths in seconds
+const DIFFUSER_LENGTHS: [f32; 4] = [0.00477, 0.00360, 0.01273, 0.00931];
+
+// furthest the lines' read heads swing at full modulation, in seconds
+const REVERB_MOD_DEPTH: f32 = 0.0008;
+
+pub const REVERB_MAX_PREDELAY: f32 = 0.25;
+
+
+// schroeder allpass, smears an impulse out without colouring it
+#[derive(Clone)]
+struct Diffuser {
+	line: DelayLine,
+	len: f32,
+}
+
+impl Diffuser {
+	#[inline]
+	fn tick( &mut self, x: f32, gain: f32 ) -> f32 {
+		let delayed = self.line.read(self.len);
+		let v = x - gain * delayed;
+		self.line.push(v);
+		delayed + gain * v
+	}
+}
+
+
+// feedback delay network. eight lines of different lengths feed back
+// through a hadamard matrix, which mixes every line into every other
+// without gaining or losing energy, so the decay is set by the gain and
+// damping each line puts on its way round. the input is diffused first,
+// the left side feeds the even lines and the right the odd ones
+pub struct ReverbEffect {
+	lines: Vec<DelayLine>,
+	damping: Vec<BiQuadraticFilter>,
+	lfos: Vec<Lfo>,
+	diffusers: [Vec<Diffuser>; 2],
+	predelay: [DelayLine; 2],
+	size: Smoothed,
+
+	decay: f32,
+	predelay_time: f32,
+	diffusion: f32,
+	modulation: f32,
+	width: f32,
+	freeze: bool,
+	mix: f32,
+	sample_rate: f32,
+
+	params: Weak<AndrewParams>,
+}
+
+impl AndrewEffect for ReverbEffect {
+	fn process_stereo(&mut self, in_bufs: [&[f32]; 2], out_bufs: [&mut [f32]; 2]) {
+		let [out_l, out_r] = out_bufs;
+		let fs = self.sample_rate;
+		let predelay = self.predelay_time * fs;
+		let mod_depth = self.modulation * REVERB_MOD_DEPTH * fs;
+		let diffusion = 0.75 * self.diffusion;
+
+		// feedback gain for each line that brings it down 60 db in `decay`,
+		// worked out once a block for the size at the start of it
+		let mut gains = [1.0; FDN_LINES];
+		if !self.freeze {
+			for (gain, len) in gains.iter_mut().zip(FDN_LENGTHS.iter()) {
+				*gain = 10f32.powf(-3.0 * len * self.size.get() / self.decay);
+			}
+		}
+		let input_gain = if self.freeze { 0.0 } else { 1.0 };
+
+		let mut taps = [0.0; FDN_LINES];
+		for i in 0..in_bufs[0].len() {
+			let size = self.size.tick();
+
+			let mut input = [0.0; 2];
+			for (chan, input) in input.iter_mut().enumerate() {
+				self.predelay[chan].push(in_bufs[chan][i] * input_gain);
+				let mut x = if predelay >= 1.0 { self.predelay[chan].read(predelay) } else { in_bufs[chan][i] * input_gain };
+				for diffuser in self.diffusers[chan].iter_mut() {
+					x = diffuser.tick(x, diffusion);
+				}
+				*input = x;
+			}
+
+			for (line, tap) in taps.iter_mut().enumerate() {
+				let wobble = mod_depth * (0.5 + 0.5 * self.lfos[line].tick());
+				let delay = FDN_LENGTHS[line] * size * fs;
+				*tap = if self.freeze {
+					// held still on whole samples, so the interpolation
+					// doesn't slowly eat the frozen tail
+					self.lines[line].read(delay.round())
+				} else {
+					self.damping[line].filter(self.lines[line].read(delay + wobble)) * gains[line]
+				};
+			}
+
+			let (mut left, mut right) = (0.0, 0.0);
+			for (line, tap) in taps.iter().enumerate() {
+				if line % 2 == 0 { left += tap } else { right += tap }
+			}
+
+			hadamard(&mut taps);
+			for (line, tap) in taps.iter().enumerate() {
+				self.lines[line].push(tap + input[line % 2]);
+			}
+
+			// the taps are already out of phase with each other, width
+			// only has to scale the side
+			let mid = 0.5 * (left + right);
+			let side = 0.5 * (left - right) * self.width;
+			let wet = 0.5;
+			out_l[i] = in_bufs[0][i] * (1.0 - self.mix) + (mid + side) * wet * self.mix;
+			out_r[i] = in_bufs[1][i] * (1.0 - self.mix) + (mid - side) * wet * self.mix;
+		}
+	}
+
+	fn update_params(&mut self) {
+		if let Some(params) = self.params.upgrade() {
+			self.size.set(params.reverb_size.get().min(REVERB_MAX_SIZE));
+			self.decay = params.reverb_decay.get().max(0.01);
+			self.predelay_time = params.reverb_predelay.get().min(REVERB_MAX_PREDELAY);
+			self.diffusion = params.reverb_diffusion.get();
+			self.modulation = params.reverb_mod.get();
+			self.width = params.reverb_width.get();
+			self.freeze = params.reverb_freeze.load(Ordering::Relaxed);
+			self.mix = params.reverb_mix.get();
+
+			let sample_rate = params.sample_rate.get();
+			if sample_rate != self.sample_rate {
+				self.set_sample_rate(sample_rate);
+			}
+			let damping = params.reverb_damping.get().min(0.45 * sample_rate);
+			for filter in self.damping.iter_mut() {
+				filter.update_center_freq(damping);
+			}
+		}
+	}
+
+	fn get_latency(&self) -> usize {0}
+
+	fn get_tail_size(&self) -> usize {
+		if self.freeze {
+			return usize::MAX
+		}
+		((self.predelay_time + self.decay) * self.sample_rate) as usize
+	}
+}
+
+impl ReverbEffect {
+	pub fn new( params: Weak<AndrewParams> ) -> Self {
+		let mut reverb = ReverbEffect {
+			lines: vec![],
+			damping: vec![],
+			lfos: vec![],
+			diffusers: [vec![], vec![]],
+			predelay: [DelayLine::new(1), DelayLine::new(1)],
+			size: Smoothed::new(1.0),
+			decay: 2.0,
+			predelay_time: 0.01,
+			diffusion: 0.7,
+			modulation: 0.3,
+			width: 1.0,
+			freeze: false,
+			mix: 0.3,
+			sample_rate: 0.0,
+			params,
+		};
+		reverb.set_sample_rate(44100.0);
+		reverb
+	}
+
+	// everything sized in seconds gets rebuilt, and the tail is dropped
+	fn set_sample_rate( &mut self, sample_rate: f32 ) {
+		self.sample_rate = sample_rate;
+		let mod_len = (REVERB_MOD_DEPTH * sample_rate) as usize;
+
+		self.lines = FDN_LENGTHS.iter()
+			.map(|len| DelayLine::new((len * REVERB_MAX_SIZE * sample_rate) as usize + mod_len + 4))
+			.collect();
+		self.damping = vec![BiQuadraticFilter::new(LOWPASS, 8000.0, sample_rate, 0.5, 0.0); FDN_LINES];
+		self.lfos = (0..FDN_LINES)
+			.map(|line| {
+				let mut lfo = Lfo::default();
+				lfo.set_sample_rate(sample_rate);
+				// slightly different rates keep the lines from wobbling together
+				lfo.set_freq(0.5 + 0.1 * line as f32);
+				lfo.set_phase(line as f32 / FDN_LINES as f32);
+				lfo
+			})
+			.collect();
+		for (chan, diffusers) in self.diffusers.iter_mut().enumerate() {
+			*diffusers = DIFFUSER_LENGTHS.iter()
+				.map(|len| {
+					// the right side runs a touch longer so the sides decorrelate
+					let len = len * sample_rate * (1.0 + 0.07 * chan as f32);
+					Diffuser { line: DelayLine::new(len as usize + 4), len }
+				})
+				.collect();
+		}
+		let predelay_len = (REVERB_MAX_PREDELAY * sample_rate) as usize + 4;
+		self.predelay = [DelayLine::new(predelay_len), DelayLine::new(predelay_len)];
+		self.size.set_time(0.1, sample_rate);
+	}
+}
+
+
+// in place fast walsh hadamard transform, scaled to stay orthonormal
+#[inline]
+fn hadamard( x: &mut [f32; FDN_LINES] ) {
+	let mut size = 1;
+	while size < FDN_LINES {
+		for start in (0..FDN_LINES).step_by(2 * size) {
+			for i in start..start + size {
+				let (a, b) = (x[i], x[i + size]);
+				x[i] = a + b;
+				x[i + size] = a - b;
+			}
+		}
+		size *= 2;
+	}
+	let norm = 1.0 / (FDN_LINES as f32).sqrt();
+	x.iter_mut().for_each(|elm| *elm *= norm);
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::Arc;
+	use crate::noise::Noise;
+
+	fn db( buf: &[f32] ) -> f32 {
+		10.0 * (buf.iter().map(|x| x * x).sum::<f32>() / buf.len() as f32).log10()
+	}
+
+	fn reverb( params: &Arc<AndrewParams> ) -> ReverbEffect {
+		params.reverb_mix.set(1.0);
+		params.reverb_predelay.set(0.0);
+		params.reverb_mod.set(0.0);
+		params.reverb_damping.set(20000.0);
+		let mut reverb = ReverbEffect::new(Arc::downgrade(params));
+		reverb.update_params();
+		reverb
+	}
+
+	fn run( reverb: &mut ReverbEffect, input: &[f32] ) -> Vec<f32> {
+		let (mut left, mut right) = (vec![0.0; input.len()], vec![0.0; input.len()]);
+		for ((ins, l), r) in input.chunks(512).zip(left.chunks_mut(512)).zip(right.chunks_mut(512)) {
+			reverb.process_stereo([ins, ins], [l, r]);
+		}
+		left
+	}
+
+	#[test]
+	fn tail_falls_60_db_in_the_decay_time() {
+		for decay in [0.5, 1.0, 2.0] {
+			let params = Arc::new(AndrewParams::default());
+			params.reverb_decay.set(decay);
+			let mut reverb = reverb(&params);
+			let mut input = vec![0.0; (1.5 * decay * 44100.0) as usize];
+			input[0] = 1.0;
+			let output = run(&mut reverb, &input);
+
+			// least squares slope of the level in 10 ms steps, once the
+			// diffusion has built up
+			let points: Vec<(f32, f32)> = output.chunks(441)
+				.enumerate()
+				.skip(20)
+				.map(|(i, chunk)| (i as f32 * 0.01, db(chunk)))
+				.collect();
+			let n = points.len() as f32;
+			let (mean_t, mean_db) = points.iter().fold((0.0, 0.0), |(t, l), (pt, pl)| (t + pt / n, l + pl / n));
+			let (cov, var) = points.iter().fold((0.0, 0.0), |(c, v), (t, l)| (c + (t - mean_t) * (l - mean_db), v + (t - mean_t).powi(2)));
+			let rt60 = -60.0 / (cov / var);
+			assert!((rt60 / decay - 1.0).abs() < 0.1, "{} s decay came out as {} s", decay, rt60);
+		}
+	}
+
+	#[test]
+	fn freeze_holds_the_tail() {
+		let params = Arc::new(AndrewParams::default());
+		let mut reverb = reverb(&params);
+		let mut noise = Noise::new(3);
+		let input: Vec<f32> = (0..22050).map(|_| 0.25 * noise.white()).collect();
+		run(&mut reverb, &input);
+
+		// held for seconds, and what comes in meanwhile stays out of it
+		params.reverb_freeze.store(true, Ordering::Relaxed);
+		reverb.update_params();
+		assert_eq!(reverb.get_tail_size(), usize::MAX);
+		let output = run(&mut reverb, &input.iter().cycle().take(176400).copied().collect::<Vec<_>>());
+		let start = db(&output[..22050]);
+		for at in (22050..176400).step_by(22050) {
+			assert!((db(&output[at..at + 22050]) - start).abs() < 0.5, "{} db against {} at {}", db(&output[at..at + 22050]), start, at);
+		}
+		assert!(start > -30.0);
+	}
+}
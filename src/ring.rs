@@ -0,0 +1,199 @@
+use std::f32::consts::TAU;
+use std::sync::{Weak, atomic::Ordering};
+
+use crate::AndrewParams;
+use crate::andrew_effect::AndrewEffect;
+use crate::modulator::{Lfo, LfoShape};
+
+// olli niemitalo's allpass coefficients. the two chains stay 90 degrees
+// apart to within a fraction of a degree from about 0.0005 fs up to
+// 0.4995 fs, so they hold whatever the sample rate
+const HILBERT_A: [f32; 4] = [0.692_387_8, 0.936_065_4, 0.988_229_5, 0.998_748_8];
+const HILBERT_B: [f32; 4] = [0.402_192_1, 0.856_171_1, 0.972_291, 0.995_288_4];
+
+// keeps the shifter's feedback loop from running away
+const SHIFT_MAX_FEEDBACK: f32 = 0.95;
+
+
+// a chain of second order allpasses in z^-2, each one
+// y[n] = a^2 (x[n] + y[n-2]) - x[n-2]
+#[derive(Clone, Default)]
+struct AllpassChain {
+	coeffs: [f32; 4],
+	x: [[f32; 2]; 4],
+	y: [[f32; 2]; 4],
+}
+
+impl AllpassChain {
+	fn new( coeffs: [f32; 4] ) -> Self {
+		AllpassChain {
+			coeffs: coeffs.map(|a| a * a),
+			..Default::default()
+		}
+	}
+
+	#[inline]
+	fn tick( &mut self, mut samp: f32 ) -> f32 {
+		for i in 0..4 {
+			let out = self.coeffs[i] * (samp + self.y[i][1]) - self.x[i][1];
+			self.x[i] = [samp, self.x[i][0]];
+			self.y[i] = [out, self.y[i][0]];
+			samp = out;
+		}
+		samp
+	}
+}
+
+
+// splits a signal into two outputs a quarter turn apart,
+// the real and imaginary parts of its analytic signal
+#[derive(Clone)]
+pub struct Hilbert {
+	a: AllpassChain,
+	b: AllpassChain,
+	// chain a runs a sample ahead
+	delay: f32,
+}
+
+impl Hilbert {
+	pub fn new() -> Self {
+		Hilbert {
+			a: AllpassChain::new(HILBERT_A),
+			b: AllpassChain::new(HILBERT_B),
+			delay: 0.0,
+		}
+	}
+
+	#[inline]
+	pub fn tick( &mut self, samp: f32 ) -> (f32, f32) {
+		let im = std::mem::replace(&mut self.delay, self.a.tick(samp));
+		(self.b.tick(samp), im)
+	}
+}
+
+
+// ring modulator, or a bode style frequency shifter. the ring mode
+// multiplies by a carrier and so gives both sidebands, the shifter
+// multiplies the analytic signal by a complex carrier and keeps one,
+// moving every partial by the same number of hz
+pub struct RingEffect {
+	carrier: [Lfo; 2],
+	hilbert: [Hilbert; 2],
+	feedback_samp: [f32; 2],
+
+	shift: bool,
+	ring_freq: f32,
+	shift_hz: f32,
+	feedback: f32,
+
+	params: Weak<AndrewParams>,
+}
+
+impl AndrewEffect for RingEffect {
+	fn process(&mut self, chan_id: usize, in_buf: &[f32], out_buf: &mut [f32]) {
+		let carrier = &mut self.carrier[chan_id];
+
+		if !self.shift {
+			for (samp, out) in in_buf.iter().zip(out_buf.iter_mut()) {
+				*out = samp * carrier.tick();
+			}
+			return
+		}
+
+		let hilbert = &mut self.hilbert[chan_id];
+		let feedback_samp = &mut self.feedback_samp[chan_id];
+		for (samp, out) in in_buf.iter().zip(out_buf.iter_mut()) {
+			let (re, im) = hilbert.tick(samp + self.feedback * *feedback_samp);
+			let phase = carrier.phase() * TAU;
+			carrier.forward(1);
+
+			*feedback_samp = re * phase.cos() - im * phase.sin();
+			*out = *feedback_samp;
+		}
+	}
+
+	fn update_params(&mut self) {
+		if let Some(params) = self.params.upgrade() {
+			let shift = params.ring_shift.load(Ordering::Relaxed);
+			if shift != self.shift {
+				self.shift = shift;
+				self.feedback_samp = [0.0; 2];
+			}
+			self.ring_freq = params.ring_freq.get();
+			self.shift_hz = params.shift_hz.get();
+			self.feedback = params.shift_feedback.get().min(SHIFT_MAX_FEEDBACK);
+
+			let shape = LfoShape::from_index(params.ring_shape.load(Ordering::Relaxed));
+			let sample_rate = params.sample_rate.get();
+			for carrier in self.carrier.iter_mut() {
+				carrier.set_sample_rate(sample_rate);
+				if self.shift {
+					carrier.set_shape(LfoShape::Sine);
+					carrier.set_freq(self.shift_hz);
+				} else {
+					carrier.set_shape(shape);
+					carrier.set_freq(self.ring_freq);
+				}
+			}
+		}
+	}
+
+	fn get_latency(&self) -> usize {0}
+}
+
+impl RingEffect {
+	pub fn new( params: Weak<AndrewParams> ) -> Self {
+		RingEffect {
+			carrier: [Lfo::default(), Lfo::default()],
+			hilbert: [Hilbert::new(), Hilbert::new()],
+			feedback_samp: [0.0; 2],
+			shift: false,
+			ring_freq: 440.0,
+			shift_hz: 0.0,
+			feedback: 0.0,
+			params,
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::Arc;
+
+	// level of `freq` in the buffer, correlated against a complex tone
+	fn level( buf: &[f32], freq: f32 ) -> f32 {
+		let (re, im) = buf.iter().enumerate().fold((0.0, 0.0), |(re, im), (i, samp)| {
+			let phase = TAU * freq * i as f32 / 44100.0;
+			(re + samp * phase.cos(), im + samp * phase.sin())
+		});
+		2.0 * (re * re + im * im).sqrt() / buf.len() as f32
+	}
+
+	fn shift( hz: f32 ) -> Vec<f32> {
+		let params = Arc::new(AndrewParams::default());
+		params.ring_shift.store(true, Ordering::Relaxed);
+		params.shift_hz.set(hz);
+		let mut shifter = RingEffect::new(Arc::downgrade(&params));
+		shifter.update_params();
+
+		let input: Vec<f32> = (0..44100).map(|i| (TAU * 1000.0 * i as f32 / 44100.0).sin() * 0.5).collect();
+		let mut output = vec![0.0; input.len()];
+		shifter.process(0, &input, &mut output);
+		// past the allpasses settling
+		output.split_off(4410)
+	}
+
+	#[test]
+	fn shifter_moves_up_or_down_by_the_shift() {
+		let up = shift(100.0);
+		assert!((level(&up, 1100.0) - 0.5).abs() < 0.02, "{}", level(&up, 1100.0));
+		assert!(level(&up, 900.0) < 0.005);
+		assert!(level(&up, 1000.0) < 0.005);
+
+		let down = shift(-100.0);
+		assert!((level(&down, 900.0) - 0.5).abs() < 0.02, "{}", level(&down, 900.0));
+		assert!(level(&down, 1100.0) < 0.005);
+	}
+}
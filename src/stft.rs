@@ -0,0 +1,258 @@
+use std::f32::consts::TAU;
+
+use crate::andrew_effect::AndrewEffect;
+use crate::fft::{Complex, Fft};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum StftWindow {
+	Hann,
+}
+
+impl StftWindow {
+	// periodic, so overlapped copies line up
+	pub fn build( &self, len: usize ) -> Vec<f32> {
+		(0..len)
+			.map(|i| {
+				let x = TAU * i as f32 / len as f32;
+				match self {
+					StftWindow::Hann => 0.5 - 0.5 * x.cos(),
+				}
+			})
+			.collect()
+	}
+}
+
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct StftConfig {
+	pub window: StftWindow,
+	// a power of two
+	pub fft_len: usize,
+	// frames per fft_len, the hop is fft_len / overlap
+	pub overlap: usize,
+}
+
+impl StftConfig {
+	pub fn hop( &self ) -> usize {
+		self.fft_len / self.overlap
+	}
+
+	// the analysis window, and the synthesis window scaled so that the
+	// pair overlap adds to one through an unscaled inverse fft
+	pub fn windows( &self ) -> (Vec<f32>, Vec<f32>) {
+		let len = self.fft_len;
+		let hop = self.hop();
+		let analysis = self.window.build(len);
+
+		// what every output sample gets from the overlapping window pairs
+		let sum: Vec<f32> = (0..hop)
+			.map(|i| (i..len).step_by(hop).map(|j| analysis[j] * analysis[j]).sum())
+			.collect();
+		let synthesis = analysis.iter()
+			.enumerate()
+			.map(|(i, w)| w / sum[i % hop].max(1e-9) / len as f32)
+			.collect();
+		(analysis, synthesis)
+	}
+
+	// gain that brings frames with randomised phases back up to the level
+	// of the input. their energy is spread evenly over the frame, and the
+	// overlapping frames add up by power rather than amplitude
+	pub fn random_phase_gain( &self ) -> f32 {
+		let (analysis, synthesis) = self.windows();
+		let len = self.fft_len as f32;
+		let spread = analysis.iter().map(|w| w * w).sum::<f32>() / len;
+		let overlap = synthesis.iter().map(|w| (w * len).powi(2)).sum::<f32>() / self.hop() as f32;
+		1.0 / (spread * overlap).sqrt()
+	}
+}
+
+impl Default for StftConfig {
+	fn default() -> Self {
+		StftConfig {
+			window: StftWindow::Hann,
+			fft_len: 2048,
+			overlap: 4,
+		}
+	}
+}
+
+
+// what a spectral effect implements. StftEffect does the framing and
+// hands over each frame as bins, dc up to nyquist. whatever is left in
+// them is resynthesised, the mirrored half is filled in after
+pub trait SpectralProcessor: Send {
+	fn process_frame( &mut self, chan_id: usize, bins: &mut [Complex] );
+
+	// called before the first frame and whenever the framing changes
+	fn configure( &mut self, _config: StftConfig ) {}
+
+	fn update_params( &mut self ) {}
+
+	// the framing wanted, checked after every param update
+	fn config( &self ) -> StftConfig {
+		StftConfig::default()
+	}
+}
+
+
+// runs a SpectralProcessor as an AndrewEffect. input is windowed and
+// transformed every hop, and the frames that come back are windowed again
+// and overlap added. the synthesis window is scaled so that the two
+// windows overlap add to one for any window and overlap, so a processor
+// that leaves the bins alone gives the input back, fft_len samples late
+pub struct StftEffect<P: SpectralProcessor> {
+	processor: P,
+	config: StftConfig,
+	fft: Fft,
+	analysis: Vec<f32>,
+	synthesis: Vec<f32>,
+	frame: Vec<Complex>,
+	chans: [StftChannel; 2],
+}
+
+struct StftChannel {
+	// the last fft_len samples of input
+	input: Vec<f32>,
+	// samples written since the last frame
+	fill: usize,
+	// overlap added output, the front hop of it is finished after each frame
+	accum: Vec<f32>,
+	// finished samples being played back
+	ready: Vec<f32>,
+}
+
+impl StftChannel {
+	fn new( config: StftConfig ) -> Self {
+		StftChannel {
+			input: vec![0.0; config.fft_len],
+			fill: 0,
+			accum: vec![0.0; config.fft_len],
+			ready: vec![0.0; config.hop()],
+		}
+	}
+}
+
+impl<P: SpectralProcessor> AndrewEffect for StftEffect<P> {
+	fn process(&mut self, chan_id: usize, in_buf: &[f32], out_buf: &mut [f32]) {
+		let len = self.config.fft_len;
+		let hop = self.config.hop();
+
+		for (samp, out) in in_buf.iter().zip(out_buf.iter_mut()) {
+			let chan = &mut self.chans[chan_id];
+			chan.input[len - hop + chan.fill] = *samp;
+			*out = chan.ready[chan.fill];
+			chan.fill += 1;
+
+			if chan.fill == hop {
+				self.run_frame(chan_id);
+				let chan = &mut self.chans[chan_id];
+				chan.input.copy_within(hop.., 0);
+				chan.fill = 0;
+			}
+		}
+	}
+
+	fn update_params(&mut self) {
+		self.processor.update_params();
+		let config = self.processor.config();
+		if config != self.config {
+			self.set_config(config);
+		}
+	}
+
+	fn get_latency(&self) -> usize {
+		self.config.fft_len
+	}
+}
+
+impl<P: SpectralProcessor> StftEffect<P> {
+	pub fn new( processor: P ) -> Self {
+		let config = processor.config();
+		let mut stft = StftEffect {
+			processor,
+			config,
+			fft: Fft::new(config.fft_len),
+			analysis: vec![],
+			synthesis: vec![],
+			frame: vec![],
+			chans: [StftChannel::new(config), StftChannel::new(config)],
+		};
+		stft.set_config(config);
+		stft
+	}
+
+	// changing the framing starts over from silence
+	pub fn set_config( &mut self, config: StftConfig ) {
+		assert!(config.overlap >= 1 && config.fft_len.is_multiple_of(config.overlap), "overlap has to divide the fft size");
+		self.config = config;
+		self.fft = Fft::new(config.fft_len);
+		(self.analysis, self.synthesis) = config.windows();
+		self.frame = vec![Complex::default(); config.fft_len];
+
+		self.chans = [StftChannel::new(config), StftChannel::new(config)];
+		self.processor.configure(config);
+	}
+
+	fn run_frame( &mut self, chan_id: usize ) {
+		let len = self.config.fft_len;
+		let half = len / 2;
+		let hop = self.config.hop();
+		let chan = &mut self.chans[chan_id];
+
+		for ((bin, samp), w) in self.frame.iter_mut().zip(chan.input.iter()).zip(self.analysis.iter()) {
+			*bin = Complex::new(samp * w, 0.0);
+		}
+		self.fft.forward(&mut self.frame);
+
+		self.processor.process_frame(chan_id, &mut self.frame[..=half]);
+		// dc and nyquist of a real signal are real
+		self.frame[0].im = 0.0;
+		self.frame[half].im = 0.0;
+		for k in half + 1..len {
+			self.frame[k] = self.frame[len - k].conj();
+		}
+		self.fft.inverse(&mut self.frame);
+
+		for ((acc, bin), w) in chan.accum.iter_mut().zip(self.frame.iter()).zip(self.synthesis.iter()) {
+			*acc += bin.re * w;
+		}
+		chan.ready.copy_from_slice(&chan.accum[..hop]);
+		chan.accum.copy_within(hop.., 0);
+		chan.accum[len - hop..].iter_mut().for_each(|acc| *acc = 0.0);
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	struct Passthrough( StftConfig );
+
+	impl SpectralProcessor for Passthrough {
+		fn process_frame( &mut self, _chan_id: usize, _bins: &mut [Complex] ) {}
+
+		fn config( &self ) -> StftConfig {
+			self.0
+		}
+	}
+
+	#[test]
+	fn untouched_frames_come_back_as_late_as_reported() {
+		let input: Vec<f32> = (0..16384).map(|i| (i as f32 * 0.01).sin() * 0.5 + (i as f32 * 0.37).cos() * 0.25).collect();
+		let window = StftWindow::Hann;
+		for (fft_len, overlap) in [(512, 4), (1024, 8), (2048, 4)] {
+			let mut stft = StftEffect::new(Passthrough(StftConfig { window, fft_len, overlap }));
+			let mut output = vec![0.0; input.len()];
+			for (ins, outs) in input.chunks(300).zip(output.chunks_mut(300)) {
+				stft.process(0, ins, outs);
+			}
+
+			let latency = stft.get_latency();
+			for (out, samp) in output[latency..].iter().zip(input.iter()) {
+				assert!((out - samp).abs() < 1e-4, "{:?} {} / {}: {} came back as {}", window, fft_len, overlap, samp, out);
+			}
+		}
+	}
+}
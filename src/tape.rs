@@ -0,0 +1,323 @@
+use std::f32::consts::TAU;
+use std::sync::{Weak, atomic::Ordering};
+
+use crate::AndrewParams;
+use crate::andrew_effect::{AndrewEffect, db_to_gain};
+use crate::biquad::{BiQuadraticFilter, FilterKind::*};
+use crate::delay_line::DelayLine;
+use crate::noise::Noise;
+
+// jiles-atherton constants for the tape, with the saturation magnetisation
+// at one. `a` sets the small signal slope to about one, `k` how wide the
+// loop opens and `c` how much of the magnetisation is reversible
+const JA_A: f32 = 0.33;
+const JA_K: f32 = 0.1;
+const JA_C: f32 = 0.9;
+const JA_ALPHA: f32 = 0.01;
+
+// the hysteresis is stiff, so it steps at a few times the sample rate
+const TAPE_SUBSTEPS: usize = 4;
+
+// largest pitch deviation from wow and from flutter, as a fraction
+const WOW_MAX_DEV: f32 = 0.004;
+const FLUTTER_MAX_DEV: f32 = 0.0015;
+
+// turns per second of the reels
+const WOW_RATE: f32 = 0.6;
+
+// delay the wobble swings around, in seconds
+const TAPE_BASE_DELAY: f32 = 0.01;
+
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TapeSpeed {
+	Ips7,
+	Ips15,
+	Ips30,
+}
+
+impl TapeSpeed {
+	pub const COUNT: u8 = 3;
+
+	pub fn from_index( i: u8 ) -> Self {
+		match i {
+			0 => TapeSpeed::Ips7,
+			1 => TapeSpeed::Ips15,
+			_ => TapeSpeed::Ips30,
+		}
+	}
+
+	pub fn name( &self ) -> &'static str {
+		match self {
+			TapeSpeed::Ips7 => "7.5 ips",
+			TapeSpeed::Ips15 => "15 ips",
+			TapeSpeed::Ips30 => "30 ips",
+		}
+	}
+
+	// head bump frequency and gain, high frequency loss corner, capstan
+	// flutter rate and hiss offset in db. slower tape bumps lower, rolls
+	// off earlier, flutters slower and hisses more
+	fn voicing( &self ) -> (f32, f32, f32, f32, f32) {
+		match self {
+			TapeSpeed::Ips7 => (45.0, 3.5, 9000.0, 5.0, 3.0),
+			TapeSpeed::Ips15 => (70.0, 2.5, 15000.0, 9.0, 0.0),
+			TapeSpeed::Ips30 => (110.0, 1.5, 20000.0, 16.0, -3.0),
+		}
+	}
+}
+
+
+// magnetisation of the tape against the field the head puts on it, after
+// jiles and atherton. the loop makes the output lag the input on the way
+// back down, and the anhysteretic curve saturates it
+#[derive(Clone, Copy, Default)]
+struct Hysteresis {
+	m: f32,
+	h: f32,
+	h_d: f32,
+}
+
+impl Hysteresis {
+	// langevin function and its derivative
+	#[inline]
+	fn langevin( q: f32 ) -> (f32, f32) {
+		if q.abs() < 1e-3 {
+			(q / 3.0, 1.0 / 3.0)
+		} else {
+			let coth = 1.0 / q.tanh();
+			(coth - 1.0 / q, 1.0 / (q * q) - coth * coth + 1.0)
+		}
+	}
+
+	#[inline]
+	fn dm_dt( m: f32, h: f32, h_d: f32 ) -> f32 {
+		let (l, l_d) = Hysteresis::langevin((h + JA_ALPHA * m) / JA_A);
+		let m_diff = l - m;
+		let delta = if h_d >= 0.0 { 1.0 } else { -1.0 };
+		let delta_m = if delta * m_diff > 0.0 { 1.0 } else { 0.0 };
+
+		let irreversible = (1.0 - JA_C) * delta_m * m_diff / ((1.0 - JA_C) * delta * JA_K - JA_ALPHA * m_diff);
+		let reversible = JA_C / JA_A * l_d;
+		(irreversible + reversible) * h_d / (1.0 - JA_C * JA_ALPHA / JA_A * l_d)
+	}
+
+	// second order runge kutta over the sample, the field
+	// moving in a straight line from the last one
+	#[inline]
+	fn tick( &mut self, h: f32, sample_rate: f32 ) -> f32 {
+		let dt = 1.0 / (sample_rate * TAPE_SUBSTEPS as f32);
+		let h_d = (h - self.h) * sample_rate;
+		let step = (h - self.h) / TAPE_SUBSTEPS as f32;
+
+		for _ in 0..TAPE_SUBSTEPS {
+			let k1 = dt * Hysteresis::dm_dt(self.m, self.h, self.h_d);
+			let k2 = dt * Hysteresis::dm_dt(self.m + 0.5 * k1, self.h + 0.5 * step, 0.5 * (self.h_d + h_d));
+			self.m = (self.m + k2).clamp(-1.0, 1.0);
+			self.h += step;
+			self.h_d = h_d;
+		}
+		if !self.m.is_finite() { self.m = 0.0 }
+		self.h = h;
+		self.m
+	}
+}
+
+
+// slow random wander, white noise through two one pole lowpasses and
+// scaled back up to unit variance
+#[derive(Clone)]
+struct Drift {
+	noise: Noise,
+	coeff: f32,
+	norm: f32,
+	state: [f32; 2],
+}
+
+impl Drift {
+	fn new( seed: u32 ) -> Self {
+		Drift { noise: Noise::new(seed), coeff: 0.0, norm: 1.0, state: [0.0; 2] }
+	}
+
+	fn set( &mut self, freq: f32, sample_rate: f32 ) {
+		let c = (-TAU * freq / sample_rate).exp();
+		self.coeff = c;
+		// power gain of the two poles, against the uniform noise's 1/3
+		let gain = (1.0 - c).powi(4) * (1.0 + c * c) / (1.0 - c * c).powi(3);
+		self.norm = (3.0 / gain).sqrt();
+	}
+
+	#[inline]
+	fn tick( &mut self ) -> f32 {
+		let c = self.coeff;
+		self.state[0] = c * self.state[0] + (1.0 - c) * self.noise.white();
+		self.state[1] = c * self.state[1] + (1.0 - c) * self.state[0];
+		self.state[1] * self.norm
+	}
+}
+
+
+// tape machine. the input is driven into a hysteresis loop, hiss is laid
+// on, the lot wobbles through a modulated delay and is played back through
+// the head bump and the high end loss. the speed moves all of it
+pub struct TapeEffect {
+	hysteresis: [Hysteresis; 2],
+	delay: [DelayLine; 2],
+	bump: [BiQuadraticFilter; 2],
+	low_cut: [BiQuadraticFilter; 2],
+	hf_loss: [BiQuadraticFilter; 2],
+	hiss: [Noise; 2],
+
+	// the same transport moves both channels
+	wow_phase: f32,
+	flutter_phase: f32,
+	wow_drift: Drift,
+	flutter_drift: Drift,
+
+	speed: TapeSpeed,
+	drive: f32,
+	wow: f32,
+	flutter: f32,
+	hiss_gain: f32,
+	sample_rate: f32,
+
+	params: Weak<AndrewParams>,
+}
+
+impl AndrewEffect for TapeEffect {
+	fn process_stereo(&mut self, in_bufs: [&[f32]; 2], mut out_bufs: [&mut [f32]; 2]) {
+		let (_, _, _, flutter_rate, _) = self.speed.voicing();
+		let wow_rate = WOW_RATE;
+
+		// depth in samples of delay swing for a given pitch deviation
+		let wow_depth = self.wow * WOW_MAX_DEV * self.sample_rate / (TAU * wow_rate);
+		let flutter_depth = self.flutter * FLUTTER_MAX_DEV * self.sample_rate / (TAU * flutter_rate);
+		let base = TAPE_BASE_DELAY * self.sample_rate;
+
+		for (chan, out_buf) in out_bufs.iter_mut().enumerate() {
+			let mut wow_phase = self.wow_phase;
+			let mut flutter_phase = self.flutter_phase;
+			let mut wow_drift = self.wow_drift.clone();
+			let mut flutter_drift = self.flutter_drift.clone();
+
+			for (samp, out) in in_bufs[chan].iter().zip(out_buf.iter_mut()) {
+				let magnetised = self.hysteresis[chan].tick(samp * self.drive, self.sample_rate) / self.drive;
+				let hiss = self.hiss_gain * self.hiss[chan].white();
+				self.delay[chan].push(magnetised + hiss);
+
+				// a periodic part from the reels and capstan, and a wander
+				wow_phase = (wow_phase + wow_rate / self.sample_rate).fract();
+				flutter_phase = (flutter_phase + flutter_rate / self.sample_rate).fract();
+				let wow = 0.7 * (TAU * wow_phase).sin() + 0.3 * wow_drift.tick();
+				let flutter = 0.6 * (TAU * flutter_phase).sin()
+					+ 0.2 * (2.0 * TAU * flutter_phase).sin()
+					+ 0.2 * flutter_drift.tick();
+				let wobbled = self.delay[chan].read(base + wow_depth * wow + flutter_depth * flutter);
+
+				let played = self.hf_loss[chan].filter(self.bump[chan].filter(self.low_cut[chan].filter(wobbled)));
+				*out = played;
+			}
+
+			// both channels run the same transport from the same start
+			if chan == 1 {
+				self.wow_phase = wow_phase;
+				self.flutter_phase = flutter_phase;
+				self.wow_drift = wow_drift;
+				self.flutter_drift = flutter_drift;
+			}
+		}
+	}
+
+	fn update_params(&mut self) {
+		if let Some(params) = self.params.upgrade() {
+			self.speed = TapeSpeed::from_index(params.tape_speed.load(Ordering::Relaxed));
+			self.drive = db_to_gain(params.tape_drive.get());
+			self.wow = params.tape_wow.get();
+			self.flutter = params.tape_flutter.get();
+			self.sample_rate = params.sample_rate.get();
+
+			let (bump_freq, bump_gain, hf_freq, flutter_rate, hiss_offset) = self.speed.voicing();
+			// uniform noise sits 4.8 db under its peak, so the hiss is set as rms
+			self.hiss_gain = db_to_gain(params.tape_hiss.get() + hiss_offset) * 3f32.sqrt();
+			let nyquist = 0.45 * self.sample_rate;
+			for chan in 0..2 {
+				self.bump[chan].recfg(LOWSHELF, bump_freq, self.sample_rate, 0.7, bump_gain);
+				self.low_cut[chan].recfg(HIGHPASS, bump_freq / 3.0, self.sample_rate, 0.7, 0.0);
+				self.hf_loss[chan].recfg(LOWPASS, hf_freq.min(nyquist), self.sample_rate, 0.6, 0.0);
+			}
+			self.wow_drift.set(WOW_RATE, self.sample_rate);
+			self.flutter_drift.set(flutter_rate, self.sample_rate);
+
+			let max_delay = (2.0 * TAPE_BASE_DELAY * self.sample_rate) as usize + 4;
+			if self.delay[0].max_delay() != max_delay {
+				self.delay = [DelayLine::new(max_delay), DelayLine::new(max_delay)];
+			}
+		}
+	}
+
+	fn get_latency(&self) -> usize {
+		(TAPE_BASE_DELAY * self.sample_rate) as usize
+	}
+}
+
+impl TapeEffect {
+	pub fn new( params: Weak<AndrewParams> ) -> Self {
+		let filter = BiQuadraticFilter::new(LOWPASS, 15000.0, 44100.0, 0.7, 0.0);
+		let delay = DelayLine::new((2.0 * TAPE_BASE_DELAY * 44100.0) as usize + 4);
+		TapeEffect {
+			hysteresis: [Hysteresis::default(); 2],
+			delay: [delay.clone(), delay],
+			bump: [filter.clone(), filter.clone()],
+			low_cut: [filter.clone(), filter.clone()],
+			hf_loss: [filter.clone(), filter],
+			hiss: [Noise::new(0x1234_5678), Noise::new(0x8765_4321)],
+			wow_phase: 0.0,
+			flutter_phase: 0.0,
+			wow_drift: Drift::new(0x0bad_cafe),
+			flutter_drift: Drift::new(0x0dec_afe0),
+			speed: TapeSpeed::Ips15,
+			drive: 1.0,
+			wow: 0.0,
+			flutter: 0.0,
+			hiss_gain: 0.0,
+			sample_rate: 44100.0,
+			params,
+		}
+	}
+}
+
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::Arc;
+
+	#[test]
+	fn impulse_comes_out_at_the_latency() {
+		let params = Arc::new(AndrewParams::default());
+		params.tape_wow.set(0.0);
+		params.tape_flutter.set(0.0);
+		params.tape_hiss.set(-200.0);
+
+		for sample_rate in [44100.0, 48000.0, 96000.0] {
+			params.sample_rate.set(sample_rate);
+			for speed in 0..TapeSpeed::COUNT {
+				params.tape_speed.store(speed, Ordering::Relaxed);
+				let mut tape = TapeEffect::new(Arc::downgrade(&params));
+				tape.update_params();
+
+				let mut input = vec![0.0; 2 * tape.get_latency()];
+				input[0] = 0.1;
+				let (mut left, mut right) = (vec![0.0; input.len()], vec![0.0; input.len()]);
+				tape.process_stereo([&input, &input], [&mut left, &mut right]);
+
+				// it starts right on time, the playback filters smear the peak out by 25 us at most
+				let start = left.iter().position(|samp| samp.abs() > 1e-6);
+				let peak = (0..left.len()).max_by(|a, b| left[*a].abs().total_cmp(&left[*b].abs())).unwrap();
+				assert_eq!(start, Some(tape.get_latency()), "{} at {}", TapeSpeed::from_index(speed).name(), sample_rate);
+				assert!((peak - tape.get_latency()) as f32 <= 2.5e-5 * sample_rate, "{} at {} peaks at {}", TapeSpeed::from_index(speed).name(), sample_rate, peak);
+			}
+		}
+	}
+}
@@ -0,0 +1,207 @@
+use std::sync::{Weak, atomic::Ordering};
+
+use vst::api::{TimeInfo, TimeInfoFlags};
+
+use crate::AndrewParams;
+use crate::andrew_effect::{AndrewEffect, Smoothed};
+use crate::modulator::{Lfo, LfoShape, NoteDivision};
+
+// how far the lfo may be from where the host's position puts it, in
+// quarter notes, before it is snapped back onto the beat
+const SYNC_SLIP: f64 = 0.01;
+
+
+// gain wobbled by an lfo. the right side's lfo runs `offset` of a cycle
+// ahead of the left, so at half a cycle the two sides take turns and it
+// pans. the gain is worked out every sample and run through a short
+// smoother, which is what takes the click out of the square
+pub struct TremoloEffect {
+	lfos: [Lfo; 2],
+	gains: [Smoothed; 2],
+
+	rate: f32,
+	depth: f32,
+	offset: f32,
+	sync: bool,
+	division: NoteDivision,
+	sample_rate: f32,
+
+	// from the host, the tempo and, while it plays, the position in
+	// quarter notes at the start of the block
+	tempo: Option<f32>,
+	position: Option<f64>,
+
+	params: Weak<AndrewParams>,
+}
+
+impl AndrewEffect for TremoloEffect {
+	fn process_stereo(&mut self, in_bufs: [&[f32]; 2], out_bufs: [&mut [f32]; 2]) {
+		let [out_l, out_r] = out_bufs;
+		let quarters = self.division.quarters();
+
+		let freq = match self.tempo {
+			Some(tempo) if self.sync => tempo / 60.0 / quarters,
+			_ => self.rate,
+		};
+		self.lfos.iter_mut().for_each(|lfo| lfo.set_freq(freq));
+
+		// lock onto the beat when the transport starts or jumps, or once the
+		// lfo has wandered off it, and otherwise let it run so it doesn't
+		// step every block
+		if let (Some(position), Some(_), true) = (self.position, self.tempo, self.sync) {
+			let phase = (position / quarters as f64).rem_euclid(1.0) as f32;
+			let drift = (self.lfos[0].phase() - phase + 0.5).rem_euclid(1.0) - 0.5;
+			if (drift * quarters).abs() as f64 > SYNC_SLIP {
+				self.lfos[0].set_phase(phase);
+				self.lfos[1].set_phase(phase + self.offset);
+			}
+		}
+
+		for i in 0..in_bufs[0].len() {
+			let mut gains = [1.0; 2];
+			for (chan, gain) in gains.iter_mut().enumerate() {
+				self.gains[chan].set(1.0 - self.depth * (0.5 - 0.5 * self.lfos[chan].tick()));
+				*gain = self.gains[chan].tick();
+			}
+			out_l[i] = in_bufs[0][i] * gains[0];
+			out_r[i] = in_bufs[1][i] * gains[1];
+		}
+	}
+
+	fn time_info(&mut self, info: &TimeInfo) {
+		let has = |flag: TimeInfoFlags| info.flags & flag.bits() != 0;
+		self.tempo = if has(TimeInfoFlags::TEMPO_VALID) && info.tempo > 0.0 { Some(info.tempo as f32) } else { None };
+		self.position = if has(TimeInfoFlags::PPQ_POS_VALID) && has(TimeInfoFlags::TRANSPORT_PLAYING) { Some(info.ppq_pos) } else { None };
+	}
+
+	fn update_params(&mut self) {
+		if let Some(params) = self.params.upgrade() {
+			self.rate = params.tremolo_rate.get();
+			self.depth = params.tremolo_depth.get();
+			self.sync = params.tremolo_sync.load(Ordering::Relaxed);
+			self.division = NoteDivision::from_index(params.tremolo_division.load(Ordering::Relaxed));
+			self.sample_rate = params.sample_rate.get();
+
+			// moving the offset moves the right side from where the left is
+			let offset = params.tremolo_offset.get();
+			if offset != self.offset {
+				self.offset = offset;
+				let phase = self.lfos[0].phase();
+				self.lfos[1].set_phase(phase + offset);
+			}
+
+			let shape = LfoShape::from_index(params.tremolo_shape.load(Ordering::Relaxed));
+			for lfo in self.lfos.iter_mut() {
+				lfo.set_shape(shape);
+				lfo.set_sample_rate(self.sample_rate);
+			}
+			for gain in self.gains.iter_mut() {
+				gain.set_time(params.tremolo_smooth.get(), self.sample_rate);
+			}
+		}
+	}
+
+	fn get_latency(&self) -> usize {0}
+}
+
+impl TremoloEffect {
+	pub fn new( params: Weak<AndrewParams> ) -> Self {
+		TremoloEffect {
+			lfos: [Lfo::default(), Lfo::default()],
+			gains: [Smoothed::new(1.0), Smoothed::new(1.0)],
+			rate: 4.0,
+			depth: 0.5,
+			offset: 0.0,
+			sync: false,
+			division: NoteDivision::Eighth,
+			sample_rate: 44100.0,
+			tempo: None,
+			position: None,
+			params,
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::Arc;
+
+	const BLOCK: usize = 500;
+
+	// the tremolo on a steady 1, synced to eighths with the host playing
+	// from `start` for `blocks`, and moved to `jump` part way. gives the
+	// position in quarter notes of each dip of the gain
+	fn dips( tempo: f64, start: f64, jump: Option<(usize, f64)>, blocks: usize ) -> Vec<f64> {
+		let params = Arc::new(AndrewParams::default());
+		params.tremolo_depth.set(1.0);
+		params.tremolo_sync.store(true, Ordering::Relaxed);
+		let mut tremolo = TremoloEffect::new(Arc::downgrade(&params));
+		tremolo.update_params();
+
+		let per_samp = tempo / 60.0 / 44100.0;
+		let mut info = TimeInfo {
+			tempo,
+			flags: (TimeInfoFlags::TEMPO_VALID | TimeInfoFlags::PPQ_POS_VALID | TimeInfoFlags::TRANSPORT_PLAYING).bits(),
+			..Default::default()
+		};
+		let input = vec![1.0; BLOCK];
+		let (mut gains, mut positions) = (vec![], vec![]);
+		for block in 0..blocks {
+			info.ppq_pos = match jump {
+				Some((at, to)) if block >= at => to + (block - at) as f64 * BLOCK as f64 * per_samp,
+				_ => start + block as f64 * BLOCK as f64 * per_samp,
+			};
+			tremolo.time_info(&info);
+			let (mut left, mut right) = (vec![0.0; BLOCK], vec![0.0; BLOCK]);
+			tremolo.process_stereo([&input, &input], [&mut left, &mut right]);
+			gains.extend(left);
+			positions.extend((0..BLOCK).map(|i| info.ppq_pos + i as f64 * per_samp));
+		}
+
+		// the smoothing takes a moment to catch up with a jump
+		let settling = jump.map_or(0..0, |(at, _)| at * BLOCK..at * BLOCK + 441);
+		(1..gains.len() - 1)
+			.filter(|i| !settling.contains(i))
+			.filter(|i| gains[*i] < 0.1 && gains[*i] <= gains[i - 1] && gains[*i] < gains[i + 1])
+			.map(|i| positions[i])
+			.collect()
+	}
+
+	// how far off the beat a dip may be, the slip the sync allows and a
+	// couple of samples for the gain smoothing
+	fn slack( tempo: f64 ) -> f64 {
+		SYNC_SLIP + 3.0 * tempo / 60.0 / 44100.0
+	}
+
+	// how far `dip` is from the nearest eighth after `reference`
+	fn off_beat( dip: f64, reference: f64 ) -> f64 {
+		(dip - reference + 0.25).rem_euclid(0.5) - 0.25
+	}
+
+	#[test]
+	fn synced_lfo_follows_the_beat() {
+		for tempo in [90.0, 120.0, 174.0] {
+			let reference = dips(tempo, 0.0, None, 400)[0];
+			for (start, jump) in [(0.0, None), (0.3, None), (7.13, None), (0.0, Some((77, 31.4)))] {
+				let dips = dips(tempo, start, jump, 400);
+				assert!(dips.len() > 10, "only {} dips at {}", dips.len(), tempo);
+				for dip in dips {
+					let off = off_beat(dip, reference);
+					assert!(off.abs() < slack(tempo), "{} bpm from {} dips {} quarters off the beat at {}", tempo, start, off, dip);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn synced_lfo_stays_on_the_beat_for_minutes() {
+		// long enough for the lfo's own steps to add up to more than the slip
+		let dips = dips(90.0, 0.0, None, 10584);
+		for dip in dips.iter() {
+			let off = off_beat(*dip, dips[0]);
+			assert!(off.abs() < slack(90.0), "dips {} quarters off the beat at {}", off, dip);
+		}
+	}
+}
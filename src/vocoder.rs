@@ -0,0 +1,315 @@
+use std::sync::{Weak, atomic::Ordering};
+
+use crate::AndrewParams;
+use crate::andrew_effect::{AndrewEffect, SIDECHAIN_RESERVE};
+use crate::biquad::{BiQuadraticFilter, FilterKind::*};
+use crate::dynamics::Envelope;
+use crate::noise::Noise;
+
+pub const VOCODER_MIN_BANDS: usize = 8;
+pub const VOCODER_MAX_BANDS: usize = 32;
+
+// where the lowest and highest bands sit
+const VOCODER_LOW: f32 = 100.0;
+const VOCODER_HIGH: f32 = 8000.0;
+
+// how fast the band followers move, in seconds
+const VOCODER_ATTACK: f32 = 0.002;
+const VOCODER_RELEASE: f32 = 0.02;
+
+// above here the modulator counts as sibilance
+const SIBILANCE_FREQ: f32 = 5000.0;
+
+// keeps the carrier levelling from blowing up the carrier's noise floor
+const CARRIER_FLOOR: f32 = 1e-3;
+
+// a sidechain that peaks under the floor for the hold time, in seconds,
+// counts as unplugged. hosts feed a sidechain with nothing on it zeros
+const SIDECHAIN_FLOOR: f32 = 1e-6;
+const SIDECHAIN_HOLD: f32 = 0.5;
+
+
+// one band, the same bandpass on the modulator and on the carrier with
+// the carrier's moved by the formant shift. two biquads deep, so the
+// bands overlap less
+#[derive(Clone)]
+struct VocoderBand {
+	modulator: [BiQuadraticFilter; 2],
+	mod_env: Envelope,
+	carrier: [[BiQuadraticFilter; 2]; 2],
+	carrier_env: [Envelope; 2],
+}
+
+impl VocoderBand {
+	fn new( freq: f32, carrier_freq: f32, q: f32, sample_rate: f32 ) -> Self {
+		let filter = |freq: f32| BiQuadraticFilter::new(BANDPASS, freq, sample_rate, q, 0.0);
+		let pair = |freq: f32| [filter(freq), filter(freq)];
+		let mut env = Envelope::default();
+		env.set_times(VOCODER_ATTACK, VOCODER_RELEASE, sample_rate);
+		VocoderBand {
+			modulator: pair(freq),
+			mod_env: env,
+			carrier: [pair(carrier_freq), pair(carrier_freq)],
+			carrier_env: [env; 2],
+		}
+	}
+
+	// moves the band without starting it over, so the filters and
+	// followers carry on from where they were
+	fn retune( &mut self, freq: f32, carrier_freq: f32, q: f32, sample_rate: f32 ) {
+		let pairs = std::iter::once((&mut self.modulator, freq))
+			.chain(self.carrier.iter_mut().map(|pair| (pair, carrier_freq)));
+		for (pair, freq) in pairs {
+			pair.iter_mut().for_each(|filter| filter.recfg(BANDPASS, freq, sample_rate, q, 0.0));
+		}
+		for env in std::iter::once(&mut self.mod_env).chain(self.carrier_env.iter_mut()) {
+			env.set_times(VOCODER_ATTACK, VOCODER_RELEASE, sample_rate);
+		}
+	}
+}
+
+
+// band limited saw, a naive ramp with polyblep corners
+#[derive(Clone, Copy, Default)]
+struct Saw {
+	phase: f32,
+}
+
+impl Saw {
+	#[inline]
+	fn tick( &mut self, step: f32 ) -> f32 {
+		let blep = if self.phase < step {
+			let t = self.phase / step;
+			2.0 * t - t * t - 1.0
+		} else if self.phase > 1.0 - step {
+			let t = (self.phase - 1.0) / step;
+			t * t + 2.0 * t + 1.0
+		} else {
+			0.0
+		};
+		let out = 2.0 * self.phase - 1.0 - blep;
+		self.phase = (self.phase + step).fract();
+		out
+	}
+}
+
+
+// filter bank vocoder. the main input is the modulator, heard through as
+// many bandpasses as there are bands, each with an envelope follower.
+// the carrier, the sidechain pair or an internal saw and noise when it's
+// silent or the saw is asked for, goes through a matching bank. each carrier
+// band is levelled to one and given the modulator's envelope, so what
+// comes out follows the modulator's level whatever the carrier is. the
+// modulator's top end is let through on its own where it's unvoiced,
+// which the bands can't make from a carrier with nothing up there
+pub struct VocoderEffect {
+	// all VOCODER_MAX_BANDS of them, the first band_count are used
+	bands: Vec<VocoderBand>,
+	sibilance_filter: [BiQuadraticFilter; 2],
+	sibilance_env: Envelope,
+	modulator_env: Envelope,
+	sc_bufs: [Vec<f32>; 2],
+	// samples the sidechain has been silent for
+	sc_silence: usize,
+	saw: Saw,
+	noise: Noise,
+
+	band_count: usize,
+	shift: f32,
+	internal: bool,
+	pitch: f32,
+	noise_mix: f32,
+	sibilance: f32,
+	mix: f32,
+	sample_rate: f32,
+
+	params: Weak<AndrewParams>,
+}
+
+impl AndrewEffect for VocoderEffect {
+	fn process_stereo(&mut self, in_bufs: [&[f32]; 2], out_bufs: [&mut [f32]; 2]) {
+		let [out_l, out_r] = out_bufs;
+		let sc_live = self.sc_silence < (SIDECHAIN_HOLD * self.sample_rate) as usize;
+		let use_sc = !self.internal && sc_live && self.sc_bufs[0].len() >= in_bufs[0].len();
+		let step = self.pitch / self.sample_rate;
+
+		for i in 0..in_bufs[0].len() {
+			let modulator = 0.5 * (in_bufs[0][i] + in_bufs[1][i]);
+			let carrier = if use_sc {
+				[self.sc_bufs[0][i], self.sc_bufs[1][i]]
+			} else {
+				let internal = (1.0 - self.noise_mix) * self.saw.tick(step) + self.noise_mix * self.noise.white();
+				[internal; 2]
+			};
+
+			let mut wet = [0.0; 2];
+			for band in self.bands[..self.band_count].iter_mut() {
+				let analysed = band.modulator.iter_mut().fold(modulator, |x, filter| filter.filter(x));
+				let level = band.mod_env.tick(analysed.abs());
+
+				for (chan, wet) in wet.iter_mut().enumerate() {
+					let filtered = band.carrier[chan].iter_mut().fold(carrier[chan], |x, filter| filter.filter(x));
+					let carrier_level = band.carrier_env[chan].tick(filtered.abs()).max(CARRIER_FLOOR);
+					*wet += filtered * level / carrier_level;
+				}
+			}
+
+			// the more of the modulator's level is up top, the more
+			// unvoiced it is and the more of it goes straight through
+			let hiss = self.sibilance_filter.iter_mut().fold(modulator, |x, filter| filter.filter(x));
+			let hiss_level = self.sibilance_env.tick(hiss.abs());
+			let total_level = self.modulator_env.tick(modulator.abs()).max(CARRIER_FLOOR);
+			let unvoiced = ((hiss_level / total_level - 0.1) / 0.3).clamp(0.0, 1.0);
+			let passed = hiss * unvoiced * self.sibilance;
+
+			out_l[i] = in_bufs[0][i] * (1.0 - self.mix) + (wet[0] + passed) * self.mix;
+			out_r[i] = in_bufs[1][i] * (1.0 - self.mix) + (wet[1] + passed) * self.mix;
+		}
+	}
+
+	fn sidechain(&mut self, sc_bufs: [&[f32]; 2]) {
+		for (buf, sc) in self.sc_bufs.iter_mut().zip(sc_bufs.iter()) {
+			buf.clear();
+			buf.extend_from_slice(sc);
+		}
+
+		let peak = sc_bufs.iter().flat_map(|sc| sc.iter()).fold(0f32, |peak, samp| peak.max(samp.abs()));
+		self.sc_silence = if peak > SIDECHAIN_FLOOR { 0 } else { self.sc_silence.saturating_add(sc_bufs[0].len()) };
+	}
+
+	fn update_params(&mut self) {
+		if let Some(params) = self.params.upgrade() {
+			self.internal = params.vocoder_internal.load(Ordering::Relaxed);
+			self.pitch = params.vocoder_pitch.get();
+			self.noise_mix = params.vocoder_noise.get();
+			self.sibilance = params.vocoder_sibilance.get();
+			self.mix = params.vocoder_mix.get();
+
+			let band_count = (params.vocoder_bands.load(Ordering::Relaxed) as usize).clamp(VOCODER_MIN_BANDS, VOCODER_MAX_BANDS);
+			let shift = params.vocoder_shift.get();
+			let sample_rate = params.sample_rate.get();
+			if band_count != self.band_count || shift != self.shift || sample_rate != self.sample_rate {
+				let old_count = self.band_count;
+				self.band_count = band_count;
+				self.shift = shift;
+				self.sample_rate = sample_rate;
+				self.tune_bands(old_count);
+			}
+		}
+	}
+
+	fn get_latency(&self) -> usize {0}
+}
+
+impl VocoderEffect {
+	pub fn new( params: Weak<AndrewParams> ) -> Self {
+		let mut vocoder = VocoderEffect {
+			bands: vec![VocoderBand::new(VOCODER_LOW, VOCODER_LOW, 1.0, 44100.0); VOCODER_MAX_BANDS],
+			sibilance_filter: [BiQuadraticFilter::default(), BiQuadraticFilter::default()],
+			sibilance_env: Envelope::default(),
+			modulator_env: Envelope::default(),
+			sc_bufs: [Vec::with_capacity(SIDECHAIN_RESERVE), Vec::with_capacity(SIDECHAIN_RESERVE)],
+			// taken as unplugged until something turns up
+			sc_silence: usize::MAX,
+			saw: Saw::default(),
+			noise: Noise::default(),
+			band_count: 16,
+			shift: 0.0,
+			internal: false,
+			pitch: 110.0,
+			noise_mix: 0.1,
+			sibilance: 0.5,
+			mix: 1.0,
+			sample_rate: 44100.0,
+			params,
+		};
+		vocoder.tune_bands(0);
+		vocoder
+	}
+
+	// bands spaced evenly in pitch, each as wide as the gap to the next.
+	// the first `running` are retuned where they are, any past them were
+	// left alone while they were unused and start again from silence
+	fn tune_bands( &mut self, running: usize ) {
+		let count = self.band_count;
+		let ratio = (VOCODER_HIGH / VOCODER_LOW).powf(1.0 / (count - 1) as f32);
+		let q = ratio.sqrt() / (ratio - 1.0);
+		let shift = 2f32.powf(self.shift / 12.0);
+		let nyquist = 0.45 * self.sample_rate;
+
+		for (i, band) in self.bands[..count].iter_mut().enumerate() {
+			let freq = (VOCODER_LOW * ratio.powi(i as i32)).min(nyquist);
+			let carrier_freq = (freq * shift).min(nyquist);
+			if i < running {
+				band.retune(freq, carrier_freq, q, self.sample_rate);
+			} else {
+				*band = VocoderBand::new(freq, carrier_freq, q, self.sample_rate);
+			}
+		}
+
+		let sibilance_freq = SIBILANCE_FREQ.min(nyquist);
+		let filter = BiQuadraticFilter::new(HIGHPASS, sibilance_freq, self.sample_rate, std::f32::consts::FRAC_1_SQRT_2, 0.0);
+		self.sibilance_filter = [filter.clone(), filter];
+		for env in [&mut self.sibilance_env, &mut self.modulator_env] {
+			env.set_times(VOCODER_ATTACK, VOCODER_RELEASE, self.sample_rate);
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::Arc;
+
+	fn render( carrier: f32 ) -> Vec<f32> {
+		let params = Arc::new(AndrewParams::default());
+		let mut vocoder = VocoderEffect::new(Arc::downgrade(&params));
+		vocoder.update_params();
+
+		let voice: Vec<f32> = (0..512).map(|i| (i as f32 * 0.05).sin() * 0.5).collect();
+		let sc = vec![carrier; 512];
+		let (mut left, mut right) = (vec![0.0; 512], vec![0.0; 512]);
+		let mut output = vec![];
+		for _ in 0..20 {
+			vocoder.sidechain([&sc, &sc]);
+			vocoder.process_stereo([&voice, &voice], [&mut left, &mut right]);
+			output.extend_from_slice(&left);
+		}
+		output
+	}
+
+	#[test]
+	fn silent_sidechain_falls_back_to_the_internal_carrier() {
+		let peak = |buf: &[f32]| buf.iter().fold(0f32, |peak, samp| peak.max(samp.abs()));
+		// zeros from an unplugged sidechain still get the saw
+		assert!(peak(&render(0.0)) > 0.05);
+		// a dc carrier has nothing in the bands, so only the sibilance gets out
+		assert!(peak(&render(0.5)[5120..]) < 0.01);
+	}
+
+	#[test]
+	fn moving_the_bands_keeps_them_running() {
+		let params = Arc::new(AndrewParams::default());
+		params.vocoder_internal.store(true, Ordering::Relaxed);
+		let mut vocoder = VocoderEffect::new(Arc::downgrade(&params));
+		vocoder.update_params();
+		let bands = vocoder.bands.as_ptr();
+
+		let rms = |buf: &[f32]| (buf.iter().map(|x| x * x).sum::<f32>() / buf.len() as f32).sqrt();
+		let voice: Vec<f32> = (0..4410).map(|i| (i as f32 * 0.05).sin() * 0.5).collect();
+		let (mut left, mut right) = (vec![0.0; 4410], vec![0.0; 4410]);
+		vocoder.process_stereo([&voice, &voice], [&mut left, &mut right]);
+		let before = rms(&left[4410 - 64..]);
+
+		for (shift, count) in [(3.0, 16), (-5.0, 24), (-5.0, 12)] {
+			params.vocoder_shift.set(shift);
+			params.vocoder_bands.store(count, Ordering::Relaxed);
+			vocoder.update_params();
+			assert_eq!(vocoder.bands.as_ptr(), bands);
+
+			vocoder.process_stereo([&voice[..64], &voice[..64]], [&mut left[..64], &mut right[..64]]);
+			assert!(rms(&left[..64]) > 0.3 * before, "{} after moving to {} bands at {}, {} before", rms(&left[..64]), count, shift, before);
+		}
+	}
+}